@@ -0,0 +1,14 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../tests/common/mod.rs"]
+mod common;
+
+fuzz_target!(|data: &[u8]| {
+    common::assert_backends_agree(&common::generate_program(data));
+    let extensions = brainfuck::Extensions {
+        pbrain: true,
+        brainfork: true,
+    };
+    common::assert_extensions_agree(&common::generate_program_with(data, extensions), extensions);
+});
@@ -0,0 +1,246 @@
+//! Bit-level brainfuck dialects.
+//!
+//! Both dialects share the brainfuck loop syntax, so they reuse the same loop table and
+//! diagnostics as the byte-level interpreters.
+use crate::{verify_loops, BrainfuckError, Cell, ExecutionContext, Extensions, Io, StdIo, Tape};
+
+/// A tape of bits that is unbounded in both directions.
+///
+/// Indices wrap around like the ones of [`interpret_with_wrapping`](crate::interpret_with_wrapping),
+/// so the cells left of `0` are the ones with the highest bit set.
+/// Cells only ever hold `0` or `1`.
+#[derive(Default)]
+struct BitTape {
+    right: Vec<Cell>,
+    left: Vec<Cell>,
+}
+
+impl Tape for BitTape {
+    fn cell(&self, index: usize) -> Cell {
+        let (cells, index) = if index.leading_zeros() == 0 {
+            (&self.left, !index)
+        } else {
+            (&self.right, index)
+        };
+        cells.get(index).copied().unwrap_or_default()
+    }
+
+    fn cell_mut(&mut self, index: usize) -> &mut Cell {
+        let (cells, index) = if index.leading_zeros() == 0 {
+            (&mut self.left, !index)
+        } else {
+            (&mut self.right, index)
+        };
+        if index >= cells.len() {
+            cells.resize(index + 1, 0);
+        }
+        &mut cells[index]
+    }
+
+    fn left_of(&self, index: usize) -> Option<usize> {
+        Some(index.wrapping_sub(1))
+    }
+
+    fn right_of(&self, index: usize) -> usize {
+        index.wrapping_add(1)
+    }
+}
+
+/// Takes a [Boolfuck](https://esolangs.org/wiki/Boolfuck) program and calculates the resulting
+/// [String] output.
+///
+/// Cells hold a single bit and the tape is unbounded in both directions. `+` flips the current
+/// bit, `,` reads the next input bit and `;` writes the current bit. Input bytes are consumed and
+/// output bytes are assembled little-endian, that is least significant bit first. A trailing
+/// partial byte is padded with zeros and a bit read past the end of the input is `0`.
+///
+/// Input is read from stdin one line at a time.
+///
+/// # Examples
+///
+/// ```
+/// use brainfuck::*;
+/// let program = translate_to_boolfuck(include_str!("../data/hello_world.bf"));
+/// assert_eq!(interpret_boolfuck(&program).unwrap(), "Hello World!\n");
+/// ```
+pub fn interpret_boolfuck(prog: &str) -> Result<String, BrainfuckError> {
+    let mut io = StdIo::default();
+    run_boolfuck(prog, &mut io)?;
+    io.finish(prog)
+}
+
+/// Runs a Boolfuck program like [`interpret_boolfuck`], handing whole bytes of input and output
+/// to `io`.
+pub fn run_boolfuck<I: Io>(prog: &str, io: &mut I) -> Result<(), BrainfuckError> {
+    let loop_table = verify_loops(prog, Extensions::default())?;
+    let prog_bytes = prog.as_bytes();
+    let mut tape = BitTape::default();
+    let mut ctx = ExecutionContext::default();
+    let (mut input_byte, mut input_bits) = (0u8, 0u8);
+    let (mut output_byte, mut output_bits) = (0u8, 0);
+    while ctx.instruction_ptr < prog_bytes.len() {
+        if io.on_step(&ctx, &tape).is_break() {
+            return Ok(());
+        }
+        let instruction = prog_bytes[ctx.instruction_ptr] as char;
+        let bit = tape.cell_mut(ctx.cell_index);
+        match instruction {
+            '+' => *bit ^= 1,
+            '<' => ctx.cell_index = ctx.cell_index.wrapping_sub(1),
+            '>' => ctx.cell_index = ctx.cell_index.wrapping_add(1),
+            ';' => {
+                output_byte |= *bit << output_bits;
+                output_bits += 1;
+                if output_bits == 8 {
+                    io.on_output(output_byte);
+                    (output_byte, output_bits) = (0, 0);
+                }
+            }
+            ',' => {
+                if input_bits == 0 {
+                    (input_byte, input_bits) = io.on_input().map_or((0, 0), |byte| (byte, 8));
+                }
+                *bit = input_byte & 1;
+                input_byte >>= 1;
+                input_bits = input_bits.saturating_sub(1);
+            }
+            '[' if *bit == 0 => ctx.instruction_ptr = loop_table[&ctx.instruction_ptr],
+            ']' if *bit != 0 => ctx.instruction_ptr = loop_table[&ctx.instruction_ptr],
+            _ => {}
+        }
+        ctx.instruction_ptr += 1;
+        ctx.cycle += 1;
+    }
+    if output_bits > 0 {
+        io.on_output(output_byte);
+    }
+    Ok(())
+}
+
+/// Takes a [Smallfuck](https://esolangs.org/wiki/Smallfuck) program and the initial state of its
+/// tape and returns the final state of the tape.
+///
+/// Cells hold a single bit and `*` flips the current one. The tape is bounded: the program ends
+/// as soon as the cell pointer moves past either end of it.
+///
+/// # Examples
+///
+/// ```
+/// use brainfuck::*;
+/// let tape = interpret_smallfuck("*>*>>*", &[false; 4]).unwrap();
+/// assert_eq!(tape, [true, true, false, true]);
+/// ```
+pub fn interpret_smallfuck(prog: &str, tape: &[bool]) -> Result<Vec<bool>, BrainfuckError> {
+    let loop_table = verify_loops(prog, Extensions::default())?;
+    let prog_bytes = prog.as_bytes();
+    let mut tape = tape.to_vec();
+    let mut ctx = ExecutionContext::default();
+    while ctx.instruction_ptr < prog_bytes.len() && ctx.cell_index < tape.len() {
+        let instruction = prog_bytes[ctx.instruction_ptr] as char;
+        let bit = &mut tape[ctx.cell_index];
+        match instruction {
+            '*' => *bit = !*bit,
+            '<' => match ctx.cell_index.checked_sub(1) {
+                Some(cell_index) => ctx.cell_index = cell_index,
+                None => break,
+            },
+            '>' => ctx.cell_index += 1,
+            '[' if !*bit => ctx.instruction_ptr = loop_table[&ctx.instruction_ptr],
+            ']' if *bit => ctx.instruction_ptr = loop_table[&ctx.instruction_ptr],
+            _ => {}
+        }
+        ctx.instruction_ptr += 1;
+        ctx.cycle += 1;
+    }
+    Ok(tape)
+}
+
+/// Translates a brainfuck program into an equivalent [Boolfuck](https://esolangs.org/wiki/Boolfuck)
+/// program using the substitutions given by the Boolfuck specification.
+///
+/// Every brainfuck cell becomes nine Boolfuck cells: one scratch bit followed by the eight bits of
+/// the value, least significant first. Non-instruction characters are dropped.
+pub fn translate_to_boolfuck<T>(prog: T) -> String
+where
+    T: AsRef<str>,
+{
+    prog.as_ref()
+        .chars()
+        .map(|instruction| match instruction {
+            '+' => ">[>]+<[+<]>>>>>>>>>[+]<<<<<<<<<",
+            '-' => ">>>>>>>>>+<<<<<<<<+[>+]<[<]>>>>>>>>>[+]<<<<<<<<<",
+            '<' => "<<<<<<<<<",
+            '>' => ">>>>>>>>>",
+            ',' => ">,>,>,>,>,>,>,>,<<<<<<<<",
+            '.' => ">;>;>;>;>;>;>;>;<<<<<<<<",
+            '[' => ">>>>>>>>>+<<<<<<<<+[>+]<[<]>>>>>>>>>[+<<<<<<<<[>]+<[+<]",
+            ']' => ">>>>>>>>>+<<<<<<<<+[>+]<[<]>>>>>>>>>]<[+<]",
+            _ => "",
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use miette::Diagnostic;
+
+    #[test]
+    fn boolfuck_output_is_little_endian() {
+        // 'A' is 0b0100_0001
+        assert_eq!("A", interpret_boolfuck("+;>;;;;;+;>;").unwrap());
+        // A partial byte is padded with zeros
+        assert_eq!("\x03", interpret_boolfuck("+;;").unwrap());
+        assert_eq!("", interpret_boolfuck("+>+<[>]").unwrap());
+    }
+
+    #[test]
+    fn boolfuck_translated_hello_world() {
+        for prog in [
+            include_str!("../data/hello_world.bf"),
+            include_str!("../data/hello_world2.bf"),
+            include_str!("../data/hello_world3.bf"),
+        ] {
+            let boolfuck = translate_to_boolfuck(prog);
+            assert_eq!(
+                crate::interpret_with_wrapping(prog).unwrap(),
+                interpret_boolfuck(&boolfuck).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn boolfuck_parse_error() {
+        let err = interpret_boolfuck("+[;").unwrap_err();
+        assert_eq!(
+            err.diagnostic_source().unwrap().to_string(),
+            "Unclosed bracket"
+        );
+    }
+
+    #[test]
+    fn smallfuck_flip() {
+        let tape = interpret_smallfuck(
+            "*>*>*>*>*>*>*>*",
+            &[false, false, true, false, true, true, false, false],
+        );
+        assert_eq!(
+            tape.unwrap(),
+            [true, true, false, true, false, false, true, true]
+        );
+    }
+
+    #[test]
+    fn smallfuck_stops_at_tape_bounds() {
+        assert_eq!(
+            interpret_smallfuck("*[>*]", &[false; 4]).unwrap(),
+            [true; 4]
+        );
+        assert_eq!(
+            interpret_smallfuck("<*", &[false, false]).unwrap(),
+            [false, false]
+        );
+        assert!(interpret_smallfuck("*", &[]).unwrap().is_empty());
+    }
+}
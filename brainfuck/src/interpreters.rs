@@ -1,5 +1,160 @@
-use crate::{get_input, verify_loops, BrainfuckError, Cell, ExecutionContext, ExecutionErrorType};
-use std::collections::HashMap;
+use crate::{verify_loops, BrainfuckError, Cell, ExecutionContext, ExecutionErrorType, Extensions};
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::BuildHasher,
+    io,
+    ops::ControlFlow,
+};
+
+/// Storage for the cells a program operates on.
+pub trait Tape {
+    /// Returns the value of the cell at `index`, which is `0` for cells that were never written.
+    fn cell(&self, index: usize) -> Cell;
+    /// Returns a mutable reference to the cell at `index`, creating it if it does not exist yet.
+    fn cell_mut(&mut self, index: usize) -> &mut Cell;
+    /// Returns the index left of `index` or [`None`] if the tape does not extend that far.
+    fn left_of(&self, index: usize) -> Option<usize>;
+    /// Returns the index right of `index`.
+    fn right_of(&self, index: usize) -> usize;
+}
+
+impl Tape for Vec<Cell> {
+    fn cell(&self, index: usize) -> Cell {
+        self.get(index).copied().unwrap_or_default()
+    }
+
+    fn cell_mut(&mut self, index: usize) -> &mut Cell {
+        if index >= self.len() {
+            self.resize(index + 1, 0);
+        }
+        &mut self[index]
+    }
+
+    fn left_of(&self, index: usize) -> Option<usize> {
+        index.checked_sub(1)
+    }
+
+    fn right_of(&self, index: usize) -> usize {
+        index + 1
+    }
+}
+
+impl<S: BuildHasher> Tape for HashMap<usize, Cell, S> {
+    fn cell(&self, index: usize) -> Cell {
+        self.get(&index).copied().unwrap_or_default()
+    }
+
+    fn cell_mut(&mut self, index: usize) -> &mut Cell {
+        self.entry(index).or_insert(0)
+    }
+
+    fn left_of(&self, index: usize) -> Option<usize> {
+        Some(index.wrapping_sub(1))
+    }
+
+    fn right_of(&self, index: usize) -> usize {
+        index.wrapping_add(1)
+    }
+}
+
+/// Connects a running program to the outside world.
+///
+/// Implement this to embed brainfuck somewhere that is not a terminal, or use [`Callbacks`] for
+/// the common case of only handling input and output.
+pub trait Io {
+    /// Called with the value of the current cell every time the program executes `.`.
+    fn on_output(&mut self, byte: u8);
+
+    /// Called every time the program executes `,`. Returning [`None`] signals the end of the
+    /// input, which sets the current cell to `0`.
+    fn on_input(&mut self) -> Option<u8>;
+
+    /// Called before every instruction with the context of the thread about to execute it.
+    ///
+    /// Returning [`ControlFlow::Break`] stops the program without an error.
+    fn on_step(&mut self, _ctx: &ExecutionContext, _tape: &dyn Tape) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+}
+
+/// An [`Io`] implementation built from a pair of closures.
+///
+/// # Examples
+///
+/// ```
+/// use brainfuck::*;
+/// let mut input = "hi".bytes();
+/// let mut output = Vec::new();
+/// let mut io = Callbacks {
+///     on_output: |byte: u8| output.push(byte.to_ascii_uppercase()),
+///     on_input: || input.next(),
+/// };
+/// run_fast(include_str!("../data/cat.bf"), Extensions::default(), &mut io).unwrap();
+/// assert_eq!(output, b"HI");
+/// ```
+pub struct Callbacks<O, I> {
+    pub on_output: O,
+    pub on_input: I,
+}
+
+impl<O, I> Io for Callbacks<O, I>
+where
+    O: FnMut(u8),
+    I: FnMut() -> Option<u8>,
+{
+    fn on_output(&mut self, byte: u8) {
+        (self.on_output)(byte);
+    }
+
+    fn on_input(&mut self) -> Option<u8> {
+        (self.on_input)()
+    }
+}
+
+/// Reads input from stdin one line at a time and collects the output into a [String].
+#[derive(Default)]
+pub(crate) struct StdIo {
+    output: String,
+    input: VecDeque<u8>,
+    error: Option<io::Error>,
+    ctx: ExecutionContext,
+}
+
+impl StdIo {
+    /// Returns the collected output, or the error stdin failed with.
+    pub(crate) fn finish(self, program: &str) -> Result<String, BrainfuckError> {
+        match self.error {
+            Some(e) => Err(self.ctx.to_error(program, e.into())),
+            None => Ok(self.output),
+        }
+    }
+}
+
+impl Io for StdIo {
+    fn on_output(&mut self, byte: u8) {
+        self.output.push(byte as char);
+    }
+
+    fn on_input(&mut self) -> Option<u8> {
+        if self.input.is_empty() {
+            let mut line = String::new();
+            if let Err(e) = io::stdin().read_line(&mut line) {
+                self.error = Some(e);
+                return None;
+            }
+            self.input.extend(line.chars().map(|c| c as u8));
+        }
+        self.input.pop_front()
+    }
+
+    fn on_step(&mut self, ctx: &ExecutionContext, _tape: &dyn Tape) -> ControlFlow<()> {
+        if self.error.is_some() {
+            return ControlFlow::Break(());
+        }
+        self.ctx = *ctx;
+        ControlFlow::Continue(())
+    }
+}
 
 /// Takes a brainfuck program and calculates the resulting [String] output.
 /// Accepts wrapping indices.
@@ -19,42 +174,27 @@ use std::collections::HashMap;
 /// assert!(interpret_fast(program).is_err())
 /// ```
 pub fn interpret_with_wrapping(prog: &str) -> Result<String, BrainfuckError> {
-    let loop_table = verify_loops(prog)?;
-    let prog_bytes = prog.as_bytes();
-    let mut user_input: Vec<char> = Vec::new();
-    let mut tape: HashMap<usize, Cell> = HashMap::from_iter([(0, 0)]);
-    let mut ctx = ExecutionContext::default();
-    let mut cell_index = 0;
-    let mut output = String::new();
-    while ctx.instruction_ptr < prog_bytes.len() {
-        let instruction = prog_bytes[ctx.instruction_ptr] as char;
-        tape.entry(cell_index).or_insert(0);
-        let cell_val = tape
-            .get_mut(&cell_index)
-            .expect("Failed to get a mutable reference to the current cell value");
-        match instruction {
-            '+' => *cell_val = cell_val.wrapping_add(1),
-            '-' => *cell_val = cell_val.wrapping_sub(1),
-            '<' => cell_index = cell_index.wrapping_sub(1),
-            '>' => {
-                cell_index = cell_index.wrapping_add(1);
-                tape.entry(cell_index).or_insert(0);
-            }
-            '.' => output.push(*cell_val as char),
-            ',' => {
-                if user_input.is_empty() {
-                    user_input = get_input(prog, &ctx)?;
-                }
-                *cell_val = user_input.remove(0) as Cell;
-            }
-            '[' if *cell_val == 0 => ctx.instruction_ptr = loop_table[&ctx.instruction_ptr],
-            ']' if *cell_val != 0 => ctx.instruction_ptr = loop_table[&ctx.instruction_ptr],
-            _ => {}
-        }
-        ctx.instruction_ptr += 1;
-        ctx.cycle += 1;
-    }
-    Ok(output)
+    interpret_with_wrapping_with(prog, Extensions::default())
+}
+
+/// Same as [`interpret_with_wrapping`] but with the given language [`Extensions`] enabled.
+pub fn interpret_with_wrapping_with(
+    prog: &str,
+    extensions: Extensions,
+) -> Result<String, BrainfuckError> {
+    let mut io = StdIo::default();
+    run_with_wrapping(prog, extensions, &mut io)?;
+    io.finish(prog)
+}
+
+/// Runs a brainfuck program with wrapping indices like [`interpret_with_wrapping`], handing all
+/// input and output to `io`.
+pub fn run_with_wrapping<I: Io>(
+    prog: &str,
+    extensions: Extensions,
+    io: &mut I,
+) -> Result<(), BrainfuckError> {
+    execute(prog, &mut HashMap::new(), extensions, io)
 }
 
 /// Takes a brainfuck program and calculates the resulting [String] output.
@@ -62,54 +202,197 @@ pub fn interpret_with_wrapping(prog: &str) -> Result<String, BrainfuckError> {
 ///
 /// Translated from: <https://github.com/Camto/Shorterpreters/blob/master/Brainfuck/brainfuck.py>
 pub fn interpret_fast(prog: &str) -> Result<String, BrainfuckError> {
-    let loop_table = verify_loops(prog)?;
-    let prog_bytes = prog.as_bytes();
-    let mut user_input: Vec<char> = Vec::new();
-    let mut tape: Vec<Cell> = Vec::from([0]);
-    let mut ctx = ExecutionContext::default();
-    let mut cell_index = 0;
-    let mut output = String::new();
-    while ctx.instruction_ptr < prog_bytes.len() {
-        let instruction = prog_bytes[ctx.instruction_ptr] as char;
-        let cell_val = tape
-            .get_mut(cell_index)
-            .expect("Failed to get a mutable reference to the current cell value");
+    interpret_fast_with(prog, Extensions::default())
+}
+
+/// Same as [`interpret_fast`] but with the given language [`Extensions`] enabled.
+///
+/// Threads forked through the Brainfork extension are stepped in round-robin order, one
+/// instruction each, with children running after the threads that existed before them.
+///
+/// # Examples
+///
+/// ```
+/// use brainfuck::*;
+/// // Defines procedure 0 which prints an `A`, then calls it twice
+/// let program = "(++++++++[>++++++++<-]>+.[-]<)::";
+/// let pbrain = Extensions {
+///     pbrain: true,
+///     ..Extensions::default()
+/// };
+/// assert_eq!(interpret_fast_with(program, pbrain).unwrap(), "AA");
+/// assert_eq!(interpret_fast(program).unwrap(), "A");
+/// ```
+pub fn interpret_fast_with(prog: &str, extensions: Extensions) -> Result<String, BrainfuckError> {
+    let mut io = StdIo::default();
+    run_fast(prog, extensions, &mut io)?;
+    io.finish(prog)
+}
+
+/// Runs a brainfuck program without wrapping indices like [`interpret_fast`], handing all input
+/// and output to `io`.
+///
+/// # Examples
+///
+/// ```
+/// use brainfuck::*;
+/// use std::ops::ControlFlow;
+///
+/// /// Plays a sound for every `.` and gives up on programs that run for too long
+/// struct Game {
+///     beeps: usize,
+/// }
+///
+/// impl Io for Game {
+///     fn on_output(&mut self, _byte: u8) {
+///         self.beeps += 1;
+///     }
+///
+///     fn on_input(&mut self) -> Option<u8> {
+///         None
+///     }
+///
+///     fn on_step(&mut self, ctx: &ExecutionContext, _tape: &dyn Tape) -> ControlFlow<()> {
+///         if ctx.cycle() < 1000 {
+///             ControlFlow::Continue(())
+///         } else {
+///             ControlFlow::Break(())
+///         }
+///     }
+/// }
+///
+/// let mut game = Game { beeps: 0 };
+/// run_fast("+[.]", Extensions::default(), &mut game).unwrap();
+/// assert_eq!(game.beeps, 499);
+/// ```
+pub fn run_fast<I: Io>(
+    prog: &str,
+    extensions: Extensions,
+    io: &mut I,
+) -> Result<(), BrainfuckError> {
+    execute(prog, &mut Vec::new(), extensions, io)
+}
+
+fn execute<T: Tape, I: Io>(
+    prog: &str,
+    tape: &mut T,
+    extensions: Extensions,
+    io: &mut I,
+) -> Result<(), BrainfuckError> {
+    let mut machine = Machine {
+        prog,
+        loop_table: verify_loops(prog, extensions)?,
+        tape,
+        extensions,
+        io,
+        procedures: HashMap::new(),
+        thread_count: 1,
+    };
+    let mut threads = vec![Thread::default()];
+    loop {
+        threads.retain(|thread| thread.ctx.instruction_ptr < prog.len());
+        if threads.is_empty() {
+            break;
+        }
+        let mut forked = Vec::new();
+        for thread in &mut threads {
+            if machine.io.on_step(&thread.ctx, machine.tape).is_break() {
+                return Ok(());
+            }
+            forked.extend(machine.step(thread)?);
+        }
+        threads.extend(forked);
+    }
+    Ok(())
+}
+
+/// A thread of execution along with the return addresses of the procedures it is currently in.
+#[derive(Default)]
+struct Thread {
+    ctx: ExecutionContext,
+    call_stack: Vec<usize>,
+}
+
+/// State shared by every thread of a running program.
+struct Machine<'a, T: Tape, I: Io> {
+    prog: &'a str,
+    loop_table: HashMap<usize, usize>,
+    tape: &'a mut T,
+    extensions: Extensions,
+    io: &'a mut I,
+    procedures: HashMap<Cell, usize>,
+    thread_count: usize,
+}
+
+impl<T: Tape, I: Io> Machine<'_, T, I> {
+    /// Executes the next instruction of `thread`, returning the child thread if it forked.
+    fn step(&mut self, thread: &mut Thread) -> Result<Option<Thread>, BrainfuckError> {
+        let ctx = &mut thread.ctx;
+        let instruction = self.prog.as_bytes()[ctx.instruction_ptr] as char;
+        let cell_val = self.tape.cell_mut(ctx.cell_index);
+        let mut child = None;
         match instruction {
             '+' => *cell_val = cell_val.wrapping_add(1),
             '-' => *cell_val = cell_val.wrapping_sub(1),
             '<' => {
-                cell_index = cell_index.checked_sub(1).map_or_else(
-                    || Err(ctx.to_error(prog, ExecutionErrorType::CellIndexUnderflow)),
+                ctx.cell_index = self.tape.left_of(ctx.cell_index).map_or_else(
+                    || Err(ctx.to_error(self.prog, ExecutionErrorType::CellIndexUnderflow)),
                     Ok,
                 )?;
             }
-            '>' => {
-                cell_index += 1;
-                if cell_index == tape.len() {
-                    tape.push(0);
-                }
+            '>' => ctx.cell_index = self.tape.right_of(ctx.cell_index),
+            '.' => self.io.on_output(*cell_val),
+            ',' => *cell_val = self.io.on_input().unwrap_or(0),
+            '[' if *cell_val == 0 => ctx.instruction_ptr = self.loop_table[&ctx.instruction_ptr],
+            ']' if *cell_val != 0 => ctx.instruction_ptr = self.loop_table[&ctx.instruction_ptr],
+            '(' if self.extensions.pbrain => {
+                self.procedures.insert(*cell_val, ctx.instruction_ptr);
+                ctx.instruction_ptr = self.loop_table[&ctx.instruction_ptr];
             }
-            '.' => output.push(*cell_val as char),
-            ',' => {
-                if user_input.is_empty() {
-                    user_input = get_input(prog, &ctx)?;
+            ')' if self.extensions.pbrain => {
+                if let Some(return_ptr) = thread.call_stack.pop() {
+                    ctx.instruction_ptr = return_ptr;
                 }
-                *cell_val = user_input.remove(0) as Cell;
             }
-            '[' if *cell_val == 0 => ctx.instruction_ptr = loop_table[&ctx.instruction_ptr],
-            ']' if *cell_val != 0 => ctx.instruction_ptr = loop_table[&ctx.instruction_ptr],
+            ':' if self.extensions.pbrain => {
+                let procedure = self.procedures.get(cell_val).copied().map_or_else(
+                    || {
+                        Err(ctx
+                            .to_error(self.prog, ExecutionErrorType::UndefinedProcedure(*cell_val)))
+                    },
+                    Ok,
+                )?;
+                thread.call_stack.push(ctx.instruction_ptr);
+                ctx.instruction_ptr = procedure;
+            }
+            'Y' if self.extensions.brainfork => {
+                *cell_val = 0;
+                let cell_index = self.tape.right_of(ctx.cell_index);
+                *self.tape.cell_mut(cell_index) = 1;
+                child = Some(Thread {
+                    ctx: ExecutionContext {
+                        instruction_ptr: ctx.instruction_ptr + 1,
+                        cycle: 0,
+                        cell_index,
+                        thread: self.thread_count,
+                    },
+                    call_stack: thread.call_stack.clone(),
+                });
+                self.thread_count += 1;
+            }
             _ => {}
         }
         ctx.instruction_ptr += 1;
         ctx.cycle += 1;
+        Ok(child)
     }
-    Ok(output)
 }
 
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
+    use miette::Diagnostic;
 
     #[test]
     fn hello_world_test() {
@@ -132,7 +415,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
+    #[should_panic(expected = "called `Result::unwrap()` on an `Err` value")]
     fn hello_world_3_fast_panics() {
         let prog = include_str!("../data/hello_world3.bf");
         interpret_fast(prog).unwrap();
@@ -150,4 +433,115 @@ mod tests {
         assert_eq!("Hello World!\n", interpret_fast(prog).unwrap());
         assert_eq!("Hello World!\n", interpret_with_wrapping(prog).unwrap());
     }
+
+    #[test]
+    fn pbrain_test() {
+        let pbrain = Extensions {
+            pbrain: true,
+            ..Extensions::default()
+        };
+        let prog = include_str!("../data/pbrain_hello_world.bf");
+        assert_eq!("Hello World!\n", interpret_fast_with(prog, pbrain).unwrap());
+        assert_eq!(
+            "Hello World!\n",
+            interpret_with_wrapping_with(prog, pbrain).unwrap()
+        );
+    }
+
+    #[test]
+    fn pbrain_undefined_procedure() {
+        let pbrain = Extensions {
+            pbrain: true,
+            ..Extensions::default()
+        };
+        let err = interpret_fast_with("(-)+:", pbrain).unwrap_err();
+        assert_eq!(
+            err.diagnostic_source().unwrap().to_string(),
+            "Called undefined procedure 1"
+        );
+        assert!(interpret_fast("(-)+:").is_ok());
+    }
+
+    #[test]
+    fn brainfork_round_robin() {
+        let brainfork = Extensions {
+            brainfork: true,
+            ..Extensions::default()
+        };
+        assert_eq!("\x01\x02", interpret_fast_with("Y+.", brainfork).unwrap());
+        assert_eq!(
+            "\0\x01\x01\x02",
+            interpret_fast_with("Y.+.", brainfork).unwrap()
+        );
+        assert_eq!(
+            "\0\0\0\x01",
+            interpret_with_wrapping_with("YY.", brainfork).unwrap()
+        );
+        assert_eq!("\0", interpret_fast("Y.").unwrap());
+    }
+
+    #[test]
+    fn brainfork_hello_world() {
+        let brainfork = Extensions {
+            brainfork: true,
+            ..Extensions::default()
+        };
+        let prog = include_str!("../data/brainfork_hello_world.bf");
+        assert_eq!(
+            "Hello World!\n",
+            interpret_fast_with(prog, brainfork).unwrap()
+        );
+        assert_eq!(
+            "Hello World!\n",
+            interpret_with_wrapping_with(prog, brainfork).unwrap()
+        );
+    }
+
+    #[test]
+    fn callbacks_receive_io() {
+        let mut input = "Hello".bytes();
+        let mut output = Vec::new();
+        let mut io = Callbacks {
+            on_output: |byte| output.push(byte),
+            on_input: || input.next(),
+        };
+        run_with_wrapping(",[.,]", Extensions::default(), &mut io).unwrap();
+        assert_eq!(output, b"Hello");
+    }
+
+    #[test]
+    fn on_step_can_stop_and_inspect() {
+        /// Stops the program as soon as any cell reaches 3
+        #[derive(Default)]
+        struct Watch {
+            output: Vec<u8>,
+            stopped_at: Option<usize>,
+        }
+
+        impl Io for Watch {
+            fn on_output(&mut self, byte: u8) {
+                self.output.push(byte);
+            }
+
+            fn on_input(&mut self) -> Option<u8> {
+                None
+            }
+
+            fn on_step(&mut self, ctx: &ExecutionContext, tape: &dyn Tape) -> ControlFlow<()> {
+                if tape.cell(ctx.cell_index()) == 3 {
+                    self.stopped_at = Some(ctx.instruction_ptr());
+                    return ControlFlow::Break(());
+                }
+                ControlFlow::Continue(())
+            }
+        }
+
+        let mut watch = Watch::default();
+        run_fast("+.+.+.+.", Extensions::default(), &mut watch).unwrap();
+        assert_eq!(watch.output, [1, 2]);
+        assert_eq!(watch.stopped_at, Some(5));
+        // Errors still surface while hooks are installed
+        let mut watch = Watch::default();
+        run_fast("<", Extensions::default(), &mut watch).unwrap_err();
+    }
 }
@@ -1,6 +1,8 @@
 #![warn(clippy::pedantic, clippy::nursery)]
 #![allow(dead_code, clippy::missing_errors_doc)]
+pub mod dialects;
 pub mod interpreters;
+pub use dialects::*;
 pub use interpreters::*;
 use miette::{Diagnostic, Result, SourceSpan};
 use std::{collections::HashMap, io};
@@ -25,7 +27,7 @@ pub enum BrainfuckError {
     ExecutionError {
         #[source_code]
         src: String,
-        #[label("Occurred at instruction: {}, cycle: {}",ctx.instruction_ptr, ctx.cycle)]
+        #[label("Occurred at instruction: {}, cycle: {}, thread: {}",ctx.instruction_ptr, ctx.cycle, ctx.thread)]
         location: SourceSpan,
         ctx: ExecutionContext,
         #[diagnostic_source]
@@ -39,6 +41,8 @@ pub enum ExecutionErrorType {
     CellIndexUnderflow,
     #[error("Could not retrieve user input")]
     InputError(#[from] io::Error),
+    #[error("Called undefined procedure {0}")]
+    UndefinedProcedure(Cell),
 }
 
 #[derive(Error, Diagnostic, Debug)]
@@ -47,6 +51,27 @@ pub enum LoopErrorType {
     UnclosedBracket,
     #[error("Unexpected closing bracket")]
     UnexpectedClosingBracket,
+    #[error("Unclosed parenthesis")]
+    UnclosedParenthesis,
+    #[error("Unexpected closing parenthesis")]
+    UnexpectedClosingParenthesis,
+}
+
+/// Opt-in language extensions understood by the interpreters.
+///
+/// Every extension is disabled by default, in which case its instructions are treated as comments
+/// like any other non-brainfuck character.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Extensions {
+    /// [pbrain](https://esolangs.org/wiki/Pbrain) procedures: `(` ... `)` defines a procedure keyed
+    /// by the current cell value and `:` calls the procedure keyed by the current cell value.
+    pub pbrain: bool,
+    /// [Brainfork](https://esolangs.org/wiki/Brainfork) threads: `Y` forks the current thread.
+    ///
+    /// The forked cell is zeroed in the parent while the child starts one cell to the right with
+    /// that cell set to 1. All threads share the same tape and are stepped one instruction at a
+    /// time in round-robin order, so execution stays deterministic.
+    pub brainfork: bool,
 }
 
 enum Operation {
@@ -63,13 +88,40 @@ enum Operation {
 
 struct BrainfuckProgram {}
 
+/// The state of a single thread of execution. Programs that do not fork only ever have one.
 #[derive(Debug, Clone, Copy, Default)]
 pub struct ExecutionContext {
     instruction_ptr: usize,
     cycle: usize,
+    cell_index: usize,
+    thread: usize,
 }
 
 impl ExecutionContext {
+    /// Index of the instruction about to be executed.
+    #[must_use]
+    pub const fn instruction_ptr(&self) -> usize {
+        self.instruction_ptr
+    }
+
+    /// Number of instructions this thread has executed so far.
+    #[must_use]
+    pub const fn cycle(&self) -> usize {
+        self.cycle
+    }
+
+    /// Index of the cell the data pointer of this thread points to.
+    #[must_use]
+    pub const fn cell_index(&self) -> usize {
+        self.cell_index
+    }
+
+    /// Identifier of this thread, `0` being the thread the program started with.
+    #[must_use]
+    pub const fn thread(&self) -> usize {
+        self.thread
+    }
+
     fn to_error(self, program: &str, e: ExecutionErrorType) -> BrainfuckError {
         BrainfuckError::ExecutionError {
             src: program.to_string(),
@@ -98,14 +150,6 @@ fn parse(prog: &str) -> Result<BrainfuckProgram, ()> {
     unimplemented!()
 }
 
-fn get_input(program: &str, ctx: &ExecutionContext) -> Result<Vec<char>, BrainfuckError> {
-    let mut line = String::new();
-    std::io::stdin()
-        .read_line(&mut line)
-        .map_err(|e| ctx.to_error(program, e.into()))?;
-    Ok(line.chars().collect())
-}
-
 pub(crate) fn print_tape(ip: usize, tape: &[Cell]) {
     println!(
         "{ip}: [{}]",
@@ -116,24 +160,34 @@ pub(crate) fn print_tape(ip: usize, tape: &[Cell]) {
     );
 }
 
-fn verify_loops(prog: &str) -> Result<HashMap<usize, usize>, BrainfuckError> {
+fn verify_loops(
+    prog: &str,
+    extensions: Extensions,
+) -> Result<HashMap<usize, usize>, BrainfuckError> {
+    let parse_error = |ip: usize, err_type| BrainfuckError::ParseError {
+        location: (ip, 0).into(),
+        src: prog.into(),
+        err_type,
+        char_index: ip,
+    };
     let mut stack = Vec::with_capacity(100);
     let mut loops = HashMap::new();
     for (ip, instruction) in prog.char_indices() {
         match instruction {
             '[' => stack.push((ip, instruction)),
-            ']' => {
-                let (loop_start, _instruction) = stack.pop().map_or_else(
-                    || {
-                        Err(BrainfuckError::ParseError {
-                            location: (ip, 0).into(),
-                            src: prog.into(),
-                            err_type: LoopErrorType::UnexpectedClosingBracket,
-                            char_index: ip,
-                        })
-                    },
-                    Ok,
-                )?;
+            '(' if extensions.pbrain => stack.push((ip, instruction)),
+            ']' | ')' => {
+                let (opening, unexpected) = if instruction == ']' {
+                    ('[', LoopErrorType::UnexpectedClosingBracket)
+                } else if extensions.pbrain {
+                    ('(', LoopErrorType::UnexpectedClosingParenthesis)
+                } else {
+                    continue;
+                };
+                let loop_start = match stack.pop() {
+                    Some((loop_start, instruction)) if instruction == opening => loop_start,
+                    _ => return Err(parse_error(ip, unexpected)),
+                };
                 loops.insert(loop_start, ip);
                 loops.insert(ip, loop_start);
             }
@@ -141,15 +195,8 @@ fn verify_loops(prog: &str) -> Result<HashMap<usize, usize>, BrainfuckError> {
         }
     }
     match stack.pop() {
-        Some((index, instruction)) => Err(match instruction {
-            '[' => BrainfuckError::ParseError {
-                location: (index, 0).into(),
-                src: prog.into(),
-                err_type: LoopErrorType::UnclosedBracket,
-                char_index: index,
-            },
-            _ => panic!(),
-        }),
+        Some((index, '(')) => Err(parse_error(index, LoopErrorType::UnclosedParenthesis)),
+        Some((index, _)) => Err(parse_error(index, LoopErrorType::UnclosedBracket)),
         None => Ok(loops),
     }
 }
@@ -161,9 +208,13 @@ mod tests {
 
     #[test]
     fn loop_search_test() {
-        let loops = verify_loops(include_str!("../data/cat.bf")).unwrap();
+        let loops = verify_loops(include_str!("../data/cat.bf"), Extensions::default()).unwrap();
         assert_eq!(loops, HashMap::from_iter([(1, 4), (4, 1)]));
-        let loops = verify_loops(include_str!("../data/hello_world.bf")).unwrap();
+        let loops = verify_loops(
+            include_str!("../data/hello_world.bf"),
+            Extensions::default(),
+        )
+        .unwrap();
         assert_eq!(
             loops,
             HashMap::from_iter([
@@ -186,20 +237,47 @@ mod tests {
     #[test]
     fn parse_test() {
         let prog = include_str!("../data/cat.bf");
-        assert!(verify_loops(prog).is_ok());
+        assert!(verify_loops(prog, Extensions::default()).is_ok());
         let prog = include_str!("../data/fails_to_parse_close.bf");
-        let result = verify_loops(prog);
+        let result = verify_loops(prog, Extensions::default());
         let err = result.unwrap_err();
         assert_eq!(
             err.diagnostic_source().unwrap().to_string(),
             "Unclosed bracket"
         );
         let prog = include_str!("../data/fails_to_parse_open.bf");
-        let result = verify_loops(prog);
+        let result = verify_loops(prog, Extensions::default());
         let err = result.unwrap_err();
         assert_eq!(
             err.diagnostic_source().unwrap().to_string(),
             "Unexpected closing bracket"
         );
     }
+
+    #[test]
+    fn pbrain_parse_test() {
+        let pbrain = Extensions {
+            pbrain: true,
+            ..Extensions::default()
+        };
+        let loops = verify_loops("([)]", Extensions::default()).unwrap();
+        assert_eq!(loops, HashMap::from_iter([(1, 3), (3, 1)]));
+        let loops = verify_loops("(+[-])", pbrain).unwrap();
+        assert_eq!(loops, HashMap::from_iter([(0, 5), (5, 0), (2, 4), (4, 2)]));
+        let err = verify_loops("(+", pbrain).unwrap_err();
+        assert_eq!(
+            err.diagnostic_source().unwrap().to_string(),
+            "Unclosed parenthesis"
+        );
+        let err = verify_loops("+)", pbrain).unwrap_err();
+        assert_eq!(
+            err.diagnostic_source().unwrap().to_string(),
+            "Unexpected closing parenthesis"
+        );
+        let err = verify_loops("([)]", pbrain).unwrap_err();
+        assert_eq!(
+            err.diagnostic_source().unwrap().to_string(),
+            "Unexpected closing parenthesis"
+        );
+    }
 }
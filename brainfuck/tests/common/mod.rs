@@ -0,0 +1,235 @@
+//! Differential testing helpers shared by the proptest suite and the fuzz target.
+use brainfuck::{
+    interpret_boolfuck, interpret_fast, interpret_fast_with, interpret_with_wrapping,
+    interpret_with_wrapping_with, run_fast, run_with_wrapping, translate_to_boolfuck,
+    BrainfuckError, ExecutionContext, ExecutionErrorType, Extensions, Io, Tape,
+};
+use miette::Diagnostic;
+use std::ops::ControlFlow;
+
+/// Loops nested deeper than this are dropped to keep the runtime of generated programs bounded.
+const MAX_LOOP_DEPTH: usize = 2;
+
+/// Forks beyond this many are dropped to keep the number of threads bounded.
+const MAX_FORKS: usize = 3;
+
+/// Steps after which programs using extensions are stopped, since they are not sure to halt.
+const MAX_STEPS: usize = 10_000;
+
+/// A loop or procedure that is still open, along with the offset of the data pointer relative to
+/// the start of its body.
+struct Block {
+    offset: usize,
+    procedure: bool,
+}
+
+/// Turns arbitrary bytes into a bracket-balanced brainfuck program that is guaranteed to halt.
+///
+/// Every loop is rewritten into the counted form `[->` body `<]`: its body starts one cell to the
+/// right of the loop counter, never moves left of that cell and returns to it before looping.
+/// The counter is therefore only ever decremented by the loop itself, which bounds the loop to
+/// at most 255 iterations. Outside of loops the data pointer is free to underflow.
+pub fn generate_program(bytes: &[u8]) -> String {
+    generate_program_with(bytes, Extensions::default())
+}
+
+/// Like [`generate_program`], but also emits the instructions of the enabled `extensions`.
+///
+/// Procedures are only defined at the top level and only called once one was defined. Their
+/// bodies follow the rules of loop bodies and never call a procedure, so a call cannot recurse or
+/// disturb the loop it is made from. Forks are
+/// only emitted at the top level and at most [`MAX_FORKS`] times. Threads share the tape and can
+/// still change each other's loop counters, so [`assert_extensions_agree`] stops these programs
+/// after [`MAX_STEPS`] instead of relying on them to halt.
+pub fn generate_program_with(bytes: &[u8], extensions: Extensions) -> String {
+    let modulus = if extensions == Extensions::default() {
+        7
+    } else {
+        10
+    };
+    let mut prog = String::new();
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut skipped_loops = 0;
+    let mut forks = 0;
+    let mut defined = false;
+    for byte in bytes {
+        match byte % modulus {
+            0 => prog.push('+'),
+            1 => prog.push('-'),
+            2 => prog.push('.'),
+            3 => {
+                prog.push('>');
+                if let Some(block) = blocks.last_mut() {
+                    block.offset += 1;
+                }
+            }
+            4 => match blocks.last_mut() {
+                Some(Block { offset: 0, .. }) => {}
+                Some(block) => {
+                    block.offset -= 1;
+                    prog.push('<');
+                }
+                None => prog.push('<'),
+            },
+            5 if blocks.len() < MAX_LOOP_DEPTH => {
+                prog.push_str("[->");
+                blocks.push(Block {
+                    offset: 0,
+                    procedure: false,
+                });
+            }
+            5 => skipped_loops += 1,
+            6 if skipped_loops > 0 => skipped_loops -= 1,
+            6 => {
+                if let Some(block) = blocks.pop() {
+                    close_block(&mut prog, &block);
+                }
+            }
+            7 if extensions.pbrain && blocks.is_empty() => {
+                prog.push('(');
+                defined = true;
+                blocks.push(Block {
+                    offset: 0,
+                    procedure: true,
+                });
+            }
+            8 if defined && !blocks.iter().any(|block| block.procedure) => {
+                prog.push(':');
+            }
+            9 if extensions.brainfork && blocks.is_empty() && forks < MAX_FORKS => {
+                prog.push('Y');
+                forks += 1;
+            }
+            _ => {}
+        }
+    }
+    while let Some(block) = blocks.pop() {
+        close_block(&mut prog, &block);
+    }
+    prog
+}
+
+/// Returns the data pointer to the start of the body of `block` and closes it. Loops go one cell
+/// further to their counter.
+fn close_block(prog: &mut String, block: &Block) {
+    if block.procedure {
+        prog.push_str(&"<".repeat(block.offset));
+        prog.push(')');
+    } else {
+        prog.push_str(&"<".repeat(block.offset + 1));
+        prog.push(']');
+    }
+}
+
+/// Reduces a result to something comparable across backends: the output or the kind of error.
+fn outcome(result: Result<String, BrainfuckError>) -> Result<String, String> {
+    result.map_err(|err| {
+        err.diagnostic_source()
+            .map_or_else(|| err.to_string(), |source| source.to_string())
+    })
+}
+
+/// Runs `prog` through every backend and asserts that they agree with each other.
+///
+/// The wrapping backends are only compared with [`interpret_fast`] when it did not underflow,
+/// since that is exactly where their semantics are meant to differ.
+pub fn assert_backends_agree(prog: &str) {
+    let all_extensions = Extensions {
+        pbrain: true,
+        brainfork: true,
+    };
+    let fast = outcome(interpret_fast(prog));
+    assert_eq!(
+        fast,
+        outcome(interpret_fast_with(prog, all_extensions)),
+        "interpret_fast with extensions diverged on {prog:?}"
+    );
+    let wrapping = outcome(interpret_with_wrapping(prog));
+    assert!(
+        wrapping.is_ok(),
+        "interpret_with_wrapping failed on {prog:?}"
+    );
+    if fast.is_ok() {
+        assert_eq!(
+            fast, wrapping,
+            "interpret_with_wrapping diverged on {prog:?}"
+        );
+    }
+    assert_eq!(
+        wrapping,
+        outcome(interpret_with_wrapping_with(prog, all_extensions)),
+        "interpret_with_wrapping with extensions diverged on {prog:?}"
+    );
+    assert_eq!(
+        wrapping,
+        outcome(interpret_boolfuck(&translate_to_boolfuck(prog))),
+        "interpret_boolfuck diverged on {prog:?}"
+    );
+}
+
+/// Collects the output of a program and stops it after [`MAX_STEPS`].
+#[derive(Default)]
+struct Budget {
+    output: Vec<u8>,
+    steps: usize,
+}
+
+impl Io for Budget {
+    fn on_output(&mut self, byte: u8) {
+        self.output.push(byte);
+    }
+
+    fn on_input(&mut self) -> Option<u8> {
+        None
+    }
+
+    fn on_step(&mut self, _ctx: &ExecutionContext, _tape: &dyn Tape) -> ControlFlow<()> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+type Run = fn(&str, Extensions, &mut Budget) -> Result<(), BrainfuckError>;
+
+/// Runs `prog` through `run` within [`MAX_STEPS`], returning the output or the error along with
+/// whether it is an underflow.
+fn run_budgeted(prog: &str, extensions: Extensions, run: Run) -> (Result<Vec<u8>, String>, bool) {
+    let mut budget = Budget::default();
+    let result = run(prog, extensions, &mut budget);
+    let underflow = matches!(
+        result,
+        Err(BrainfuckError::ExecutionError {
+            err_type: ExecutionErrorType::CellIndexUnderflow,
+            ..
+        })
+    );
+    let output = result.map(|()| budget.output);
+    (
+        output.map_err(|err| {
+            err.diagnostic_source()
+                .map_or_else(|| err.to_string(), |source| source.to_string())
+        }),
+        underflow,
+    )
+}
+
+/// Runs `prog` with `extensions` through both byte-level backends, each stopped after
+/// [`MAX_STEPS`], and asserts that they agree unless [`run_fast`] underflowed.
+pub fn assert_extensions_agree(prog: &str, extensions: Extensions) {
+    let (fast, fast_underflowed) = run_budgeted(prog, extensions, run_fast);
+    let (wrapping, wrapping_underflowed) = run_budgeted(prog, extensions, run_with_wrapping);
+    assert!(
+        !wrapping_underflowed,
+        "run_with_wrapping underflowed on {prog:?}"
+    );
+    if !fast_underflowed {
+        assert_eq!(
+            fast, wrapping,
+            "run_with_wrapping diverged with {extensions:?} on {prog:?}"
+        );
+    }
+}
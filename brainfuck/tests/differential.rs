@@ -0,0 +1,58 @@
+mod common;
+
+use brainfuck::Extensions;
+use common::{
+    assert_backends_agree, assert_extensions_agree, generate_program, generate_program_with,
+};
+use proptest::prelude::*;
+
+proptest! {
+    #[test]
+    fn backends_agree(bytes in prop::collection::vec(any::<u8>(), 0..64)) {
+        assert_backends_agree(&generate_program(&bytes));
+    }
+
+    #[test]
+    fn extensions_agree(
+        bytes in prop::collection::vec(any::<u8>(), 0..64),
+        pbrain in any::<bool>(),
+        brainfork in any::<bool>(),
+    ) {
+        let extensions = Extensions { pbrain, brainfork };
+        assert_extensions_agree(&generate_program_with(&bytes, extensions), extensions);
+    }
+}
+
+#[test]
+fn generated_programs_are_balanced() {
+    let prog = generate_program(&[5, 5, 5, 4, 3, 3, 0, 6, 4, 1, 6]);
+    assert_eq!(prog, "[->[->>>+<-<<]<]");
+    assert_eq!(generate_program(&[4, 6, 5, 2]), "<[->.<]");
+}
+
+#[test]
+fn generated_programs_use_extensions() {
+    let all = Extensions {
+        pbrain: true,
+        brainfork: true,
+    };
+    let prog = generate_program_with(&[7, 0, 5, 8, 3, 9, 6, 3, 8, 6, 8, 9, 7], all);
+    assert_eq!(prog, "(+[->><<]><):Y()");
+    let pbrain = Extensions {
+        pbrain: true,
+        brainfork: false,
+    };
+    assert_eq!(generate_program_with(&[9, 8, 7, 6, 9, 8], pbrain), "():");
+}
+
+#[test]
+fn data_programs_agree() {
+    for prog in [
+        include_str!("../data/hello_world.bf"),
+        include_str!("../data/hello_world2.bf"),
+        include_str!("../data/hello_world3.bf"),
+        include_str!("../data/hello_world4.bf"),
+    ] {
+        assert_backends_agree(prog);
+    }
+}
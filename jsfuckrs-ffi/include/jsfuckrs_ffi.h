@@ -0,0 +1,252 @@
+#ifndef JSFUCKRS_FFI_H
+#define JSFUCKRS_FFI_H
+
+/* Generated by cbindgen, run `UPDATE_HEADER=1 cargo test -p jsfuckrs-ffi --test header` after changing the bindings */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * How [`bf_run`] ended, with a code for every kind of [`BrainfuckError`]
+ */
+typedef enum BfStatus {
+  /**
+   * The program ran to its end
+   */
+  BF_STATUS_OK,
+  /**
+   * The program is a null pointer
+   */
+  BF_STATUS_NULL_ARGUMENT,
+  /**
+   * The program is not valid UTF-8
+   */
+  BF_STATUS_INVALID_UTF8,
+  /**
+   * A `[` has no matching `]`
+   */
+  BF_STATUS_UNCLOSED_BRACKET,
+  /**
+   * A `]` has no matching `[`
+   */
+  BF_STATUS_UNEXPECTED_CLOSING_BRACKET,
+  /**
+   * A `(` has no matching `)`
+   */
+  BF_STATUS_UNCLOSED_PARENTHESIS,
+  /**
+   * A `)` has no matching `(`
+   */
+  BF_STATUS_UNEXPECTED_CLOSING_PARENTHESIS,
+  /**
+   * The data pointer moved left of the first cell
+   */
+  BF_STATUS_CELL_INDEX_UNDERFLOW,
+  /**
+   * The input could not be read
+   */
+  BF_STATUS_INPUT_ERROR,
+  /**
+   * `:` called a procedure that was never defined
+   */
+  BF_STATUS_UNDEFINED_PROCEDURE,
+  /**
+   * The interpreter panicked, which is a bug, and its output was lost
+   */
+  BF_STATUS_PANIC,
+} BfStatus;
+
+/**
+ * Why [`jsfuck_compile`] returned null, or [`JsfuckStatus::Ok`] when it did not
+ */
+typedef enum JsfuckStatus {
+  /**
+   * The code was compiled
+   */
+  JSFUCK_STATUS_OK,
+  /**
+   * The code is a null pointer
+   */
+  JSFUCK_STATUS_NULL_ARGUMENT,
+  /**
+   * The code is not valid UTF-8
+   */
+  JSFUCK_STATUS_INVALID_UTF8,
+  /**
+   * A field of [`JsfuckOptions`] holds no variant of its enum
+   */
+  JSFUCK_STATUS_INVALID_OPTION,
+  /**
+   * The aemkei backend was given a target other than [`JsfuckTarget::Generic`] or asked to hoist
+   */
+  JSFUCK_STATUS_UNSUPPORTED_OPTION,
+  /**
+   * The encoder panicked, which is a bug, and its output was lost
+   */
+  JSFUCK_STATUS_PANIC,
+} JsfuckStatus;
+
+/**
+ * The encoder [`jsfuck_compile`] uses, like [`jsfuckrs::Backend`]
+ */
+typedef enum JsfuckBackend {
+  JSFUCK_BACKEND_LBP,
+  JSFUCK_BACKEND_LBP_STRICT,
+  JSFUCK_BACKEND_AEMKEI,
+} JsfuckBackend;
+
+/**
+ * What the compiled code is run by, the [`Wrapper`]s that take no arguments
+ */
+typedef enum JsfuckWrapper {
+  JSFUCK_WRAPPER_FUNCTION,
+  JSFUCK_WRAPPER_EXPRESSION,
+  JSFUCK_WRAPPER_EVAL,
+  JSFUCK_WRAPPER_SET_TIMEOUT,
+  JSFUCK_WRAPPER_STRING,
+} JsfuckWrapper;
+
+/**
+ * The engines the output of the lbp backends has to run on, see [`Target`]
+ */
+typedef enum JsfuckTarget {
+  JSFUCK_TARGET_GENERIC,
+  JSFUCK_TARGET_ES5,
+  JSFUCK_TARGET_BROWSER,
+  JSFUCK_TARGET_NODE,
+} JsfuckTarget;
+
+/**
+ * The outcome of [`bf_run`], released by [`bf_result_free`]
+ */
+typedef struct BfResult {
+  /**
+   * Whether the program succeeded, or why it did not
+   */
+  enum BfStatus status;
+  /**
+   * The bytes printed before the program ended, followed by a NUL `output_len` does not count
+   *
+   * The output itself may contain NUL bytes.
+   */
+  uint8_t *output;
+  /**
+   * The number of bytes printed
+   */
+  size_t output_len;
+  /**
+   * A description of the error, null when the status is [`BfStatus::Ok`]
+   */
+  char *message;
+  /**
+   * The byte offset of the instruction in the program the error is about
+   */
+  size_t position;
+} BfResult;
+
+/**
+ * Which interpreter [`bf_run`] uses and the extensions it understands, all off by default
+ */
+typedef struct BfOptions {
+  /**
+   * Let the data pointer move left of the first cell, see [`run_with_wrapping`]
+   */
+  bool wrapping;
+  /**
+   * See [`Extensions::pbrain`]
+   */
+  bool pbrain;
+  /**
+   * See [`Extensions::brainfork`]
+   */
+  bool brainfork;
+} BfOptions;
+
+/**
+ * How [`jsfuck_compile`] encodes, the first variant of every field being the default
+ *
+ * The enum fields are plain integers, since C may store any value in them, and are checked
+ * before use.
+ */
+typedef struct JsfuckOptions {
+  /**
+   * A [`JsfuckBackend`]
+   */
+  uint32_t backend;
+  /**
+   * A [`JsfuckWrapper`]
+   */
+  uint32_t wrapper;
+  /**
+   * A [`JsfuckTarget`], only supported by the lbp backends
+   */
+  uint32_t target;
+  /**
+   * Share the expensive derivations between characters, only supported by the lbp backends
+   */
+  bool hoist;
+} JsfuckOptions;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Runs a NUL-terminated brainfuck program, reading from `input` and collecting what it prints
+ *
+ * `input` may be null for an empty input, which like reading past its end gives the program no
+ * input.
+ * `options` may be null for the defaults of [`BfOptions`].
+ * A panic of the interpreter is reported as [`BfStatus::Panic`] rather than unwinding into C.
+ *
+ * # Safety
+ * `program` and `input`, unless null, must point to NUL-terminated strings and `options`,
+ * unless null, to a `BfOptions`. The result must be released with [`bf_result_free`].
+ */
+struct BfResult bf_run(const char *program, const char *input, const struct BfOptions *options);
+
+/**
+ * Releases the output and message of a [`BfResult`], leaving them null
+ *
+ * Releasing the same result twice, or a null pointer, does nothing.
+ *
+ * # Safety
+ * `result` must be null or point to a result returned by [`bf_run`], whose `output_len` was not
+ * changed.
+ */
+void bf_result_free(struct BfResult *result);
+
+/**
+ * Encodes NUL-terminated javascript into a NUL-terminated string of jsf*ck
+ *
+ * `options` may be null for the defaults of [`JsfuckOptions`].
+ *
+ * Returns null when the code could not be compiled, and stores why in `status` unless it is null,
+ * see [`JsfuckStatus`]. A panic of the encoder, which is a bug, is reported rather than unwinding
+ * into C.
+ *
+ * # Safety
+ * `code` must be null or point to a NUL-terminated string, `options` must be null or point to a
+ * `JsfuckOptions` and `status` must be null or point to a writable `JsfuckStatus`. The result must
+ * be released with [`jsfuck_free`].
+ */
+char *jsfuck_compile(const char *code,
+                     const struct JsfuckOptions *options,
+                     enum JsfuckStatus *status);
+
+/**
+ * Releases a string returned by [`jsfuck_compile`], doing nothing for a null pointer
+ *
+ * # Safety
+ * `encoded` must be null or a string returned by [`jsfuck_compile`] that was not released yet.
+ */
+void jsfuck_free(char *encoded);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* JSFUCKRS_FFI_H */
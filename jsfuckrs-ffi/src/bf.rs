@@ -0,0 +1,214 @@
+//! `bf_run` and what it takes and returns
+use std::{
+    ffi::{c_char, CStr, CString},
+    ptr, slice,
+};
+
+use crate::catch_panic;
+use brainfuck::{
+    run_fast, run_with_wrapping, BrainfuckError, Callbacks, ExecutionErrorType, Extensions,
+    LoopErrorType,
+};
+
+/// How [`bf_run`] ended, with a code for every kind of [`BrainfuckError`]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BfStatus {
+    /// The program ran to its end
+    Ok,
+    /// The program is a null pointer
+    NullArgument,
+    /// The program is not valid UTF-8
+    InvalidUtf8,
+    /// A `[` has no matching `]`
+    UnclosedBracket,
+    /// A `]` has no matching `[`
+    UnexpectedClosingBracket,
+    /// A `(` has no matching `)`
+    UnclosedParenthesis,
+    /// A `)` has no matching `(`
+    UnexpectedClosingParenthesis,
+    /// The data pointer moved left of the first cell
+    CellIndexUnderflow,
+    /// The input could not be read
+    InputError,
+    /// `:` called a procedure that was never defined
+    UndefinedProcedure,
+    /// The interpreter panicked, which is a bug, and its output was lost
+    Panic,
+}
+
+/// Which interpreter [`bf_run`] uses and the extensions it understands, all off by default
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BfOptions {
+    /// Let the data pointer move left of the first cell, see [`run_with_wrapping`]
+    pub wrapping: bool,
+    /// See [`Extensions::pbrain`]
+    pub pbrain: bool,
+    /// See [`Extensions::brainfork`]
+    pub brainfork: bool,
+}
+
+/// The outcome of [`bf_run`], released by [`bf_result_free`]
+#[repr(C)]
+#[derive(Debug)]
+pub struct BfResult {
+    /// Whether the program succeeded, or why it did not
+    pub status: BfStatus,
+    /// The bytes printed before the program ended, followed by a NUL `output_len` does not count
+    ///
+    /// The output itself may contain NUL bytes.
+    pub output: *mut u8,
+    /// The number of bytes printed
+    pub output_len: usize,
+    /// A description of the error, null when the status is [`BfStatus::Ok`]
+    pub message: *mut c_char,
+    /// The byte offset of the instruction in the program the error is about
+    pub position: usize,
+}
+
+impl BfResult {
+    fn new(
+        status: BfStatus,
+        mut output: Vec<u8>,
+        message: Option<String>,
+        position: usize,
+    ) -> Self {
+        let output_len = output.len();
+        output.push(0);
+        Self {
+            status,
+            output: Box::into_raw(output.into_boxed_slice()).cast(),
+            output_len,
+            message: message
+                .and_then(|message| CString::new(message).ok())
+                .map_or(ptr::null_mut(), CString::into_raw),
+            position,
+        }
+    }
+
+    fn failed(status: BfStatus, message: &str) -> Self {
+        Self::new(status, Vec::new(), Some(message.to_owned()), 0)
+    }
+}
+
+/// The status and position of an error, along with its message and that of its cause
+fn describe(error: &BrainfuckError) -> (BfStatus, usize, String) {
+    let (status, position, cause) = match error {
+        BrainfuckError::ParseError {
+            err_type,
+            char_index,
+            ..
+        } => {
+            let status = match err_type {
+                LoopErrorType::UnclosedBracket => BfStatus::UnclosedBracket,
+                LoopErrorType::UnexpectedClosingBracket => BfStatus::UnexpectedClosingBracket,
+                LoopErrorType::UnclosedParenthesis => BfStatus::UnclosedParenthesis,
+                LoopErrorType::UnexpectedClosingParenthesis => {
+                    BfStatus::UnexpectedClosingParenthesis
+                }
+            };
+            (status, *char_index, err_type.to_string())
+        }
+        BrainfuckError::ExecutionError { ctx, err_type, .. } => {
+            let status = match err_type {
+                ExecutionErrorType::CellIndexUnderflow => BfStatus::CellIndexUnderflow,
+                ExecutionErrorType::InputError(_) => BfStatus::InputError,
+                ExecutionErrorType::UndefinedProcedure(_) => BfStatus::UndefinedProcedure,
+            };
+            (status, ctx.instruction_ptr(), err_type.to_string())
+        }
+    };
+    (status, position, format!("{error}: {cause}"))
+}
+
+/// Runs a NUL-terminated brainfuck program, reading from `input` and collecting what it prints
+///
+/// `input` may be null for an empty input, which like reading past its end gives the program no
+/// input.
+/// `options` may be null for the defaults of [`BfOptions`].
+/// A panic of the interpreter is reported as [`BfStatus::Panic`] rather than unwinding into C.
+///
+/// # Safety
+/// `program` and `input`, unless null, must point to NUL-terminated strings and `options`,
+/// unless null, to a `BfOptions`. The result must be released with [`bf_result_free`].
+#[no_mangle]
+pub unsafe extern "C" fn bf_run(
+    program: *const c_char,
+    input: *const c_char,
+    options: *const BfOptions,
+) -> BfResult {
+    catch_panic(
+        || run(program, input, options),
+        |message| {
+            BfResult::failed(
+                BfStatus::Panic,
+                &format!("The interpreter panicked: {message}"),
+            )
+        },
+    )
+}
+
+/// The body of [`bf_run`], which has the same safety requirements
+unsafe fn run(program: *const c_char, input: *const c_char, options: *const BfOptions) -> BfResult {
+    if program.is_null() {
+        return BfResult::failed(BfStatus::NullArgument, "The program is a null pointer");
+    }
+    let Ok(program) = CStr::from_ptr(program).to_str() else {
+        return BfResult::failed(BfStatus::InvalidUtf8, "The program is not valid UTF-8");
+    };
+    let input = if input.is_null() {
+        &[]
+    } else {
+        CStr::from_ptr(input).to_bytes()
+    };
+    let options = options.as_ref().copied().unwrap_or_default();
+    let extensions = Extensions {
+        pbrain: options.pbrain,
+        brainfork: options.brainfork,
+    };
+
+    let mut output = Vec::new();
+    let mut input = input.iter().copied();
+    let mut io = Callbacks {
+        on_output: |byte| output.push(byte),
+        on_input: || input.next(),
+    };
+    let result = if options.wrapping {
+        run_with_wrapping(program, extensions, &mut io)
+    } else {
+        run_fast(program, extensions, &mut io)
+    };
+    match result {
+        Ok(()) => BfResult::new(BfStatus::Ok, output, None, 0),
+        Err(error) => {
+            let (status, position, message) = describe(&error);
+            BfResult::new(status, output, Some(message), position)
+        }
+    }
+}
+
+/// Releases the output and message of a [`BfResult`], leaving them null
+///
+/// Releasing the same result twice, or a null pointer, does nothing.
+///
+/// # Safety
+/// `result` must be null or point to a result returned by [`bf_run`], whose `output_len` was not
+/// changed.
+#[no_mangle]
+pub unsafe extern "C" fn bf_result_free(result: *mut BfResult) {
+    let Some(result) = result.as_mut() else {
+        return;
+    };
+    if !result.output.is_null() {
+        let output = slice::from_raw_parts_mut(result.output, result.output_len + 1);
+        drop(Box::from_raw(output));
+        result.output = ptr::null_mut();
+        result.output_len = 0;
+    }
+    if !result.message.is_null() {
+        drop(CString::from_raw(result.message));
+        result.message = ptr::null_mut();
+    }
+}
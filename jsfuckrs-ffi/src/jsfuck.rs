@@ -0,0 +1,205 @@
+//! `jsfuck_compile` and its options
+use std::{
+    ffi::{c_char, CStr, CString},
+    ptr,
+};
+
+use crate::catch_panic;
+use jsfuckrs::{
+    aemkei::Aemkei,
+    lbp::{Lbp, Target},
+    CompileOptions, JsFuckEncoder, Wrapper,
+};
+
+/// Why [`jsfuck_compile`] returned null, or [`JsfuckStatus::Ok`] when it did not
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsfuckStatus {
+    /// The code was compiled
+    Ok,
+    /// The code is a null pointer
+    NullArgument,
+    /// The code is not valid UTF-8
+    InvalidUtf8,
+    /// A field of [`JsfuckOptions`] holds no variant of its enum
+    InvalidOption,
+    /// The aemkei backend was given a target other than [`JsfuckTarget::Generic`] or asked to hoist
+    UnsupportedOption,
+    /// The encoder panicked, which is a bug, and its output was lost
+    Panic,
+}
+
+/// Implements `TryFrom<u32>` for a fieldless enum whose variants are numbered from 0
+macro_rules! try_from_u32 {
+    ($name:ident { $($variant:ident),+ $(,)? }) => {
+        impl TryFrom<u32> for $name {
+            type Error = JsfuckStatus;
+
+            fn try_from(value: u32) -> Result<Self, JsfuckStatus> {
+                [$(Self::$variant),+]
+                    .into_iter()
+                    .find(|&variant| variant as u32 == value)
+                    .ok_or(JsfuckStatus::InvalidOption)
+            }
+        }
+    };
+}
+
+/// The encoder [`jsfuck_compile`] uses, like [`jsfuckrs::Backend`]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsfuckBackend {
+    #[default]
+    Lbp,
+    LbpStrict,
+    Aemkei,
+}
+
+try_from_u32!(JsfuckBackend {
+    Lbp,
+    LbpStrict,
+    Aemkei
+});
+
+/// What the compiled code is run by, the [`Wrapper`]s that take no arguments
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsfuckWrapper {
+    #[default]
+    Function,
+    Expression,
+    Eval,
+    SetTimeout,
+    String,
+}
+
+try_from_u32!(JsfuckWrapper {
+    Function,
+    Expression,
+    Eval,
+    SetTimeout,
+    String,
+});
+
+/// The engines the output of the lbp backends has to run on, see [`Target`]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsfuckTarget {
+    #[default]
+    Generic,
+    Es5,
+    Browser,
+    Node,
+}
+
+try_from_u32!(JsfuckTarget {
+    Generic,
+    Es5,
+    Browser,
+    Node,
+});
+
+/// How [`jsfuck_compile`] encodes, the first variant of every field being the default
+///
+/// The enum fields are plain integers, since C may store any value in them, and are checked
+/// before use.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JsfuckOptions {
+    /// A [`JsfuckBackend`]
+    pub backend: u32,
+    /// A [`JsfuckWrapper`]
+    pub wrapper: u32,
+    /// A [`JsfuckTarget`], only supported by the lbp backends
+    pub target: u32,
+    /// Share the expensive derivations between characters, only supported by the lbp backends
+    pub hoist: bool,
+}
+
+impl JsfuckOptions {
+    fn encoder(self) -> Result<Box<dyn JsFuckEncoder>, JsfuckStatus> {
+        let target = match JsfuckTarget::try_from(self.target)? {
+            JsfuckTarget::Generic => Target::Generic,
+            JsfuckTarget::Es5 => Target::Es5,
+            JsfuckTarget::Browser => Target::Browser,
+            JsfuckTarget::Node => Target::Node,
+        };
+        match JsfuckBackend::try_from(self.backend)? {
+            backend @ (JsfuckBackend::Lbp | JsfuckBackend::LbpStrict) => Ok(Box::new(Lbp {
+                strict: backend == JsfuckBackend::LbpStrict,
+                hoist: self.hoist,
+                target,
+            })),
+            JsfuckBackend::Aemkei if self.hoist || target != Target::Generic => {
+                Err(JsfuckStatus::UnsupportedOption)
+            }
+            JsfuckBackend::Aemkei => Ok(Box::new(Aemkei)),
+        }
+    }
+
+    fn wrapper(self) -> Result<Wrapper, JsfuckStatus> {
+        Ok(match JsfuckWrapper::try_from(self.wrapper)? {
+            JsfuckWrapper::Function => Wrapper::Function,
+            JsfuckWrapper::Expression => Wrapper::Expression,
+            JsfuckWrapper::Eval => Wrapper::Eval,
+            JsfuckWrapper::SetTimeout => Wrapper::SetTimeout,
+            JsfuckWrapper::String => Wrapper::String,
+        })
+    }
+}
+
+/// Encodes NUL-terminated javascript into a NUL-terminated string of jsf*ck
+///
+/// `options` may be null for the defaults of [`JsfuckOptions`].
+///
+/// Returns null when the code could not be compiled, and stores why in `status` unless it is null,
+/// see [`JsfuckStatus`]. A panic of the encoder, which is a bug, is reported rather than unwinding
+/// into C.
+///
+/// # Safety
+/// `code` must be null or point to a NUL-terminated string, `options` must be null or point to a
+/// `JsfuckOptions` and `status` must be null or point to a writable `JsfuckStatus`. The result must
+/// be released with [`jsfuck_free`].
+#[no_mangle]
+pub unsafe extern "C" fn jsfuck_compile(
+    code: *const c_char,
+    options: *const JsfuckOptions,
+    status: *mut JsfuckStatus,
+) -> *mut c_char {
+    let result = catch_panic(|| compile(code, options), |_| Err(JsfuckStatus::Panic));
+    if let Some(status) = status.as_mut() {
+        *status = result
+            .as_ref()
+            .map_or_else(|&status| status, |_| JsfuckStatus::Ok);
+    }
+    result.map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// The body of [`jsfuck_compile`], which has the same safety requirements
+unsafe fn compile(
+    code: *const c_char,
+    options: *const JsfuckOptions,
+) -> Result<CString, JsfuckStatus> {
+    if code.is_null() {
+        return Err(JsfuckStatus::NullArgument);
+    }
+    let code = CStr::from_ptr(code)
+        .to_str()
+        .map_err(|_| JsfuckStatus::InvalidUtf8)?;
+    let options = options.as_ref().copied().unwrap_or_default();
+    let encoder = options.encoder()?;
+    let wrapper = options.wrapper()?;
+    let output = encoder.compile_with(code, &CompileOptions { wrapper });
+    Ok(CString::new(output).expect("the encoders escape NUL"))
+}
+
+/// Releases a string returned by [`jsfuck_compile`], doing nothing for a null pointer
+///
+/// # Safety
+/// `encoded` must be null or a string returned by [`jsfuck_compile`] that was not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn jsfuck_free(encoded: *mut c_char) {
+    if !encoded.is_null() {
+        drop(CString::from_raw(encoded));
+    }
+}
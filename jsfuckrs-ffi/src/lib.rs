@@ -0,0 +1,40 @@
+//! C bindings of the [`brainfuck`] interpreters and the [`jsfuckrs`] encoders
+//!
+//! The crate builds a `cdylib` whose declarations are in `include/jsfuckrs_ffi.h`. Everything the
+//! library returns belongs to the caller, who releases it with the matching free function.
+//! Panics are caught before they reach C, where they would abort the process.
+#![warn(clippy::pedantic, clippy::nursery)]
+use std::panic::{self, AssertUnwindSafe};
+
+mod bf;
+mod jsfuck;
+
+pub use bf::*;
+pub use jsfuck::*;
+
+/// Runs `f`, or returns what `on_panic` makes of the message of its panic
+fn catch_panic<T>(f: impl FnOnce() -> T, on_panic: impl FnOnce(&str) -> T) -> T {
+    // Nothing `f` borrows is used again once it panicked
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("Box<dyn Any>");
+        on_panic(message)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catches_panics() {
+        assert_eq!(catch_panic(|| 1, |_| 2), 1);
+        let message = |message: &str| message.to_owned();
+        assert_eq!(catch_panic(|| panic!("static"), message), "static");
+        assert_eq!(catch_panic(|| panic!("{}", 1 + 1), message), "2");
+        assert_eq!(catch_panic(|| panic::panic_any(1), message), "Box<dyn Any>");
+    }
+}
@@ -0,0 +1,245 @@
+//! Loads the `cdylib` and calls it through the C ABI, the way C programs use it
+use std::{
+    env,
+    ffi::{c_char, CStr},
+    ptr, slice,
+    sync::OnceLock,
+};
+
+use jsfuckrs::{decode, is_jsfuck, Backend, JsFuckEncoder};
+use jsfuckrs_ffi::{
+    BfOptions, BfResult, BfStatus, JsfuckBackend, JsfuckOptions, JsfuckStatus, JsfuckTarget,
+    JsfuckWrapper,
+};
+use libloading::{Library, Symbol};
+
+type BfRun = unsafe extern "C" fn(*const c_char, *const c_char, *const BfOptions) -> BfResult;
+type BfResultFree = unsafe extern "C" fn(*mut BfResult);
+type JsfuckCompile =
+    unsafe extern "C" fn(*const c_char, *const JsfuckOptions, *mut JsfuckStatus) -> *mut c_char;
+type JsfuckFree = unsafe extern "C" fn(*mut c_char);
+
+const HELLO_WORLD: &CStr = c"++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+fn library() -> &'static Library {
+    static LIBRARY: OnceLock<Library> = OnceLock::new();
+    LIBRARY.get_or_init(|| {
+        // Integration tests are built into the same directory as the library
+        let exe = env::current_exe().unwrap();
+        let path = exe.with_file_name(libloading::library_filename("jsfuckrs_ffi"));
+        unsafe { Library::new(path).unwrap() }
+    })
+}
+
+fn symbol<T>(name: &str) -> Symbol<'static, T> {
+    unsafe { library().get(name.as_bytes()).unwrap() }
+}
+
+/// Runs a program, returning its status, output, message and position once the result is freed
+fn run(
+    program: &CStr,
+    input: Option<&CStr>,
+    options: Option<BfOptions>,
+) -> (BfStatus, Vec<u8>, Option<String>, usize) {
+    let (bf_run, bf_result_free) = (
+        symbol::<BfRun>("bf_run"),
+        symbol::<BfResultFree>("bf_result_free"),
+    );
+    let input = input.map_or(ptr::null(), CStr::as_ptr);
+    let options = options.as_ref().map_or(ptr::null(), ptr::from_ref);
+    unsafe {
+        let mut result = bf_run(program.as_ptr(), input, options);
+        let output = slice::from_raw_parts(result.output, result.output_len + 1);
+        assert_eq!(output.last(), Some(&0));
+        let output = output[..result.output_len].to_vec();
+        let message = (!result.message.is_null())
+            .then(|| CStr::from_ptr(result.message).to_str().unwrap().to_owned());
+        let (status, position) = (result.status, result.position);
+        bf_result_free(&mut result);
+        assert!(result.output.is_null() && result.message.is_null());
+        // Freeing twice does nothing
+        bf_result_free(&mut result);
+        (status, output, message, position)
+    }
+}
+
+fn compile(code: Option<&CStr>, options: Option<JsfuckOptions>) -> Result<String, JsfuckStatus> {
+    let jsfuck_compile = symbol::<JsfuckCompile>("jsfuck_compile");
+    let jsfuck_free = symbol::<JsfuckFree>("jsfuck_free");
+    let options = options.as_ref().map_or(ptr::null(), ptr::from_ref);
+    let mut status = JsfuckStatus::Panic;
+    unsafe {
+        let encoded = jsfuck_compile(code.map_or(ptr::null(), CStr::as_ptr), options, &mut status);
+        assert_eq!(encoded.is_null(), status != JsfuckStatus::Ok);
+        let result = (!encoded.is_null())
+            .then(|| CStr::from_ptr(encoded).to_str().unwrap().to_owned())
+            .ok_or(status);
+        jsfuck_free(encoded);
+        result
+    }
+}
+
+#[test]
+fn runs_programs() {
+    let (status, output, message, _) = run(HELLO_WORLD, None, None);
+    assert_eq!(status, BfStatus::Ok);
+    assert_eq!(output, b"Hello World!\n");
+    assert_eq!(message, None);
+
+    let (status, output, ..) = run(c",[.,]", Some(c"cat"), None);
+    assert_eq!(
+        (status, output.as_slice()),
+        (BfStatus::Ok, b"cat".as_slice())
+    );
+
+    // The output is not cut at the first NUL
+    let (status, output, ..) = run(c".+.", None, None);
+    assert_eq!(
+        (status, output.as_slice()),
+        (BfStatus::Ok, [0, 1].as_slice())
+    );
+}
+
+#[test]
+fn reports_every_error() {
+    let pbrain = Some(BfOptions {
+        pbrain: true,
+        ..BfOptions::default()
+    });
+    let cases = [
+        (c"+[", None, BfStatus::UnclosedBracket, 1),
+        (c"+]", None, BfStatus::UnexpectedClosingBracket, 1),
+        (c"+(", pbrain, BfStatus::UnclosedParenthesis, 1),
+        (c"+)", pbrain, BfStatus::UnexpectedClosingParenthesis, 1),
+        (c"+:", pbrain, BfStatus::UndefinedProcedure, 1),
+        (c".<", None, BfStatus::CellIndexUnderflow, 1),
+    ];
+    for (program, options, expected, expected_position) in cases {
+        let (status, _, message, position) = run(program, None, options);
+        assert_eq!(status, expected, "{program:?}");
+        assert_eq!(position, expected_position, "{program:?}");
+        assert!(message.unwrap().contains(": "), "{program:?}");
+    }
+
+    let (_, output, message, _) = run(c"+.<", None, None);
+    assert_eq!(output, [1], "what was printed before the error is kept");
+    assert_eq!(
+        message.unwrap(),
+        "An error occurred while executing the program: Unsigned integer underflow ocurred in cell index"
+    );
+}
+
+#[test]
+fn applies_options() {
+    let wrapping = BfOptions {
+        wrapping: true,
+        ..BfOptions::default()
+    };
+    let (status, ..) = run(c"<", None, Some(wrapping));
+    assert_eq!(status, BfStatus::Ok);
+
+    // Without the extensions their instructions are comments
+    assert_eq!(run(c"+:Y", None, None).0, BfStatus::Ok);
+    let brainfork = BfOptions {
+        brainfork: true,
+        ..BfOptions::default()
+    };
+    let (status, output, ..) = run(
+        c"Y+++++++++++++++++++++++++++++++++++++++++++++++.",
+        None,
+        Some(brainfork),
+    );
+    assert_eq!(status, BfStatus::Ok);
+    assert_eq!(output.len(), 2);
+}
+
+#[test]
+fn rejects_invalid_programs() {
+    let bf_run = symbol::<BfRun>("bf_run");
+    let bf_result_free = symbol::<BfResultFree>("bf_result_free");
+    unsafe {
+        let mut result = bf_run(ptr::null(), ptr::null(), ptr::null());
+        assert_eq!(result.status, BfStatus::NullArgument);
+        assert_eq!(result.output_len, 0);
+        bf_result_free(&mut result);
+        bf_result_free(ptr::null_mut());
+    }
+    let (status, _, message, _) = run(c"+\xff", None, None);
+    assert_eq!(status, BfStatus::InvalidUtf8);
+    assert!(message.is_some());
+}
+
+#[test]
+fn compiles_javascript() {
+    let encoded = compile(Some(c"alert(1)"), None).unwrap();
+    assert_eq!(encoded, Backend::Lbp.compile("alert(1)"));
+    assert_eq!(decode(&encoded).unwrap(), "alert(1)");
+
+    for backend in [JsfuckBackend::LbpStrict, JsfuckBackend::Aemkei] {
+        let options = JsfuckOptions {
+            backend: backend as u32,
+            ..JsfuckOptions::default()
+        };
+        let encoded = compile(Some(c"alert('é')"), Some(options)).unwrap();
+        assert!(is_jsfuck(&encoded), "{backend:?}");
+        assert_eq!(decode(&encoded).unwrap(), "alert('é')", "{backend:?}");
+    }
+
+    let options = JsfuckOptions {
+        wrapper: JsfuckWrapper::Expression as u32,
+        target: JsfuckTarget::Es5 as u32,
+        hoist: true,
+        ..JsfuckOptions::default()
+    };
+    let encoded = compile(Some(c"1+1"), Some(options)).unwrap();
+    assert_eq!(jsfuckrs::eval::eval(&encoded).unwrap().to_string(), "2");
+}
+
+#[test]
+fn refuses_to_compile() {
+    assert_eq!(compile(None, None), Err(JsfuckStatus::NullArgument));
+    assert_eq!(compile(Some(c"\xff"), None), Err(JsfuckStatus::InvalidUtf8));
+    let options = JsfuckOptions {
+        backend: JsfuckBackend::Aemkei as u32,
+        hoist: true,
+        ..JsfuckOptions::default()
+    };
+    assert_eq!(
+        compile(Some(c"alert(1)"), Some(options)),
+        Err(JsfuckStatus::UnsupportedOption)
+    );
+    let options = JsfuckOptions {
+        backend: JsfuckBackend::Aemkei as u32,
+        target: JsfuckTarget::Node as u32,
+        ..JsfuckOptions::default()
+    };
+    assert_eq!(
+        compile(Some(c"alert(1)"), Some(options)),
+        Err(JsfuckStatus::UnsupportedOption)
+    );
+    for options in [
+        JsfuckOptions {
+            backend: 3,
+            ..JsfuckOptions::default()
+        },
+        JsfuckOptions {
+            wrapper: 5,
+            ..JsfuckOptions::default()
+        },
+        JsfuckOptions {
+            target: u32::MAX,
+            ..JsfuckOptions::default()
+        },
+    ] {
+        assert_eq!(
+            compile(Some(c"alert(1)"), Some(options)),
+            Err(JsfuckStatus::InvalidOption),
+            "{options:?}"
+        );
+    }
+    let jsfuck_compile = symbol::<JsfuckCompile>("jsfuck_compile");
+    let encoded = unsafe { jsfuck_compile(c"1".as_ptr(), ptr::null(), ptr::null_mut()) };
+    assert!(!encoded.is_null());
+    unsafe { symbol::<JsfuckFree>("jsfuck_free")(encoded) };
+    unsafe { symbol::<JsfuckFree>("jsfuck_free")(ptr::null_mut()) };
+}
@@ -0,0 +1,26 @@
+//! Keeps the committed C header in sync with the bindings
+use std::{env, fs, path::Path};
+
+#[test]
+fn header_is_up_to_date() {
+    let crate_dir = env!("CARGO_MANIFEST_DIR");
+    let path = Path::new(crate_dir).join("include/jsfuckrs_ffi.h");
+    let config = cbindgen::Config::from_root_or_default(crate_dir);
+    let bindings = cbindgen::Builder::new()
+        .with_crate(crate_dir)
+        .with_config(config)
+        .generate()
+        .unwrap();
+    let mut header = Vec::new();
+    bindings.write(&mut header);
+    let header = String::from_utf8(header).unwrap();
+    if env::var_os("UPDATE_HEADER").is_some() {
+        fs::write(&path, &header).unwrap();
+    }
+    let committed = fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        header == committed,
+        "{} is outdated, regenerate it with `UPDATE_HEADER=1 cargo test -p jsfuckrs-ffi --test header`",
+        path.display()
+    );
+}
@@ -0,0 +1,21 @@
+//! Encodes javascript into jsf*ck while compiling, see [`jsfuck!`]
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, LitStr};
+
+/// Encodes a string literal of javascript into a `&'static str` of jsf*ck
+///
+/// The output is the same as [`jsfuckrs::lbp::compile`] at runtime, but costs nothing there.
+///
+/// # Examples
+/// ```
+/// use jsfuckrs_macros::jsfuck;
+/// const ALERT: &str = jsfuck!("alert(1)");
+/// assert_eq!(ALERT, jsfuckrs::lbp::compile("alert(1)"));
+/// ```
+#[proc_macro]
+pub fn jsfuck(input: TokenStream) -> TokenStream {
+    let code = parse_macro_input!(input as LitStr);
+    let encoded = LitStr::new(&jsfuckrs::lbp::compile(code.value()), code.span());
+    quote!(#encoded).into()
+}
@@ -0,0 +1,22 @@
+use jsfuckrs::{decode, lbp};
+use jsfuckrs_macros::jsfuck;
+
+#[test]
+fn matches_the_runtime_encoder() {
+    assert_eq!(jsfuck!("alert(1)"), lbp::compile("alert(1)"));
+    assert_eq!(jsfuck!(""), lbp::compile(""));
+    assert_eq!(
+        jsfuck!("document.title = 'caf\u{e9}\\n'"),
+        lbp::compile("document.title = 'caf\u{e9}\\n'")
+    );
+    assert_eq!(
+        jsfuck!(r#"console.log("raw")"#),
+        lbp::compile(r#"console.log("raw")"#)
+    );
+}
+
+#[test]
+fn is_static() {
+    static ENCODED: &str = jsfuck!("alert(1)");
+    assert_eq!(decode(ENCODED).unwrap(), "alert(1)");
+}
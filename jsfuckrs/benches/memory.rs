@@ -0,0 +1,85 @@
+//! Peak heap usage of compiling a 50 KB script into a `String` and streaming it into a sink
+//!
+//! Run with `cargo bench --bench memory --features dhat-heap`. Every measurement runs in a process
+//! of its own, as dhat only knows the peak since the profiler started.
+
+#[cfg(feature = "dhat-heap")]
+#[global_allocator]
+static ALLOC: dhat::Alloc = dhat::Alloc;
+
+#[cfg(all(feature = "dhat-heap", any(feature = "lbp", feature = "aemkei")))]
+mod heap {
+    use std::{env, io, process::Command};
+
+    use jsfuckrs::{Backend, IoWriter, JsFuckEncoder};
+
+    /// Selects the measurement a child process runs
+    const CHILD: &str = "JSFUCK_MEMORY_BENCH";
+
+    /// Counts the bytes written into it
+    struct Counter(usize);
+
+    impl io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn script() -> String {
+        let line = "document.querySelector(\"#out\").textContent += `caf\u{e9} ${i} \u{1f600}`;\n";
+        line.repeat(50 * 1024 / line.len())
+    }
+
+    fn measure(backend: Backend, method: &str) {
+        let _profiler = dhat::Profiler::builder().testing().build();
+        let script = script();
+        // The derivation tables are built once and kept, so they do not count
+        backend.compile(&script[..200]);
+        let baseline = dhat::HeapStats::get().curr_bytes;
+        let len = if method == "string" {
+            backend.compile(&script).len()
+        } else {
+            let mut out = IoWriter::new(Counter(0));
+            let result = backend.compile_to(&mut out, &script);
+            out.finish(result).map_or(0, |counter| counter.0)
+        };
+        let peak = dhat::HeapStats::get().max_bytes - baseline;
+        println!("{peak} {len}");
+    }
+
+    pub fn main() {
+        if let Ok(child) = env::var(CHILD) {
+            let (backend, method) = child.split_once('/').expect("backend/method");
+            measure(backend.parse().expect("known backend"), method);
+            return;
+        }
+        let exe = env::current_exe().expect("path of the benchmark");
+        println!("{} byte script", script().len());
+        for backend in Backend::ALL {
+            for method in ["string", "stream"] {
+                let output = Command::new(&exe)
+                    .env(CHILD, format!("{backend}/{method}"))
+                    .output()
+                    .expect("benchmark runs");
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let (peak, len) = stdout.trim().split_once(' ').unwrap_or(("?", "?"));
+                println!(
+                    "{:>10} {method:>6}: peak {peak:>9} bytes, output {len:>8} bytes",
+                    backend.name()
+                );
+            }
+        }
+    }
+}
+
+fn main() {
+    #[cfg(all(feature = "dhat-heap", any(feature = "lbp", feature = "aemkei")))]
+    heap::main();
+    #[cfg(not(all(feature = "dhat-heap", any(feature = "lbp", feature = "aemkei"))))]
+    eprintln!("Measuring memory needs `--features dhat-heap` and a backend");
+}
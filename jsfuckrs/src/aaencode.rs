@@ -0,0 +1,227 @@
+//! Port of Yosuke Hasegawa's AAencode
+//!
+//! The output is a script of Japanese style emoticons like `(ﾟДﾟ)` and `(o^_^o)`, which are
+//! variables holding the building blocks. Each character of the code becomes an escape sequence
+//! whose digits are sums of those variables.
+//!
+//! [Original](https://utf-8.jp/public/aaencode.html)
+use std::fmt;
+
+use crate::{writer::collect, JsFuckEncoder};
+
+/// The expressions evaluating to the hexadecimal digits, numbers up to `9` and letters after
+const DIGITS: [&str; 16] = [
+    "(c^_^o)",
+    "(ﾟΘﾟ)",
+    "((o^_^o) - (ﾟΘﾟ))",
+    "(o^_^o)",
+    "(ﾟｰﾟ)",
+    "((ﾟｰﾟ) + (ﾟΘﾟ))",
+    "((o^_^o) +(o^_^o))",
+    "((ﾟｰﾟ) + (o^_^o))",
+    "((ﾟｰﾟ) + (ﾟｰﾟ))",
+    "((ﾟｰﾟ) + (ﾟｰﾟ) + (ﾟΘﾟ))",
+    "(ﾟДﾟ) .ﾟωﾟﾉ",
+    "(ﾟДﾟ) .ﾟΘﾟﾉ",
+    "(ﾟДﾟ) ['c']",
+    "(ﾟДﾟ) .ﾟｰﾟﾉ",
+    "(ﾟДﾟ) .ﾟДﾟﾉ",
+    "(ﾟДﾟ) [ﾟΘﾟ]",
+];
+
+/// Assigns the digits, `"constructor"` to `(ﾟoﾟ)`, `"return"` to `(ﾟεﾟ)`, `Function` to
+/// `(ﾟДﾟ) ['_']`, and a backslash, `u` and a quote to `(ﾟДﾟ)[ﾟεﾟ]`, `(oﾟｰﾟo)` and `(ﾟДﾟ)[ﾟoﾟ]`
+const PRELUDE: &str = concat!(
+    "ﾟωﾟﾉ= /｀ｍ´）ﾉ ~┻━┻   //*´∇｀*/ ['_']; o=(ﾟｰﾟ)  =_=3; c=(ﾟΘﾟ) =(ﾟｰﾟ)-(ﾟｰﾟ); ",
+    "(ﾟДﾟ) =(ﾟΘﾟ)= (o^_^o)/ (o^_^o);",
+    "(ﾟДﾟ)={ﾟΘﾟ: '_' ,ﾟωﾟﾉ : ((ﾟωﾟﾉ==3) +'_') [ﾟΘﾟ] ",
+    ",ﾟｰﾟﾉ :(ﾟωﾟﾉ+ '_')[o^_^o -(ﾟΘﾟ)] ",
+    ",ﾟДﾟﾉ:((ﾟｰﾟ==3) +'_')[ﾟｰﾟ] }; (ﾟДﾟ) [ﾟΘﾟ] =((ﾟωﾟﾉ==3) +'_') [c^_^o];",
+    "(ﾟДﾟ) ['c'] = ((ﾟДﾟ)+'_') [ (ﾟｰﾟ)+(ﾟｰﾟ)-(ﾟΘﾟ) ];",
+    "(ﾟДﾟ) ['o'] = ((ﾟДﾟ)+'_') [ﾟΘﾟ];",
+    "(ﾟoﾟ)=(ﾟДﾟ) ['c']+(ﾟДﾟ) ['o']+(ﾟωﾟﾉ +'_')[ﾟΘﾟ]+ ((ﾟωﾟﾉ==3) +'_') [ﾟｰﾟ] + ",
+    "((ﾟДﾟ) +'_') [(ﾟｰﾟ)+(ﾟｰﾟ)]+ ((ﾟｰﾟ==3) +'_') [ﾟΘﾟ]+",
+    "((ﾟｰﾟ==3) +'_') [(ﾟｰﾟ) - (ﾟΘﾟ)]+(ﾟДﾟ) ['c']+",
+    "((ﾟДﾟ)+'_') [(ﾟｰﾟ)+(ﾟｰﾟ)]+ (ﾟДﾟ) ['o']+",
+    "((ﾟｰﾟ==3) +'_') [ﾟΘﾟ];(ﾟДﾟ) ['_'] =(o^_^o) [ﾟoﾟ] [ﾟoﾟ];",
+    "(ﾟεﾟ)=((ﾟｰﾟ==3) +'_') [ﾟΘﾟ]+ (ﾟДﾟ) .ﾟДﾟﾉ+",
+    "((ﾟДﾟ)+'_') [(ﾟｰﾟ) + (ﾟｰﾟ)]+((ﾟｰﾟ==3) +'_') [o^_^o -ﾟΘﾟ]+",
+    "((ﾟｰﾟ==3) +'_') [ﾟΘﾟ]+ (ﾟωﾟﾉ +'_') [ﾟΘﾟ]; ",
+    "(ﾟｰﾟ)+=(ﾟΘﾟ); (ﾟДﾟ)[ﾟεﾟ]='\\\\'; ",
+    "(ﾟДﾟ).ﾟΘﾟﾉ=(ﾟДﾟ+ ﾟｰﾟ)[o^_^o -(ﾟΘﾟ)];",
+    "(oﾟｰﾟo)=(ﾟωﾟﾉ +'_')[c^_^o];",
+    "(ﾟДﾟ) [ﾟoﾟ]='\\\"';",
+);
+
+/// Opens `Function("return\"…\"")(1)`, the string of escape sequences
+const ESCAPED: &str = "(ﾟДﾟ) ['_'] (ﾟεﾟ+(ﾟДﾟ)[ﾟoﾟ]+ ";
+
+/// Closes the string and the call opened by [`ESCAPED`]
+const ESCAPED_END: &str = "(ﾟДﾟ)[ﾟoﾟ]) (ﾟΘﾟ)";
+
+/// The encoder, whose output assigns the emoticons as global variables
+///
+/// # Examples
+/// ```
+/// use jsfuckrs::{aaencode::Aaencode, eval::eval, JsFuckEncoder};
+/// assert_eq!(eval(&Aaencode.string("caf\u{e9}")).unwrap().to_string(), "caf\u{e9}");
+/// assert_eq!(eval(&Aaencode.number(42)).unwrap().to_string(), "42");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Aaencode;
+
+impl JsFuckEncoder for Aaencode {
+    fn number(&self, n: usize) -> String {
+        collect(|out| {
+            open_expression(out)?;
+            if n < 10 {
+                out.write_str(DIGITS[n])?;
+            } else {
+                out.write_str("+(")?;
+                write_concatenation(out, &n.to_string())?;
+                out.write_char(')')?;
+            }
+            out.write_char(')')
+        })
+    }
+
+    /// Adds up the digits when every character is one, and builds the string from escape
+    /// sequences otherwise
+    fn string_to(&self, out: &mut dyn fmt::Write, s: &str) -> fmt::Result {
+        open_expression(out)?;
+        if s.chars().all(|c| self.is_mapped(c)) {
+            write_concatenation(out, s)?;
+        } else {
+            write_escaped(out, s)?;
+        }
+        out.write_char(')')
+    }
+
+    fn compile_to(&self, out: &mut dyn fmt::Write, code: &str) -> fmt::Result {
+        compile_to(out, code)
+    }
+
+    fn is_mapped(&self, c: char) -> bool {
+        matches!(c, '0'..='9' | 'a'..='f')
+    }
+
+    fn function_constructor(&self) -> String {
+        collect(|out| {
+            open_expression(out)?;
+            out.write_str("(ﾟДﾟ) ['_'])")
+        })
+    }
+}
+
+/// Encodes `code` into a script running it
+///
+/// # Examples
+/// ```
+/// use jsfuckrs::{aaencode, decode};
+/// let encoded = aaencode::compile("alert(1)");
+/// assert!(encoded.starts_with("ﾟωﾟﾉ= /｀ｍ´）ﾉ ~┻━┻   //*´∇｀*/ ['_'];"));
+/// assert_eq!(decode(&encoded).unwrap(), "alert(1)");
+/// ```
+#[must_use]
+pub fn compile(code: &str) -> String {
+    collect(|out| compile_to(out, code))
+}
+
+/// Streams what [`compile`] returns into `out`
+///
+/// # Errors
+/// When `out` fails
+pub fn compile_to<W: fmt::Write + ?Sized>(out: &mut W, code: &str) -> fmt::Result {
+    out.write_str(PRELUDE)?;
+    out.write_str("(ﾟДﾟ) ['_'] ( ")?;
+    write_escaped(out, code)?;
+    out.write_str(") ('_');")
+}
+
+/// The string [`ESCAPED`] builds, evaluating to `s`
+fn write_escaped<W: fmt::Write + ?Sized>(out: &mut W, s: &str) -> fmt::Result {
+    out.write_str(ESCAPED)?;
+    for unit in s.encode_utf16() {
+        out.write_str("(ﾟДﾟ)[ﾟεﾟ]+")?;
+        // `\` followed by an octal escape of ASCII, or by `u` and four hexadecimal digits
+        let digits = if unit < 0x80 {
+            format!("{unit:o}")
+        } else {
+            out.write_str("(oﾟｰﾟo)+ ")?;
+            format!("{unit:04x}")
+        };
+        for digit in digits.chars() {
+            let digit = digit.to_digit(16).unwrap_or_default() as usize;
+            write!(out, "{}+ ", DIGITS[digit])?;
+        }
+    }
+    out.write_str(ESCAPED_END)
+}
+
+/// The hexadecimal digits of `s` added to an empty string
+fn write_concatenation(out: &mut dyn fmt::Write, s: &str) -> fmt::Result {
+    out.write_str("''")?;
+    for digit in s.chars() {
+        let digit = digit.to_digit(16).unwrap_or_default() as usize;
+        write!(out, "+{}", DIGITS[digit])?;
+    }
+    Ok(())
+}
+
+/// Opens an expression that makes the assignments of [`PRELUDE`], then evaluates to what is
+/// written after it up to a closing parenthesis
+fn open_expression(out: &mut dyn fmt::Write) -> fmt::Result {
+    write!(out, "({},", PRELUDE.trim_end_matches(';').replace(';', ","))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{decode, eval::Evaluator};
+
+    const CORPUS: [&str; 6] = [
+        "alert(1);",
+        "console.log(\"Hello world!\");",
+        "",
+        "x = '\\\\' + \"\\\"\"\n\t\u{7f}",
+        "document.title = 'caf\u{e9} \u{1f600}'",
+        "\u{0}1 \u{9}5",
+    ];
+
+    #[test]
+    fn round_trip() {
+        for code in CORPUS {
+            let encoded = compile(code);
+            let mut evaluator = Evaluator::default();
+            // Running the code itself fails on anything but the empty string
+            evaluator.eval(&encoded).ok();
+            assert_eq!(evaluator.code().last().unwrap(), code, "{code:?}");
+            assert_eq!(decode(&encoded).unwrap(), code, "{code:?}");
+        }
+    }
+
+    #[test]
+    fn expressions() {
+        for code in CORPUS.into_iter().chain(["2024", "c0ffee"]) {
+            assert_eq!(decode(&Aaencode.string(code)).unwrap(), code, "{code:?}");
+        }
+        for n in [0, 9, 10, 4096] {
+            assert_eq!(decode(&Aaencode.number(n)).unwrap(), n.to_string());
+        }
+        let name = format!(
+            "{}[{}]",
+            Aaencode.function_constructor(),
+            Aaencode.string("name")
+        );
+        assert_eq!(decode(&name).unwrap(), "Function");
+    }
+
+    #[test]
+    fn emoticons() {
+        let encoded = compile("a");
+        assert!(encoded.ends_with("(ﾟДﾟ)[ﾟεﾟ]+(ﾟΘﾟ)+ (ﾟｰﾟ)+ (ﾟΘﾟ)+ (ﾟДﾟ)[ﾟoﾟ]) (ﾟΘﾟ)) ('_');"));
+        assert!(compile("\u{e9}").contains("(ﾟДﾟ)[ﾟεﾟ]+(oﾟｰﾟo)+ (c^_^o)+ (c^_^o)+ (ﾟДﾟ) .ﾟДﾟﾉ+ "));
+        assert!(!compile("alert(1)").contains("alert"));
+    }
+}
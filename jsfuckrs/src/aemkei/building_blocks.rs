@@ -0,0 +1,652 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    iter_utils::IteratorUtils,
+    writer::{collect, count},
+};
+use lazy_static::lazy_static;
+
+pub const FALSE: &str = "![]";
+pub const TRUE: &str = "!![]";
+pub const UNDEFINED: &str = "[][[]]";
+pub const NAN: &str = "+[![]]";
+/// `+"1e1000"`
+pub const INFINITY: &str = "+(+!+[]+(!+[]+[])[!+[]+!+[]+!+[]]+[+!+[]]+[+[]]+[+[]]+[+[]])";
+
+/// Values that are encoded as a whole wherever their name appears, in matching order
+const SIMPLE: [(&str, &str); 5] = [
+    ("false", FALSE),
+    ("true", TRUE),
+    ("undefined", UNDEFINED),
+    ("NaN", NAN),
+    ("Infinity", INFINITY),
+];
+
+/// Expressions whose `constructor` is the global of the same name
+const CONSTRUCTORS: [(&str, &str); 7] = [
+    ("Array", "[]"),
+    ("Number", "(+[])"),
+    ("String", "([]+[])"),
+    ("Boolean", "(![])"),
+    ("Function", "[][\"flat\"]"),
+    ("RegExp", "Function(\"return/\"+false+\"/\")()"),
+    ("Object", "[][\"entries\"]()"),
+];
+
+/// Characters that can be read out of a value as readable javascript, before it is rewritten in
+/// terms of the six symbols.
+///
+/// [`None`] marks characters without a cheap derivation. Those are produced by evaluating a
+/// `"\uXXXX"` escape instead.
+const READABLE_MAP: [(char, Option<&str>); 85] = [
+    ('a', Some("(false+\"\")[1]")),
+    ('b', Some("([][\"entries\"]()+\"\")[2]")),
+    ('c', Some("([][\"flat\"]+\"\")[3]")),
+    ('d', Some("(undefined+\"\")[2]")),
+    ('e', Some("(true+\"\")[3]")),
+    ('f', Some("(false+\"\")[0]")),
+    ('g', Some("(false+[0]+String)[20]")),
+    ('h', Some("(+(101))[\"to\"+String[\"name\"]](21)[1]")),
+    ('i', Some("([false]+undefined)[10]")),
+    ('j', Some("([][\"entries\"]()+\"\")[3]")),
+    ('k', Some("(+(20))[\"to\"+String[\"name\"]](21)")),
+    ('l', Some("(false+\"\")[2]")),
+    ('m', Some("(Number+\"\")[11]")),
+    ('n', Some("(undefined+\"\")[1]")),
+    ('o', Some("(true+[][\"flat\"])[10]")),
+    ('p', Some("(+(211))[\"to\"+String[\"name\"]](31)[1]")),
+    ('q', Some("(\"\")[\"fontcolor\"]([0]+false+\")[20]")),
+    ('r', Some("(true+\"\")[1]")),
+    ('s', Some("(false+\"\")[3]")),
+    ('t', Some("(true+\"\")[0]")),
+    ('u', Some("(undefined+\"\")[0]")),
+    ('v', Some("(+(31))[\"to\"+String[\"name\"]](32)")),
+    ('w', Some("(+(32))[\"to\"+String[\"name\"]](33)")),
+    ('x', Some("(+(101))[\"to\"+String[\"name\"]](34)[1]")),
+    ('y', Some("(NaN+[Infinity])[10]")),
+    ('z', Some("(+(35))[\"to\"+String[\"name\"]](36)")),
+    ('A', Some("(NaN+[][\"entries\"]())[11]")),
+    ('B', Some("(+[]+Boolean)[10]")),
+    (
+        'C',
+        Some("Function(\"return escape\")()((\"\")[\"italics\"]())[2]"),
+    ),
+    (
+        'D',
+        Some("Function(\"return escape\")()([][\"flat\"])[\"slice\"](\"-1\")"),
+    ),
+    ('E', Some("(RegExp+\"\")[12]")),
+    ('F', Some("(+[]+Function)[10]")),
+    ('G', Some("(false+Function(\"return Date\")()())[30]")),
+    ('H', None),
+    ('I', Some("(Infinity+\"\")[0]")),
+    ('J', None),
+    ('K', None),
+    ('L', None),
+    ('M', Some("(true+Function(\"return Date\")()())[30]")),
+    ('N', Some("(NaN+\"\")[0]")),
+    ('O', Some("(+[]+Object)[10]")),
+    ('P', None),
+    ('Q', None),
+    ('R', Some("(+[]+RegExp)[10]")),
+    ('S', Some("(+[]+String)[10]")),
+    ('T', Some("(NaN+Function(\"return Date\")()())[30]")),
+    (
+        'U',
+        Some("(NaN+Object()[\"to\"+String[\"name\"]][\"call\"]())[11]"),
+    ),
+    ('V', None),
+    ('W', None),
+    ('X', None),
+    ('Y', None),
+    ('Z', None),
+    (' ', Some("(NaN+[][\"flat\"])[11]")),
+    ('!', None),
+    ('"', Some("(\"\")[\"fontcolor\"]()[12]")),
+    ('#', None),
+    ('$', None),
+    ('%', Some("Function(\"return escape\")()([][\"flat\"])[21]")),
+    ('&', Some("(\"\")[\"fontcolor\"](\")[13]")),
+    ('\'', None),
+    ('(', Some("([][\"flat\"]+\"\")[13]")),
+    (')', Some("([0]+false+[][\"flat\"])[20]")),
+    ('*', None),
+    (
+        '+',
+        Some("(+(+!+[]+(!+[]+[])[!+[]+!+[]+!+[]]+[+!+[]]+[+[]]+[+[]])+[])[2]"),
+    ),
+    (',', Some("[[]][\"concat\"]([[]])+\"\"")),
+    ('-', Some("(+(.+[0000001])+\"\")[2]")),
+    (
+        '.',
+        Some("(+(+!+[]+[+!+[]]+(!![]+[])[!+[]+!+[]+!+[]]+[!+[]+!+[]]+[+[]])+[])[+!+[]]"),
+    ),
+    ('/', Some("(false+[0])[\"italics\"]()[10]")),
+    (':', Some("(RegExp()+\"\")[3]")),
+    (';', Some("(\"\")[\"fontcolor\"](NaN+\")[21]")),
+    ('<', Some("(\"\")[\"italics\"]()[0]")),
+    ('=', Some("(\"\")[\"fontcolor\"]()[11]")),
+    ('>', Some("(\"\")[\"italics\"]()[2]")),
+    ('?', Some("(RegExp()+\"\")[2]")),
+    ('@', None),
+    ('[', Some("([][\"entries\"]()+\"\")[0]")),
+    ('\\', Some("(RegExp(\"/\")+\"\")[1]")),
+    (']', Some("([][\"entries\"]()+\"\")[22]")),
+    ('^', None),
+    ('_', None),
+    ('`', None),
+    ('{', Some("(true+[][\"flat\"])[20]")),
+    ('|', None),
+    ('}', Some("([][\"flat\"]+\"\")[\"slice\"](\"-1\")")),
+    ('~', None),
+];
+
+/// The characters kept as-is when most of the input has to be escaped, because their own
+/// encodings are shorter than the escape sequence that would replace them
+const CHEAP_CHARS: &str = "0123456789.adefilnrsuN";
+
+fn is_symbol(c: char) -> bool {
+    matches!(c, '[' | ']' | '(' | ')' | '!' | '+')
+}
+
+/// Generates the expression for a single digit, `2` becoming `!+[]+!+[]`
+fn digit(n: u32) -> String {
+    match n {
+        0 => "+[]".to_owned(),
+        1 => "+!+[]".to_owned(),
+        n => (0..n).map(|_| "!+[]").collect_vec().join("+"),
+    }
+}
+
+/// Generates an expression that evaluates to the decimal string of the digits in `s`
+fn digits(s: &str) -> String {
+    let mut chars = s.chars().filter_map(|c| c.to_digit(10));
+    let head = chars.next().map(digit).unwrap_or_default();
+    std::iter::once(head)
+        .chain(chars.map(|d| format!("[{}]", digit(d))))
+        .collect_vec()
+        .join("+")
+}
+
+/// Generates jsf*ck expression that will evaluate into the desired number
+///
+/// # Examples
+/// ```
+/// use jsfuckrs::aemkei::building_blocks::number;
+/// assert_eq!(number(0), "+[]");
+/// assert_eq!(number(2), "!+[]+!+[]");
+/// assert_eq!(number(10), "+(+!+[]+[+[]])");
+/// ```
+pub fn number(n: usize) -> String {
+    match n {
+        0..=9 => digit(n as u32),
+        n => format!("+({})", digits(&n.to_string())),
+    }
+}
+
+/// Replaces every occurrence of `word` that starts at a word boundary
+fn replace_word(s: &str, word: &str, replacement: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(index) = rest.find(word) {
+        let boundary = !rest[..index]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_');
+        out.push_str(&rest[..index]);
+        out.push_str(if boundary { replacement } else { word });
+        rest = &rest[index + word.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Rewrites the number literals of a readable derivation in terms of the six symbols.
+///
+/// Numbers of two digits or more become digit strings, while single digits wrapped in brackets or
+/// parentheses become the array holding that digit. Other digits are left for
+/// [`resolve_strings`], which replaces them with the same array.
+fn replace_numbers(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = String::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        let len = bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+        if len >= 2 {
+            out.push_str(&digits(&s[i..i + len]));
+            i += len;
+        } else if len == 0
+            && matches!(
+                (bytes[i], bytes.get(i + 2)),
+                (b'(', Some(b')')) | (b'[', Some(b']'))
+            )
+            && bytes[i + 1].is_ascii_digit()
+        {
+            out.push_str(&format!("[{}]", digit(u32::from(bytes[i + 1] - b'0'))));
+            i += 3;
+        } else {
+            let c = s[i..].chars().next().unwrap_or_default();
+            out.push(c);
+            i += c.len_utf8();
+        }
+    }
+    out
+}
+
+/// Turns every `"string literal"` into the concatenation of its characters, leaving the
+/// characters themselves to be replaced by their derivations.
+fn split_strings(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('"') {
+        out.push_str(&rest[..start]);
+        match rest[start + 1..].find('"') {
+            Some(len) if len > 0 => {
+                let literal = &rest[start + 1..start + 1 + len];
+                out.push_str(&literal.chars().map(String::from).collect_vec().join("+"));
+                rest = &rest[start + len + 2..];
+            }
+            _ => {
+                out.push('"');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Replaces every character outside of the six symbols with its derivation, starting with the
+/// derivations that do not depend on any others.
+fn resolve_strings(map: &mut HashMap<char, String>) {
+    for value in map.values_mut() {
+        *value = split_strings(value);
+    }
+    let mut missing = map
+        .iter()
+        .filter(|(_, value)| !value.chars().all(is_symbol))
+        .map(|(&c, _)| c)
+        .collect_vec();
+    while !missing.is_empty() {
+        let before = missing.len();
+        for c in missing.clone() {
+            let value = map[&c]
+                .chars()
+                .map(|d| match map.get(&d) {
+                    Some(derivation) if !is_symbol(d) && !missing.contains(&d) => {
+                        derivation.clone()
+                    }
+                    _ => d.to_string(),
+                })
+                .collect();
+            map.insert(c, value);
+        }
+        missing.retain(|c| !map[c].chars().all(is_symbol));
+        assert!(
+            missing.len() < before,
+            "Could not compile the following chars: {missing:?}"
+        );
+    }
+}
+
+/// The escape sequence of a UTF-16 code unit inside a javascript string literal, with octal ones
+/// padded so a following digit is not taken as part of them
+fn escape_sequence(unit: u16) -> String {
+    if unit < 256 {
+        format!("\\{unit:03o}")
+    } else {
+        format!("\\u{unit:04x}")
+    }
+}
+
+fn escape_char(c: char) -> String {
+    let mut units = [0; 2];
+    c.encode_utf16(&mut units)
+        .iter()
+        .map(|&unit| escape_sequence(unit))
+        .collect()
+}
+
+fn encode(input: &str, wrap_with_eval: bool, run_in_parent_scope: bool) -> String {
+    collect(|out| encode_to(out, input, wrap_with_eval, run_in_parent_scope))
+}
+
+/// Port of `JSFuck.encode`, writing the wrappers around the characters as it goes
+pub(super) fn encode_to(
+    out: &mut dyn fmt::Write,
+    input: &str,
+    wrap_with_eval: bool,
+    run_in_parent_scope: bool,
+) -> fmt::Result {
+    if input.is_empty() {
+        return Ok(());
+    }
+    let unmapped = input
+        .chars()
+        .filter(|c| !MAP.contains_key(c))
+        .map(char::len_utf16)
+        .sum::<usize>();
+    // Unmapped characters are escaped one by one inside a string literal, or every character but
+    // the cheapest is escaped with `t` in place of the backslash, which is then put back by
+    // `split` and `join`. The backslash costs about 2000 symbols, so the second way is usually
+    // shorter once there are several unmapped characters, although not always.
+    let (input, split) = if unmapped == 0 {
+        (input.to_owned(), false)
+    } else {
+        let escaped = input
+            .chars()
+            .map(|c| match c {
+                '"' | '\\' => escape_char(c),
+                c if !MAP.contains_key(&c) => escape_char(c),
+                c => c.to_string(),
+            })
+            .collect::<String>();
+        let split = input
+            .chars()
+            .map(|c| match c {
+                c if CHEAP_CHARS.contains(c) => c.to_string(),
+                c => escape_char(c).replace('\\', "t"),
+            })
+            .collect::<String>();
+        if count(|out| write_body(out, &split, true))
+            < count(|out| write_body(out, &escaped, false))
+        {
+            (split, true)
+        } else {
+            (escaped, false)
+        }
+    };
+
+    let function = || {
+        format!(
+            "[][{}][{}]",
+            encode("flat", false, false),
+            encode("constructor", false, false)
+        )
+    };
+    if wrap_with_eval {
+        if run_in_parent_scope {
+            write!(
+                out,
+                "{}({})()(",
+                function(),
+                encode("return eval", false, false)
+            )?;
+        } else {
+            write!(out, "{}(", function())?;
+        }
+    }
+    if unmapped > 0 {
+        write!(out, "{}({}+", function(), encode("return\"", false, false))?;
+    }
+    write_body(out, &input, split)?;
+    if unmapped > 0 {
+        write!(out, "+{})()", encode("\"", false, false))?;
+    }
+    if wrap_with_eval {
+        out.write_str(if run_in_parent_scope { ")" } else { ")()" })?;
+    }
+    Ok(())
+}
+
+/// Writes the characters of `input`, putting the backslashes replaced by `t` back when `split`
+fn write_body(out: &mut dyn fmt::Write, input: &str, split: bool) -> fmt::Result {
+    if split {
+        out.write_char('(')?;
+    }
+    let mut rest = input;
+    while let Some(c) = rest.chars().next() {
+        if rest.len() < input.len() {
+            out.write_char('+')?;
+        }
+        match SIMPLE.iter().find(|(name, _)| rest.starts_with(name)) {
+            Some((name, value)) => {
+                write!(out, "({value}+[])")?;
+                rest = &rest[name.len()..];
+            }
+            None => {
+                out.write_str(&MAP[&c])?;
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    if input.len() == 1 && input.chars().all(|c| c.is_ascii_digit()) {
+        out.write_str("+[]")?;
+    }
+    if split {
+        write!(
+            out,
+            ")[{}]({})[{}]({})",
+            encode("split", false, false),
+            encode("t", false, false),
+            encode("join", false, false),
+            encode("\\", false, false)
+        )?;
+    }
+    Ok(())
+}
+
+/// Generates jsf*ck expression that will evaluate into the desired string
+///
+/// # Examples
+/// ```
+/// use jsfuckrs::aemkei::building_blocks::string;
+/// assert_eq!(string("a"), "(![]+[])[+!+[]]");
+/// assert_eq!(string("true"), "(!![]+[])");
+/// ```
+pub fn string<T>(s: T) -> String
+where
+    T: AsRef<str>,
+{
+    encode(s.as_ref(), false, false)
+}
+
+/// Writes the expression [`string`] generates into `out`, one character at a time
+pub fn string_to<W, T>(out: &mut W, s: T) -> fmt::Result
+where
+    W: fmt::Write,
+    T: AsRef<str>,
+{
+    encode_to(out, s.as_ref(), false, false)
+}
+
+/// Characters of [`READABLE_MAP`] without a value are built from their escape sequence too
+pub(super) fn is_mapped(c: char) -> bool {
+    MAP.contains_key(&c) && !READABLE_MAP.contains(&(c, None))
+}
+
+/// Compile javascript code into jsf*ck that runs it through `[]["flat"]["constructor"](code)()`
+pub fn compile<T>(code: T) -> String
+where
+    T: AsRef<str>,
+{
+    encode(code.as_ref(), true, false)
+}
+
+/// Writes the output of [`compile`] into `out`, one character at a time
+pub fn compile_to<W, T>(out: &mut W, code: T) -> fmt::Result
+where
+    W: fmt::Write,
+    T: AsRef<str>,
+{
+    encode_to(out, code.as_ref(), true, false)
+}
+
+lazy_static! {
+    static ref MAP: HashMap<char, String> = {
+        let raw: HashMap<char, &str> = READABLE_MAP
+            .iter()
+            .filter_map(|&(c, value)| Some((c, value?)))
+            .collect();
+        let mut m: HashMap<char, String> = READABLE_MAP
+            .iter()
+            .map(|&(c, value)| {
+                let value = value.map_or_else(
+                    || {
+                        let hex = format!("{:04x}", u32::from(c));
+                        format!(
+                            "Function(\"return\"+{quote}+\"\\u\"+{}+{quote})()",
+                            hex.chars().map(String::from).collect_vec().join("+"),
+                            quote = raw[&'"']
+                        )
+                    },
+                    str::to_owned,
+                );
+                (c, value)
+            })
+            .collect();
+        for value in m.values_mut() {
+            let mut replaced = value.clone();
+            // Constructors may be written in terms of other constructors
+            loop {
+                let next = CONSTRUCTORS.iter().fold(replaced.clone(), |s, (name, value)| {
+                    replace_word(&s, name, &format!("{value}[\"constructor\"]"))
+                });
+                if next == replaced {
+                    break;
+                }
+                replaced = next;
+            }
+            for (name, value) in SIMPLE {
+                replaced = replaced.replace(name, value);
+            }
+            replaced = replace_numbers(&replaced)
+                .replace("+\"\"", "+[]")
+                .replace("\"\"", "[]+[]");
+            *value = replaced;
+        }
+        for n in 0..10 {
+            m.insert(
+                char::from_digit(n, 10).unwrap_or_default(),
+                format!("[{}]", digit(n)),
+            );
+        }
+        resolve_strings(&mut m);
+        m
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    mod numbers {
+        use crate::aemkei::building_blocks::*;
+
+        #[test]
+        fn digits() {
+            assert_eq!(number(0), "+[]");
+            assert_eq!(number(1), "+!+[]");
+            assert_eq!(number(3), "!+[]+!+[]+!+[]");
+        }
+
+        #[test]
+        fn multiple_digits() {
+            assert_eq!(number(21), "+(!+[]+!+[]+[+!+[]])");
+            assert_eq!(number(100), "+(+!+[]+[+[]]+[+[]])");
+        }
+    }
+
+    mod chars {
+        use crate::aemkei::building_blocks::*;
+        use crate::is_jsfuck;
+
+        #[test]
+        fn printable_ascii_is_mapped() {
+            for c in ' '..='~' {
+                assert!(MAP.contains_key(&c), "{c:?} is not mapped");
+                assert!(is_jsfuck(&MAP[&c]), "{c:?} is not strict: {}", MAP[&c]);
+            }
+        }
+
+        #[test]
+        fn a() {
+            assert_eq!(MAP[&'a'], "(![]+[])[+!+[]]");
+        }
+
+        #[test]
+        fn digits() {
+            assert_eq!(MAP[&'0'], "[+[]]");
+            assert_eq!(MAP[&'2'], "[!+[]+!+[]]");
+        }
+    }
+
+    mod strings {
+        use crate::aemkei::building_blocks::*;
+        use crate::is_jsfuck;
+
+        #[test]
+        fn simple_values() {
+            assert_eq!(string("false"), "(![]+[])");
+            assert_eq!(string("NaN"), "(+[![]]+[])");
+        }
+
+        #[test]
+        fn single_digit() {
+            assert_eq!(string("1"), "[+!+[]]+[]");
+        }
+
+        #[test]
+        fn unmapped() {
+            assert!(is_jsfuck(&string("\u{e9}")));
+            assert!(is_jsfuck(&string("\u{1f600} and \u{e9}")));
+        }
+    }
+
+    mod compiled {
+        use crate::aemkei::building_blocks::compile;
+        use crate::is_jsfuck;
+
+        #[test]
+        fn hello_world() {
+            let compiled = compile("console.log(\"Hello world!\");");
+            assert!(is_jsfuck(&compiled));
+            assert_eq!(compiled, include_str!("data/hello_world.txt"));
+        }
+
+        #[test]
+        fn alert() {
+            let compiled = compile("alert(1);");
+            assert!(is_jsfuck(&compiled));
+            assert_eq!(compiled, include_str!("data/alert.txt"));
+        }
+
+        #[test]
+        #[cfg(feature = "lbp")]
+        fn differs_from_lbp() {
+            assert_ne!(compile("alert(1);"), crate::lbp::compile("alert(1);"));
+        }
+    }
+
+    mod evaluated {
+        use crate::aemkei::building_blocks::*;
+        use crate::eval::{eval, Evaluator};
+
+        #[test]
+        fn chars() {
+            for (c, encoded) in MAP.iter() {
+                let value = eval(encoded).unwrap_or_else(|e| panic!("{c:?}: {e:?}"));
+                assert_eq!(value.to_string(), c.to_string(), "{encoded}");
+            }
+        }
+
+        #[test]
+        fn strings() {
+            for s in [
+                "Hello world!",
+                "\u{e9}",
+                "\u{1f600} and \u{e9}",
+                "7",
+                "\u{0}0 \u{7}\u{e9}",
+            ] {
+                assert_eq!(eval(&string(s)).unwrap().to_string(), s);
+            }
+        }
+
+        #[test]
+        fn compiled() {
+            let code = "console.log(\"Hello world!\");";
+            let mut evaluator = Evaluator::default();
+            assert!(evaluator.eval(&compile(code)).is_err());
+            assert_eq!(evaluator.code().last().unwrap(), code);
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! Port of the encoder behind [jsfuck.com](http://www.jsfuck.com)
+//!
+//! Unlike [`lbp`](crate::lbp), its output only ever uses the six characters `[]()!+`.
+//!
+//! [GitHub Link](https://github.com/aemkei/jsfuck/blob/master/jsfuck.js)
+use std::fmt;
+
+pub mod building_blocks;
+
+pub use building_blocks::{compile, compile_to};
+
+/// The [`JsFuckEncoder`](crate::JsFuckEncoder) of this backend
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Aemkei;
+
+impl crate::JsFuckEncoder for Aemkei {
+    fn number(&self, n: usize) -> String {
+        building_blocks::number(n)
+    }
+
+    fn string_to(&self, out: &mut dyn fmt::Write, s: &str) -> fmt::Result {
+        building_blocks::encode_to(out, s, false, false)
+    }
+
+    fn compile_to(&self, out: &mut dyn fmt::Write, code: &str) -> fmt::Result {
+        building_blocks::encode_to(out, code, true, false)
+    }
+
+    fn is_mapped(&self, c: char) -> bool {
+        building_blocks::is_mapped(c)
+    }
+}
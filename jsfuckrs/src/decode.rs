@@ -0,0 +1,225 @@
+//! Recovers the source of jsf*ck encoded programs
+//!
+//! Compiled programs end by calling a function made with the `Function` constructor, or by handing
+//! a string to `eval`. Everything before that final call is constant folded with the
+//! [evaluator](crate::eval) and the code it would run is returned without running it. The scripts
+//! of [`jjencode`](crate::jjencode) and [`aaencode`](crate::aaencode) are statements ending with
+//! such a call. Any other
+//! expression, like the output of [`JsFuckEncoder::string`](crate::JsFuckEncoder::string), decodes
+//! to the string it evaluates to. So does the program returning a string literal of escape
+//! sequences that [aemkei](crate::aemkei) builds strings with unmapped characters from, when it is
+//! exactly what an encoder writes for that string.
+use crate::{
+    eval::{eval, Builtin, EvalError, EvalErrorType, Function, Value},
+    Backend, JsFuckEncoder,
+};
+use miette::{Diagnostic, SourceSpan};
+use thiserror::Error;
+
+#[derive(Error, Diagnostic, Debug)]
+#[error("Could not decode the expression")]
+pub struct DecodeError {
+    #[source_code]
+    pub src: String,
+    #[label("{err_type}")]
+    pub location: SourceSpan,
+    #[diagnostic_source]
+    pub err_type: EvalErrorType,
+}
+
+impl DecodeError {
+    /// Converts an error in `src[offset..]` into one pointing into `src`
+    fn new(src: &str, err: EvalError, offset: usize) -> Self {
+        Self {
+            src: src.to_owned(),
+            location: (offset + err.location.offset(), err.location.len()).into(),
+            err_type: err.err_type,
+        }
+    }
+}
+
+/// Decodes a jsf*ck expression into the code it runs or the string it evaluates to
+///
+/// # Examples
+/// ```
+/// use jsfuckrs::{decode, Backend, JsFuckEncoder};
+/// for backend in Backend::ALL {
+///     assert_eq!(decode(&backend.compile("alert(1)")).unwrap(), "alert(1)");
+///     assert_eq!(decode(&backend.string("jsf*ck")).unwrap(), "jsf*ck");
+/// }
+/// ```
+pub fn decode(src: &str) -> Result<String, DecodeError> {
+    let trimmed = src.trim_end().trim_end_matches(';').trim_end();
+    // Encoders turn the empty string into no code at all
+    if trimmed.trim_start().is_empty() {
+        return Ok(String::new());
+    }
+    if let Some(open) = final_call(trimmed) {
+        let args = &trimmed[open + 1..trimmed.len() - 1];
+        match eval(&trimmed[..open]) {
+            // A function without parameters ignores its arguments, like the `'_'` of aaencode
+            Ok(Value::Function(Function::Dynamic { body, params }))
+                if params.is_empty() || args.trim().is_empty() =>
+            {
+                if returns_escaped_string(&body) {
+                    if let Ok(Value::String(s)) = eval(trimmed) {
+                        // A program that happens to return such a literal is left alone
+                        let s = String::from_utf16_lossy(&s);
+                        if Backend::ALL
+                            .iter()
+                            .any(|backend| backend.string(&s) == trimmed)
+                        {
+                            return Ok(s);
+                        }
+                    }
+                }
+                return Ok(body);
+            }
+            Ok(Value::Function(Function::Builtin(Builtin::Eval))) => {
+                return eval(args)
+                    .map(|code| code.to_string())
+                    .map_err(|err| DecodeError::new(src, err, open + 1));
+            }
+            _ => {}
+        }
+    }
+    eval(src)
+        .map(|value| value.to_string())
+        .map_err(|err| DecodeError::new(src, err, 0))
+}
+
+/// Whether `body` is the `return"…"` aemkei builds strings with unmapped characters from, a
+/// string literal with at least one of the escape sequences it writes
+fn returns_escaped_string(body: &str) -> bool {
+    let Some(literal) = body
+        .strip_prefix("return\"")
+        .and_then(|rest| rest.strip_suffix('"'))
+    else {
+        return false;
+    };
+    let mut chars = literal.chars();
+    let mut escaped = false;
+    let digits = |count: usize, radix: u32, chars: &mut std::str::Chars| {
+        (0..count).all(|_| chars.next().is_some_and(|c| c.is_digit(radix)))
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return false,
+            '\\' => match chars.next() {
+                Some('"' | '\\') => {}
+                Some('u') if digits(4, 16, &mut chars) => escaped = true,
+                Some('0'..='3') if digits(2, 8, &mut chars) => escaped = true,
+                _ => return false,
+            },
+            _ => {}
+        }
+    }
+    escaped
+}
+
+/// The index of the parenthesis opening the arguments of the call `src` ends with
+fn final_call(src: &str) -> Option<usize> {
+    if !src.ends_with(')') {
+        return None;
+    }
+    let mut depth = 0usize;
+    for (i, b) in src.bytes().enumerate().rev() {
+        match b {
+            b')' | b']' | b'}' => depth += 1,
+            b'(' | b'[' | b'{' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return (b == b'(' && i > 0).then_some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    const CORPUS: [&str; 9] = [
+        "alert(1);",
+        "console.log(\"Hello world!\");",
+        "",
+        "return 1+1",
+        "return \"abc\"",
+        "return 1",
+        "document.title = 'caf\u{e9} \u{1f600}'",
+        "if (a) { b(\"(\") } else [c]",
+        "\"\\\\\"\n\t",
+    ];
+
+    #[test]
+    fn round_trip() {
+        for backend in Backend::ALL {
+            for code in CORPUS {
+                assert_eq!(
+                    decode(&backend.compile(code)).unwrap(),
+                    code,
+                    "{backend} {code:?}"
+                );
+                assert_eq!(
+                    decode(&backend.string(code)).unwrap(),
+                    code,
+                    "{backend} {code:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn escaped_strings() {
+        assert!(returns_escaped_string("return\"caf\\351\""));
+        assert!(returns_escaped_string("return\"\\ud83d\\ude00\\\"\""));
+        assert!(!returns_escaped_string("return\"abc\""));
+        assert!(!returns_escaped_string("return \"\\351\""));
+        assert!(!returns_escaped_string("return\"a\"+\"\\351\""));
+        assert!(!returns_escaped_string("return\"\\x41\""));
+    }
+
+    #[test]
+    fn returned_escapes() {
+        let code = "return\"\\u0041\"";
+        for backend in Backend::ALL {
+            assert_eq!(decode(&backend.compile(code)).unwrap(), code, "{backend}");
+        }
+    }
+
+    #[test]
+    fn too_long() {
+        let src = format!("a=\"ab\";{}a", "a=a+a;".repeat(40));
+        let err = decode(&src).unwrap_err();
+        assert_eq!(err.err_type, EvalErrorType::TooLong("string"));
+    }
+
+    #[test]
+    fn constants() {
+        assert_eq!(decode("+!![]").unwrap(), "1");
+        assert_eq!(decode("[][[]]\n").unwrap(), "undefined");
+        assert_eq!(decode("(![]+[])[+!![]]").unwrap(), "a");
+    }
+
+    #[test]
+    fn eval_in_parent_scope() {
+        let src = "[][\"flat\"][\"constructor\"](\"return eval\")()(\"alert(1)\")";
+        assert_eq!(decode(src).unwrap(), "alert(1)");
+    }
+
+    #[test]
+    fn errors() {
+        let err = decode("[][[]][[]]").unwrap_err();
+        assert_eq!(err.location, (0, 10).into());
+        assert_eq!(err.err_type, EvalErrorType::ReadOfUndefined(String::new()));
+        let err = decode("Function(\"return eval\")()(+[]+)").unwrap_err();
+        assert_eq!(err.location, (30, 0).into());
+        assert_eq!(err.err_type, EvalErrorType::UnexpectedEnd);
+        let err = decode("+[]]").unwrap_err();
+        assert_eq!(err.location, (3, 1).into());
+    }
+}
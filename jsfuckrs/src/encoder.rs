@@ -0,0 +1,672 @@
+use std::{fmt, str::FromStr};
+
+use crate::writer::collect;
+use miette::Diagnostic;
+use thiserror::Error;
+
+/// Common interface of the jsf*ck backends
+///
+/// # Examples
+/// ```
+/// use jsfuckrs::{eval::eval, Backend, JsFuckEncoder};
+/// for backend in Backend::ALL {
+///     assert_eq!(eval(&backend.number(0)).unwrap().to_string(), "0");
+/// }
+/// assert_eq!(Backend::Aemkei.number(0), "+[]");
+/// ```
+pub trait JsFuckEncoder {
+    /// Generates jsf*ck expression that will evaluate into the desired number
+    fn number(&self, n: usize) -> String;
+    /// Generates jsf*ck expression that will evaluate into the desired string
+    fn string(&self, s: &str) -> String {
+        collect(|out| self.string_to(out, s))
+    }
+    /// Compile a javascript expression into jsf*ck
+    fn compile(&self, code: &str) -> String {
+        collect(|out| self.compile_to(out, code))
+    }
+    /// Writes the expression [`string`](Self::string) generates into `out`, one character at a
+    /// time, see [`IoWriter`](crate::IoWriter) to write into an [`io::Write`](std::io::Write)
+    fn string_to(&self, out: &mut dyn fmt::Write, s: &str) -> fmt::Result;
+    /// Writes the output of [`compile`](Self::compile) into `out`, one character at a time
+    fn compile_to(&self, out: &mut dyn fmt::Write, code: &str) -> fmt::Result;
+    /// Whether `c` has a derivation of its own, instead of being built from its character code
+    fn is_mapped(&self, c: char) -> bool;
+
+    /// Generates jsf*ck expression that will evaluate into a value equal to `value`
+    ///
+    /// # Examples
+    /// ```
+    /// use jsfuckrs::{eval::eval, json::Value, Backend, JsFuckEncoder};
+    /// let value = Value::Array(vec![Value::Number(-1.5), Value::Bool(true)]);
+    /// let encoded = Backend::LbpStrict.encode_value(&value);
+    /// assert_eq!(eval(&encoded).unwrap().to_string(), "-1.5,true");
+    /// ```
+    fn encode_value(&self, value: &crate::json::Value) -> String {
+        crate::json::encode(self, value)
+    }
+
+    /// Generates jsf*ck expression that will evaluate into the `Function` constructor
+    fn function_constructor(&self) -> String {
+        format!(
+            "[][{}][{}]",
+            self.string("flat"),
+            self.string("constructor")
+        )
+    }
+
+    /// Compile javascript code into jsf*ck, running it the way `options` asks for
+    ///
+    /// # Examples
+    /// ```
+    /// use jsfuckrs::{decode, Backend, CompileOptions, JsFuckEncoder, Wrapper};
+    /// let options = CompileOptions {
+    ///     wrapper: Wrapper::Eval,
+    /// };
+    /// for backend in Backend::ALL {
+    ///     assert_eq!(decode(&backend.compile_with("alert(1)", &options)).unwrap(), "alert(1)");
+    /// }
+    /// ```
+    fn compile_with(&self, code: &str, options: &CompileOptions) -> String {
+        collect(|out| self.compile_with_to(out, code, options))
+    }
+
+    /// Writes the output of [`compile_with`](Self::compile_with) into `out`, one character at a
+    /// time
+    fn compile_with_to(
+        &self,
+        out: &mut dyn fmt::Write,
+        code: &str,
+        options: &CompileOptions,
+    ) -> fmt::Result {
+        let function = self.function_constructor();
+        let mut call_with = |name: &str| {
+            write!(out, "{function}(")?;
+            self.string_to(out, &format!("return {name}"))?;
+            out.write_str(")()(")?;
+            self.string_to(out, code)?;
+            out.write_char(')')
+        };
+        match &options.wrapper {
+            Wrapper::Function => self.compile_to(out, code),
+            Wrapper::Expression => {
+                write!(out, "{function}(")?;
+                // The newline keeps a trailing line comment from swallowing the parenthesis
+                self.string_to(out, &format!("return({code}\n)"))?;
+                out.write_str(")()")
+            }
+            Wrapper::Eval => call_with("eval"),
+            Wrapper::SetTimeout => call_with("setTimeout"),
+            Wrapper::Call(Parameters(arguments)) => {
+                // Calls can not be given several arguments without commas, so every argument gets
+                // a function of its own
+                let mut body = arguments
+                    .iter()
+                    .map(|(name, _)| format!("return function({name}){{"))
+                    .collect::<String>();
+                body.push_str(code);
+                if !arguments.is_empty() {
+                    // The newline keeps a trailing line comment from swallowing the braces
+                    body.push('\n');
+                    body.push_str(&"}".repeat(arguments.len()));
+                }
+                write!(out, "{function}(")?;
+                self.string_to(out, &body)?;
+                out.write_str(")()")?;
+                for (_, value) in arguments {
+                    write!(out, "({value})")?;
+                }
+                Ok(())
+            }
+            Wrapper::String => self.string_to(out, code),
+        }
+    }
+}
+
+/// How [`JsFuckEncoder::compile_with`] compiles code
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompileOptions {
+    /// What the code is run by
+    pub wrapper: Wrapper,
+}
+
+/// What compiled code is run by
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Wrapper {
+    /// The statements are the body of a function, `Function(code)()`, like
+    /// [`JsFuckEncoder::compile`]
+    #[default]
+    Function,
+    /// The code is an expression the output evaluates to, `Function("return(" + code + ")")()`
+    Expression,
+    /// The code is run in the global scope by `Function("return eval")()(code)`, evaluating to
+    /// its completion value
+    Eval,
+    /// The code is run once the current script is done by `Function("return setTimeout")()(code)`
+    ///
+    /// Only browsers accept a string of code, Node.js throws a `TypeError`.
+    SetTimeout,
+    /// The statements are the body of a function taking the named parameters, which is called
+    /// with the jsf*ck expressions next to them
+    ///
+    /// # Examples
+    /// ```
+    /// use jsfuckrs::{Backend, CompileOptions, JsFuckEncoder, Wrapper};
+    /// let backend = Backend::default();
+    /// let options = CompileOptions {
+    ///     wrapper: Wrapper::call(vec![
+    ///         ("a".to_owned(), backend.number(1)),
+    ///         ("b".to_owned(), backend.string("b")),
+    ///     ])
+    ///     .unwrap(),
+    /// };
+    /// let output = backend.compile_with("return a + b", &options);
+    /// ```
+    Call(Parameters),
+    /// Nothing runs the code, the output evaluates to it as a string, like
+    /// [`JsFuckEncoder::string`]
+    String,
+}
+
+impl Wrapper {
+    /// [`Wrapper::Call`], once every parameter is known to be named by an identifier
+    ///
+    /// # Errors
+    /// The first name that is not an identifier, or one of the words javascript reserves
+    ///
+    /// # Examples
+    /// ```
+    /// use jsfuckrs::Wrapper;
+    /// assert!(Wrapper::call(vec![("a".to_owned(), "+[]".to_owned())]).is_ok());
+    /// assert!(Wrapper::call(vec![("a){}//".to_owned(), "+[]".to_owned())]).is_err());
+    /// ```
+    pub fn call(arguments: Vec<(String, String)>) -> Result<Self, InvalidParameter> {
+        match arguments.iter().find(|(name, _)| !is_identifier(name)) {
+            Some((name, _)) => Err(InvalidParameter(name.clone())),
+            None => Ok(Self::Call(Parameters(arguments))),
+        }
+    }
+}
+
+/// The parameters of [`Wrapper::Call`] and their values, only made by [`Wrapper::call`] so that
+/// every name is an identifier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameters(Vec<(String, String)>);
+
+/// A parameter of [`Wrapper::Call`] that is not named by an identifier
+#[derive(Error, Diagnostic, Debug, Clone, PartialEq, Eq)]
+#[error("{0:?} can not be used as the name of a parameter")]
+#[diagnostic(help("use an identifier like `a` or `_arg` that is not a keyword"))]
+pub struct InvalidParameter(pub String);
+
+/// Words that cannot name a variable, or name a global it could not be assigned to
+const RESERVED: &[&str] = &[
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "let",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+    "arguments",
+    "eval",
+    "Infinity",
+    "NaN",
+    "undefined",
+];
+
+/// Whether `name` is an identifier that is not one of the [`RESERVED`] words
+pub(crate) fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+        && !RESERVED.contains(&name)
+}
+
+/// The six symbols jsf*ck is written in
+pub const ALPHABET: [char; 6] = ['[', ']', '(', ')', '!', '+'];
+
+/// Checks that `code` only uses the six symbols of [`ALPHABET`]
+///
+/// # Examples
+/// ```
+/// use jsfuckrs::is_jsfuck;
+/// assert!(is_jsfuck("(![]+[])[+[]]"));
+/// assert!(!is_jsfuck("+{}"));
+/// ```
+#[must_use]
+pub fn is_jsfuck(code: &str) -> bool {
+    code.chars().all(|c| ALPHABET.contains(&c))
+}
+
+/// The backends compiled into this crate, selectable at runtime
+///
+/// [`lbp`](crate::lbp) is the default whenever it is compiled in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(any(feature = "lbp", feature = "aemkei"), derive(Default))]
+pub enum Backend {
+    /// [`lbp`](crate::lbp)
+    #[cfg(feature = "lbp")]
+    #[default]
+    Lbp,
+    /// [`lbp::strict`](crate::lbp::strict)
+    #[cfg(feature = "lbp")]
+    LbpStrict,
+    /// [`aemkei`](crate::aemkei)
+    #[cfg(feature = "aemkei")]
+    #[cfg_attr(not(feature = "lbp"), default)]
+    Aemkei,
+    /// [`jjencode`](crate::jjencode), keeping its building blocks in `$`
+    #[cfg(feature = "jjencode")]
+    JjEncode,
+    /// [`aaencode`](crate::aaencode)
+    #[cfg(feature = "aaencode")]
+    AaEncode,
+}
+
+impl Backend {
+    /// Every backend compiled into this crate
+    pub const ALL: &'static [Self] = &[
+        #[cfg(feature = "lbp")]
+        Self::Lbp,
+        #[cfg(feature = "lbp")]
+        Self::LbpStrict,
+        #[cfg(feature = "aemkei")]
+        Self::Aemkei,
+        #[cfg(feature = "jjencode")]
+        Self::JjEncode,
+        #[cfg(feature = "aaencode")]
+        Self::AaEncode,
+    ];
+
+    /// The name used to select this backend
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "lbp")]
+            Self::Lbp => "lbp",
+            #[cfg(feature = "lbp")]
+            Self::LbpStrict => "lbp-strict",
+            #[cfg(feature = "aemkei")]
+            Self::Aemkei => "aemkei",
+            #[cfg(feature = "jjencode")]
+            Self::JjEncode => "jjencode",
+            #[cfg(feature = "aaencode")]
+            Self::AaEncode => "aaencode",
+        }
+    }
+
+    /// Whether the output only uses the six symbols `[]()!+`, see [`is_jsfuck`]
+    #[must_use]
+    pub const fn is_strict(self) -> bool {
+        match self {
+            #[cfg(feature = "lbp")]
+            Self::Lbp => false,
+            #[cfg(feature = "lbp")]
+            Self::LbpStrict => true,
+            #[cfg(feature = "aemkei")]
+            Self::Aemkei => true,
+            #[cfg(feature = "jjencode")]
+            Self::JjEncode => false,
+            #[cfg(feature = "aaencode")]
+            Self::AaEncode => false,
+        }
+    }
+
+    /// The encoder implementing this backend
+    #[must_use]
+    pub fn encoder(self) -> &'static dyn JsFuckEncoder {
+        match self {
+            #[cfg(feature = "lbp")]
+            Self::Lbp => &crate::lbp::Lbp {
+                strict: false,
+                hoist: false,
+                target: crate::lbp::Target::Generic,
+            },
+            #[cfg(feature = "lbp")]
+            Self::LbpStrict => &crate::lbp::Lbp {
+                strict: true,
+                hoist: false,
+                target: crate::lbp::Target::Generic,
+            },
+            #[cfg(feature = "aemkei")]
+            Self::Aemkei => &crate::aemkei::Aemkei,
+            #[cfg(feature = "jjencode")]
+            Self::JjEncode => {
+                static JJENCODE: std::sync::OnceLock<crate::jjencode::Jjencode> =
+                    std::sync::OnceLock::new();
+                JJENCODE.get_or_init(crate::jjencode::Jjencode::default)
+            }
+            #[cfg(feature = "aaencode")]
+            Self::AaEncode => &crate::aaencode::Aaencode,
+        }
+    }
+}
+
+impl JsFuckEncoder for Backend {
+    fn number(&self, n: usize) -> String {
+        self.encoder().number(n)
+    }
+
+    fn string(&self, s: &str) -> String {
+        self.encoder().string(s)
+    }
+
+    fn compile(&self, code: &str) -> String {
+        self.encoder().compile(code)
+    }
+
+    fn string_to(&self, out: &mut dyn fmt::Write, s: &str) -> fmt::Result {
+        self.encoder().string_to(out, s)
+    }
+
+    fn compile_to(&self, out: &mut dyn fmt::Write, code: &str) -> fmt::Result {
+        self.encoder().compile_to(out, code)
+    }
+
+    fn is_mapped(&self, c: char) -> bool {
+        self.encoder().is_mapped(c)
+    }
+
+    fn function_constructor(&self) -> String {
+        self.encoder().function_constructor()
+    }
+
+    fn compile_with(&self, code: &str, options: &CompileOptions) -> String {
+        self.encoder().compile_with(code, options)
+    }
+
+    fn compile_with_to(
+        &self,
+        out: &mut dyn fmt::Write,
+        code: &str,
+        options: &CompileOptions,
+    ) -> fmt::Result {
+        self.encoder().compile_with_to(out, code, options)
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("Unknown backend {0:?}")]
+pub struct UnknownBackend(pub String);
+
+impl FromStr for Backend {
+    type Err = UnknownBackend;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|backend| backend.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| UnknownBackend(s.to_owned()))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        for &backend in Backend::ALL {
+            assert_eq!(backend.to_string().parse(), Ok(backend));
+        }
+        assert_eq!(
+            "brainfuck".parse::<Backend>(),
+            Err(UnknownBackend("brainfuck".to_owned()))
+        );
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "lbp",
+        feature = "aemkei",
+        feature = "jjencode",
+        feature = "aaencode"
+    ))]
+    fn every_backend() {
+        assert_eq!(
+            Backend::ALL,
+            [
+                Backend::Lbp,
+                Backend::LbpStrict,
+                Backend::Aemkei,
+                Backend::JjEncode,
+                Backend::AaEncode
+            ]
+        );
+        assert_eq!(Backend::default(), Backend::Lbp);
+        assert_eq!(
+            Backend::Lbp.compile("alert(1);"),
+            crate::lbp::compile("alert(1);")
+        );
+        assert_eq!(
+            Backend::Aemkei.compile("alert(1);"),
+            crate::aemkei::compile("alert(1);")
+        );
+        assert_eq!(
+            Backend::LbpStrict.compile("alert(1);"),
+            crate::lbp::strict::compile("alert(1);")
+        );
+        assert_eq!(
+            Backend::JjEncode.compile("alert(1);"),
+            crate::jjencode::compile("alert(1);")
+        );
+        assert_eq!(
+            Backend::AaEncode.compile("alert(1);"),
+            crate::aaencode::compile("alert(1);")
+        );
+        assert_ne!(Backend::Lbp.string("abc"), Backend::Aemkei.string("abc"));
+        let strict = Backend::ALL.iter().filter(|backend| backend.is_strict());
+        assert_eq!(
+            strict.collect::<Vec<_>>(),
+            [&Backend::LbpStrict, &Backend::Aemkei]
+        );
+    }
+
+    /// Remembers the longest single write
+    #[derive(Default)]
+    struct Chunks {
+        len: usize,
+        longest: usize,
+    }
+
+    impl fmt::Write for Chunks {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.len += s.len();
+            self.longest = self.longest.max(s.len());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn streaming() {
+        let code = format!(
+            "alert(\"{}{}\")",
+            "jsf*ck ".repeat(200),
+            "caf\u{e9} \u{1f600}".repeat(200)
+        );
+        for &backend in Backend::ALL {
+            let mut out = String::new();
+            backend.compile_to(&mut out, &code).unwrap();
+            assert_eq!(out, backend.compile(&code), "{backend}");
+            let mut chunks = Chunks::default();
+            backend.compile_to(&mut chunks, &code).unwrap();
+            assert_eq!(chunks.len, out.len());
+            assert!(chunks.longest * 20 < chunks.len, "{backend}");
+            for wrapper in [Wrapper::Expression, Wrapper::Eval, Wrapper::String] {
+                let options = CompileOptions { wrapper };
+                let mut out = String::new();
+                backend.compile_with_to(&mut out, &code, &options).unwrap();
+                assert_eq!(out, backend.compile_with(&code, &options), "{backend}");
+            }
+        }
+    }
+
+    mod wrappers {
+        use super::*;
+        use crate::eval::{eval, EvalErrorType, Evaluator, Value};
+
+        fn compile_with(backend: Backend, code: &str, wrapper: Wrapper) -> String {
+            let output = backend.compile_with(code, &CompileOptions { wrapper });
+            assert!(!backend.is_strict() || is_jsfuck(&output), "{backend}");
+            output
+        }
+
+        #[test]
+        fn function() {
+            for &backend in Backend::ALL {
+                assert_eq!(
+                    compile_with(backend, "alert(1)", Wrapper::Function),
+                    backend.compile("alert(1)")
+                );
+                assert_eq!(
+                    eval(&format!(
+                        "{}[{}]",
+                        backend.function_constructor(),
+                        backend.string("name")
+                    ))
+                    .unwrap()
+                    .to_string(),
+                    "Function"
+                );
+            }
+        }
+
+        #[test]
+        fn expression() {
+            for &backend in Backend::ALL {
+                let output = compile_with(backend, "[1]+1\n", Wrapper::Expression);
+                assert_eq!(eval(&output).unwrap().to_string(), "11", "{backend}");
+            }
+        }
+
+        #[test]
+        fn eval_in_global_scope() {
+            for &backend in Backend::ALL {
+                let mut evaluator = Evaluator::default();
+                let output = compile_with(backend, "2+[]", Wrapper::Eval);
+                assert_eq!(evaluator.eval(&output).unwrap().to_string(), "2");
+                assert_eq!(evaluator.code().last().unwrap(), "2+[]", "{backend}");
+            }
+        }
+
+        #[test]
+        fn set_timeout() {
+            for &backend in Backend::ALL {
+                let mut evaluator = Evaluator::default();
+                let output = compile_with(backend, "alert(1)", Wrapper::SetTimeout);
+                // The evaluator has no event loop to hand the code to
+                assert_eq!(
+                    evaluator.eval(&output).unwrap_err().err_type,
+                    EvalErrorType::NotDefined("setTimeout".to_owned())
+                );
+                assert_eq!(
+                    evaluator.code().last().unwrap(),
+                    "return setTimeout",
+                    "{backend}"
+                );
+            }
+        }
+
+        #[test]
+        fn call() {
+            for &backend in Backend::ALL {
+                let arguments = vec![
+                    ("a".to_owned(), backend.number(1)),
+                    ("b".to_owned(), backend.string("b")),
+                ];
+                let mut evaluator = Evaluator::default();
+                let wrapper = Wrapper::call(arguments).unwrap();
+                let output = compile_with(backend, "return a+b", wrapper.clone());
+                assert_eq!(evaluator.eval(&output).unwrap().to_string(), "1b");
+                assert_eq!(
+                    evaluator.code().last().unwrap(),
+                    "return function(a){return function(b){return a+b\n}}",
+                    "{backend}"
+                );
+                let output = compile_with(backend, "return a+b//", wrapper);
+                assert_eq!(eval(&output).unwrap().to_string(), "1b", "{backend}");
+                assert_eq!(
+                    compile_with(backend, "alert(1)", Wrapper::call(Vec::new()).unwrap()),
+                    format!(
+                        "{}({})()",
+                        backend.function_constructor(),
+                        backend.string("alert(1)")
+                    )
+                );
+            }
+        }
+
+        #[test]
+        fn call_parameters() {
+            let arguments = |name: &str| vec![(name.to_owned(), "+[]".to_owned())];
+            for name in ["a", "_", "$0", "été"] {
+                assert!(Wrapper::call(arguments(name)).is_ok(), "{name:?}");
+            }
+            for name in ["", "0a", "a b", "a){}//", "return", "undefined"] {
+                assert_eq!(
+                    Wrapper::call(arguments(name)),
+                    Err(InvalidParameter(name.to_owned()))
+                );
+            }
+        }
+
+        #[test]
+        fn string() {
+            for &backend in Backend::ALL {
+                let output = compile_with(backend, "alert(1)", Wrapper::String);
+                assert_eq!(output, backend.string("alert(1)"));
+                assert!(matches!(eval(&output).unwrap(), Value::String(_)));
+            }
+        }
+    }
+}
@@ -0,0 +1,2061 @@
+//! A small javascript evaluator covering the subset jsf*ck is written in
+//!
+//! Besides the six symbols it understands the syntax the loose [`lbp`](crate::lbp) encoding
+//! relies on: `{}`, `()=>{}`, regular expression literals, `*` and `/`, along with the object
+//! literals and `null` of [encoded values](crate::json), and the functions taking parameters, like
+//! `a=>a`, that [hoisted](crate::lbp::building_blocks::compile_hoisted) programs are built
+//! around. The scripts of [`jjencode`] and [`aaencode`] add statements separated by `;`, comments,
+//! global variables, assignments, `.` property access, `++`, `~`, `^` and `==`. Code given to
+//! `Function` or `eval` is evaluated as well when it is a single expression, like
+//! `return escape`, and is recorded either way so the payload of a compiled program can be
+//! inspected without running it.
+//!
+//! [`jjencode`]: https://utf-8.jp/public/jjencode.html
+//! [`aaencode`]: https://utf-8.jp/public/aaencode.html
+//!
+//! # Examples
+//! ```
+//! use jsfuckrs::eval::{eval, Evaluator};
+//! assert_eq!(eval("(![]+[])[+!![]]").unwrap().to_string(), "a");
+//!
+//! let mut evaluator = Evaluator::default();
+//! assert!(evaluator.eval("[][\"flat\"][\"constructor\"](\"alert(1)\")()").is_err());
+//! assert_eq!(evaluator.code(), ["alert(1)"]);
+//! ```
+use std::{collections::HashMap, fmt, ops::Range};
+
+use crate::iter_utils::IteratorUtils;
+use miette::{Diagnostic, SourceSpan};
+use thiserror::Error;
+
+/// Nesting beyond this is reported instead of overflowing the stack
+const MAX_DEPTH: usize = 128;
+
+/// Longer strings and arrays are reported, like javascript's `RangeError`, instead of exhausting memory
+const MAX_LENGTH: usize = 1 << 24;
+
+/// What `Date()` evaluates to, so results do not depend on the current time
+const DATE: &str = "Thu Jan 01 1970 00:00:00 GMT+0000 (Coordinated Universal Time)";
+
+#[derive(Error, Diagnostic, Debug)]
+#[error("Could not evaluate the expression")]
+pub struct EvalError {
+    #[source_code]
+    pub src: String,
+    #[label("{err_type}")]
+    pub location: SourceSpan,
+    #[diagnostic_source]
+    pub err_type: EvalErrorType,
+}
+
+#[derive(Error, Diagnostic, Debug, Clone, PartialEq, Eq)]
+pub enum EvalErrorType {
+    #[error("Unexpected end of input")]
+    UnexpectedEnd,
+    #[error("Unexpected character {0:?}")]
+    UnexpectedChar(char),
+    #[error("{0} is not defined")]
+    NotDefined(String),
+    #[error("Value is not a function")]
+    NotAFunction,
+    #[error("Cannot read property {0:?} of undefined")]
+    ReadOfUndefined(String),
+    #[error("Cannot read property {0:?} of null")]
+    ReadOfNull(String),
+    #[error("Expression is nested too deeply")]
+    TooDeep,
+    #[error("Invalid {0} length")]
+    TooLong(&'static str),
+    #[error("Unsupported {0}")]
+    Unsupported(String),
+}
+
+/// A javascript string: UTF-16 code units that do not need to form valid UTF-16
+pub type JsString = Vec<u16>;
+
+/// The values the evaluator knows about
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Undefined,
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(JsString),
+    Array(Vec<Value>),
+    Object(Object),
+    Function(Function),
+    /// The source of a regular expression
+    RegExp(JsString),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+    /// `{}` along with its own properties, in the order they were added
+    Plain(Vec<(JsString, Value)>),
+    /// `[]["entries"]()`
+    ArrayIterator,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Function {
+    Builtin(Builtin),
+    /// A method along with the value it was read from
+    Method(Method, Box<Value>),
+    /// A function created by the `Function` constructor
+    Dynamic {
+        params: Vec<String>,
+        body: String,
+    },
+    /// `()=>{}`
+    Arrow,
+    /// A function expression taking parameters, like `a=>a` or `function(a){return a}`
+    Literal(Box<Literal>),
+}
+
+/// A function expression, boxed to keep [`Value`] small for the deeply nested expressions
+#[derive(Debug, Clone, PartialEq)]
+pub struct Literal {
+    pub params: Vec<String>,
+    pub body: String,
+    pub source: String,
+    /// The parameters of the function it was created in
+    pub closure: HashMap<String, Value>,
+}
+
+/// Global functions and constructors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    Array,
+    Boolean,
+    Date,
+    Function,
+    Number,
+    Object,
+    RegExp,
+    String,
+    Escape,
+    Unescape,
+    Eval,
+    FromCharCode,
+    FromCodePoint,
+}
+
+/// Methods of the built-in prototypes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Call,
+    Concat,
+    Entries,
+    Filter,
+    Find,
+    Flat,
+    Fontcolor,
+    Italics,
+    Join,
+    Slice,
+    Split,
+    ToString,
+    /// `Object.prototype.toString`
+    ObjectToString,
+}
+
+impl Builtin {
+    const GLOBALS: [Self; 11] = [
+        Self::Array,
+        Self::Boolean,
+        Self::Date,
+        Self::Function,
+        Self::Number,
+        Self::Object,
+        Self::RegExp,
+        Self::String,
+        Self::Escape,
+        Self::Unescape,
+        Self::Eval,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Array => "Array",
+            Self::Boolean => "Boolean",
+            Self::Date => "Date",
+            Self::Function => "Function",
+            Self::Number => "Number",
+            Self::Object => "Object",
+            Self::RegExp => "RegExp",
+            Self::String => "String",
+            Self::Escape => "escape",
+            Self::Unescape => "unescape",
+            Self::Eval => "eval",
+            Self::FromCharCode => "fromCharCode",
+            Self::FromCodePoint => "fromCodePoint",
+        }
+    }
+}
+
+impl Method {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Call => "call",
+            Self::Concat => "concat",
+            Self::Entries => "entries",
+            Self::Filter => "filter",
+            Self::Find => "find",
+            Self::Flat => "flat",
+            Self::Fontcolor => "fontcolor",
+            Self::Italics => "italics",
+            Self::Join => "join",
+            Self::Slice => "slice",
+            Self::Split => "split",
+            Self::ToString | Self::ObjectToString => "toString",
+        }
+    }
+
+    /// The method called `name` on the prototype of `value`
+    fn lookup(value: &Value, name: &str) -> Option<Self> {
+        let methods: &[Self] = match value {
+            Value::Undefined | Value::Null => &[],
+            Value::String(_) => &[
+                Self::Concat,
+                Self::Fontcolor,
+                Self::Italics,
+                Self::Slice,
+                Self::Split,
+                Self::ToString,
+            ],
+            Value::Array(_) => &[
+                Self::Concat,
+                Self::Entries,
+                Self::Filter,
+                Self::Find,
+                Self::Flat,
+                Self::Join,
+                Self::Slice,
+                Self::ToString,
+            ],
+            Value::Object(_) => &[Self::ObjectToString],
+            Value::Function(_) => &[Self::Call, Self::ToString],
+            Value::Bool(_) | Value::Number(_) | Value::RegExp(_) => &[Self::ToString],
+        };
+        methods.iter().copied().find(|method| method.name() == name)
+    }
+}
+
+impl Function {
+    fn name(&self) -> &str {
+        match self {
+            Self::Builtin(builtin) => builtin.name(),
+            Self::Method(method, _) => method.name(),
+            Self::Dynamic { .. } => "anonymous",
+            Self::Arrow | Self::Literal(_) => "",
+        }
+    }
+}
+
+impl Value {
+    fn string(s: &str) -> Self {
+        Self::String(s.encode_utf16().collect())
+    }
+
+    /// The result of javascript's `String(value)`
+    #[must_use]
+    pub fn to_js_string(&self) -> JsString {
+        match self {
+            Self::String(s) => s.clone(),
+            Self::Array(values) => {
+                let mut s = vec![];
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        s.push(u16::from(b','));
+                    }
+                    if !value.is_nullish() {
+                        s.extend(value.to_js_string());
+                    }
+                }
+                s
+            }
+            Self::RegExp(source) => {
+                let mut s = vec![u16::from(b'/')];
+                s.extend(source);
+                s.push(u16::from(b'/'));
+                s
+            }
+            value => value.to_string().encode_utf16().collect(),
+        }
+    }
+
+    /// The result of javascript's `Number(value)`
+    #[must_use]
+    pub fn to_number(&self) -> f64 {
+        match self {
+            Self::Undefined => f64::NAN,
+            Self::Null => 0.0,
+            Self::Bool(b) => f64::from(u8::from(*b)),
+            Self::Number(n) => *n,
+            value => string_to_number(&String::from_utf16_lossy(&value.to_js_string())),
+        }
+    }
+
+    /// The result of javascript's `Boolean(value)`
+    #[must_use]
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Self::Undefined | Self::Null => false,
+            Self::Bool(b) => *b,
+            Self::Number(n) => *n != 0.0 && !n.is_nan(),
+            Self::String(s) => !s.is_empty(),
+            _ => true,
+        }
+    }
+
+    const fn is_primitive(&self) -> bool {
+        matches!(
+            self,
+            Self::Undefined | Self::Null | Self::Bool(_) | Self::Number(_) | Self::String(_)
+        )
+    }
+
+    /// The length of [`to_js_string`](Self::to_js_string), without building it
+    fn js_string_len(&self) -> usize {
+        match self {
+            Self::String(s) => s.len(),
+            Self::Array(values) => {
+                values
+                    .iter()
+                    .fold(values.len().saturating_sub(1), |len, value| {
+                        len.saturating_add(if value.is_nullish() {
+                            0
+                        } else {
+                            value.js_string_len()
+                        })
+                    })
+            }
+            Self::RegExp(source) => source.len() + 2,
+            value => value.to_js_string().len(),
+        }
+    }
+
+    const fn is_nullish(&self) -> bool {
+        matches!(self, Self::Undefined | Self::Null)
+    }
+
+    const fn constructor(&self) -> Builtin {
+        match self {
+            Self::Undefined | Self::Null | Self::Object(_) => Builtin::Object,
+            Self::Bool(_) => Builtin::Boolean,
+            Self::Number(_) => Builtin::Number,
+            Self::String(_) => Builtin::String,
+            Self::Array(_) => Builtin::Array,
+            Self::Function(_) => Builtin::Function,
+            Self::RegExp(_) => Builtin::RegExp,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Undefined => f.write_str("undefined"),
+            Self::Null => f.write_str("null"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Number(n) => f.write_str(&number_to_string(*n)),
+            Self::Object(Object::Plain(_)) => f.write_str("[object Object]"),
+            Self::Object(Object::ArrayIterator) => f.write_str("[object Array Iterator]"),
+            Self::Function(Function::Arrow) => f.write_str("()=>{}"),
+            Self::Function(Function::Literal(literal)) => f.write_str(&literal.source),
+            Self::Function(Function::Dynamic { params, body }) => {
+                write!(
+                    f,
+                    "function anonymous({}\n) {{\n{body}\n}}",
+                    params.join(",")
+                )
+            }
+            Self::Function(function) => {
+                write!(f, "function {}() {{ [native code] }}", function.name())
+            }
+            value => f.write_str(&String::from_utf16_lossy(&value.to_js_string())),
+        }
+    }
+}
+
+/// The result of javascript's `+` on two values
+fn add(lhs: Value, rhs: Value) -> Result<Value, EvalErrorType> {
+    if lhs.is_primitive()
+        && rhs.is_primitive()
+        && !matches!(lhs, Value::String(_))
+        && !matches!(rhs, Value::String(_))
+    {
+        Ok(Value::Number(lhs.to_number() + rhs.to_number()))
+    } else {
+        check_length(
+            lhs.js_string_len().saturating_add(rhs.js_string_len()),
+            "string",
+        )?;
+        let mut s = lhs.to_js_string();
+        s.extend(rhs.to_js_string());
+        Ok(Value::String(s))
+    }
+}
+
+/// Fails for a string or an array of more than [`MAX_LENGTH`] elements
+const fn check_length(len: usize, kind: &'static str) -> Result<(), EvalErrorType> {
+    if len > MAX_LENGTH {
+        Err(EvalErrorType::TooLong(kind))
+    } else {
+        Ok(())
+    }
+}
+
+/// `ToInt32`, the conversion behind the bitwise operators
+fn to_int32(value: &Value) -> i32 {
+    let n = value.to_number();
+    if n.is_finite() {
+        n.trunc().rem_euclid(4_294_967_296.0) as u32 as i32
+    } else {
+        0
+    }
+}
+
+/// Javascript's `===`, where objects are never identical as the evaluator copies them
+fn strict_equals(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Function(Function::Builtin(a)), Value::Function(Function::Builtin(b))) => a == b,
+        (Value::Function(Function::Method(a, _)), Value::Function(Function::Method(b, _))) => {
+            a == b
+        }
+        _ => a.is_primitive() && a == b,
+    }
+}
+
+/// Javascript's `==`
+fn loose_equals(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        _ if a.is_nullish() || b.is_nullish() => a.is_nullish() && b.is_nullish(),
+        (Value::String(a), Value::String(b)) => a == b,
+        _ if a.is_primitive() && b.is_primitive() => a.to_number() == b.to_number(),
+        (Value::String(s), object) | (object, Value::String(s)) => *s == object.to_js_string(),
+        _ if a.is_primitive() || b.is_primitive() => a.to_number() == b.to_number(),
+        _ => strict_equals(a, b),
+    }
+}
+
+/// Formats a number the way javascript's `Number.prototype.toString` does
+pub(crate) fn number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        return "NaN".to_owned();
+    }
+    if n == 0.0 {
+        return "0".to_owned();
+    }
+    if n < 0.0 {
+        return format!("-{}", number_to_string(-n));
+    }
+    if n.is_infinite() {
+        return "Infinity".to_owned();
+    }
+    // Rust already finds the shortest digits that round trip
+    let scientific = format!("{n:e}");
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let digits = mantissa.replace('.', "");
+    let k = digits.len() as i64;
+    let n = exponent.parse::<i64>().unwrap_or_default() + 1;
+    if k <= n && n <= 21 {
+        format!("{digits}{}", "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        let (int, frac) = digits.split_at(n as usize);
+        format!("{int}.{frac}")
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{digits}", "0".repeat((-n) as usize))
+    } else {
+        let (first, rest) = digits.split_at(1);
+        let rest = if rest.is_empty() {
+            String::new()
+        } else {
+            format!(".{rest}")
+        };
+        let sign = if n > 0 { '+' } else { '-' };
+        format!("{first}{rest}e{sign}{}", (n - 1).abs())
+    }
+}
+
+/// Parses a number the way javascript's `Number(string)` does
+fn string_to_number(s: &str) -> f64 {
+    let s = s.trim();
+    match s {
+        "" => return 0.0,
+        "Infinity" | "+Infinity" => return f64::INFINITY,
+        "-Infinity" => return f64::NEG_INFINITY,
+        _ => {}
+    }
+    let radix = match s.get(..2) {
+        Some("0x" | "0X") => 16,
+        Some("0o" | "0O") => 8,
+        Some("0b" | "0B") => 2,
+        _ => 10,
+    };
+    if radix != 10 {
+        return u64::from_str_radix(&s[2..], radix).map_or(f64::NAN, |n| n as f64);
+    }
+    if s.bytes().any(|b| b.is_ascii_digit())
+        && s.bytes()
+            .all(|b| b.is_ascii_digit() || b"+-.eE".contains(&b))
+    {
+        s.parse().unwrap_or(f64::NAN)
+    } else {
+        f64::NAN
+    }
+}
+
+/// Formats an integer in the given radix, like `(255)["toString"](16)`
+fn integer_to_radix(n: f64, radix: u32) -> Option<String> {
+    if n.fract() != 0.0 || n.abs() >= 2f64.powi(53) {
+        return None;
+    }
+    let mut rest = n.abs() as u64;
+    let mut digits = vec![];
+    loop {
+        digits.push(char::from_digit((rest % u64::from(radix)) as u32, radix)?);
+        rest /= u64::from(radix);
+        if rest == 0 {
+            break;
+        }
+    }
+    if n < 0.0 {
+        digits.push('-');
+    }
+    Some(digits.iter().rev().collect())
+}
+
+/// `ToIntegerOrInfinity` clamped into the range of a slice of length `len`
+fn relative_index(value: &Value, len: usize, default: usize) -> usize {
+    if *value == Value::Undefined {
+        return default;
+    }
+    let n = value.to_number();
+    let n = if n.is_nan() { 0.0 } else { n.trunc() };
+    if n < 0.0 {
+        len.saturating_sub((-n).min(len as f64) as usize)
+    } else {
+        n.min(len as f64) as usize
+    }
+}
+
+fn escape(s: &[u16]) -> JsString {
+    let mut out = String::new();
+    for &unit in s {
+        match u8::try_from(unit) {
+            Ok(b) if b.is_ascii_alphanumeric() || b"@*_+-./".contains(&b) => {
+                out.push(char::from(b));
+            }
+            Ok(b) => out += &format!("%{b:02X}"),
+            Err(_) => out += &format!("%u{unit:04X}"),
+        }
+    }
+    out.encode_utf16().collect()
+}
+
+fn unescape(s: &[u16]) -> JsString {
+    let hex = |units: &[u16]| {
+        let digits = String::from_utf16(units).ok()?;
+        if digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            u16::from_str_radix(&digits, 16).ok()
+        } else {
+            None
+        }
+    };
+    let mut out = vec![];
+    let mut i = 0;
+    while i < s.len() {
+        if s[i] == u16::from(b'%') {
+            if s.get(i + 1) == Some(&u16::from(b'u')) {
+                if let Some(unit) = s.get(i + 2..i + 6).and_then(hex) {
+                    out.push(unit);
+                    i += 6;
+                    continue;
+                }
+            } else if let Some(unit) = s.get(i + 1..i + 3).and_then(hex) {
+                out.push(unit);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(s[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Evaluates a javascript expression, or statements separated by `;` to the value of the last one
+///
+/// Use an [`Evaluator`] to inspect the code the expression handed to `Function` or `eval`.
+pub fn eval(src: &str) -> Result<Value, EvalError> {
+    Evaluator::default().eval(src)
+}
+
+/// Evaluates javascript expressions while recording the code they generate
+///
+/// Global variables assigned by one call of [`eval`](Self::eval) are visible to the next.
+#[derive(Debug, Default)]
+pub struct Evaluator {
+    code: Vec<String>,
+    globals: HashMap<String, Value>,
+}
+
+impl Evaluator {
+    /// Evaluates a javascript expression, or statements separated by `;`
+    pub fn eval(&mut self, src: &str) -> Result<Value, EvalError> {
+        let mut parser = Parser {
+            evaluator: self,
+            src,
+            pos: 0,
+            depth: 0,
+            scope: HashMap::new(),
+            member: false,
+        };
+        parser
+            .script()
+            .map_err(|Fail { err_type, span }| EvalError {
+                src: src.to_owned(),
+                location: (span.start, span.len()).into(),
+                err_type,
+            })
+    }
+
+    /// The code given to `Function` and `eval` so far, in order
+    #[must_use]
+    pub fn code(&self) -> &[String] {
+        &self.code
+    }
+}
+
+struct Fail {
+    err_type: EvalErrorType,
+    span: Range<usize>,
+}
+
+type EvalResult<T> = Result<T, Fail>;
+
+/// A variable, or a property of one, that can be assigned to
+struct Reference {
+    name: String,
+    key: Option<String>,
+    span: Range<usize>,
+}
+
+struct Parser<'a> {
+    evaluator: &'a mut Evaluator,
+    src: &'a str,
+    pos: usize,
+    depth: usize,
+    /// The parameters of the function being evaluated
+    scope: HashMap<String, Value>,
+    /// Whether the last postfix expression ended with a property access
+    member: bool,
+}
+
+impl Parser<'_> {
+    fn fail<T>(&self, err_type: EvalErrorType, span: Range<usize>) -> EvalResult<T> {
+        Err(Fail { err_type, span })
+    }
+
+    fn unexpected<T>(&self) -> EvalResult<T> {
+        match self.src[self.pos..].chars().next() {
+            Some(c) => self.fail(
+                EvalErrorType::UnexpectedChar(c),
+                self.pos..self.pos + c.len_utf8(),
+            ),
+            None => self.fail(EvalErrorType::UnexpectedEnd, self.pos..self.pos),
+        }
+    }
+
+    /// Skips whitespace along with `//` and `/* */` comments
+    fn skip_whitespace(&mut self) {
+        loop {
+            let rest = &self.src[self.pos..];
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            let comment = if trimmed.starts_with("//") {
+                trimmed.find('\n').unwrap_or(trimmed.len())
+            } else if let Some(comment) = trimmed.strip_prefix("/*") {
+                match comment.find("*/") {
+                    Some(end) => end + 4,
+                    None => return,
+                }
+            } else {
+                return;
+            };
+            self.pos += comment;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.src.as_bytes().get(self.pos).copied()
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.src[self.pos..].starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, token: &str) -> EvalResult<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            self.unexpected()
+        }
+    }
+
+    fn end(&mut self) -> EvalResult<()> {
+        if self.pos == self.src.len() {
+            Ok(())
+        } else {
+            self.unexpected()
+        }
+    }
+
+    /// Consumes an operator, rejecting the `++` and `--` that javascript would parse instead
+    fn operator(&mut self, operators: &[u8]) -> Option<u8> {
+        let op = self.peek().filter(|op| operators.contains(op))?;
+        let doubled = self.src.as_bytes().get(self.pos + 1) == Some(&op);
+        if doubled && b"+-".contains(&op) {
+            return None;
+        }
+        self.pos += 1;
+        Some(op)
+    }
+
+    /// The span of the identifier at the current position, if any
+    fn identifier_name(&mut self) -> Option<Range<usize>> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let rest = &self.src[start..];
+        if !rest.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '$') {
+            return None;
+        }
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+            .unwrap_or(rest.len());
+        self.pos += len;
+        Some(start..self.pos)
+    }
+
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> EvalResult<T>) -> EvalResult<T> {
+        if self.depth == MAX_DEPTH {
+            return self.fail(EvalErrorType::TooDeep, self.pos..self.pos);
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// A function body or the argument of `eval`: a single expression or `return` statement
+    fn program(&mut self) -> EvalResult<Value> {
+        self.skip_whitespace();
+        let rest = &self.src[self.pos..];
+        let is_return = rest.starts_with("return")
+            && !rest[6..]
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+        if is_return {
+            self.pos += 6;
+        }
+        let value = if is_return && matches!(self.peek(), None | Some(b';')) {
+            Value::Undefined
+        } else {
+            self.expression()?
+        };
+        self.eat(";");
+        self.skip_whitespace();
+        self.end()?;
+        Ok(value)
+    }
+
+    /// Statements separated by `;`, evaluating to the value of the last one like `eval` does
+    fn script(&mut self) -> EvalResult<Value> {
+        let mut value = Value::Undefined;
+        loop {
+            while self.eat(";") {}
+            if self.peek().is_none() {
+                return Ok(value);
+            }
+            value = self.expression()?;
+            if !self.eat(";") {
+                self.skip_whitespace();
+                self.end()?;
+                return Ok(value);
+            }
+        }
+    }
+
+    /// An expression, which may assign its value to a variable or property
+    fn expression(&mut self) -> EvalResult<Value> {
+        let start = self.pos;
+        if self.eat("(") {
+            if self.eat(")") {
+                self.expect("=>")?;
+                self.expect("{")?;
+                self.expect("}")?;
+                return Ok(Value::Function(Function::Arrow));
+            }
+            self.pos = start;
+        }
+        if let Some(function) = self.function_literal()? {
+            return Ok(Value::Function(function));
+        }
+        self.pos = start;
+        // The target is found before evaluating anything, as it may turn out not to be one
+        let is_assignment = self.skim_reference()
+            && (self.eat("+=") || (self.eat("=") && !self.src[self.pos..].starts_with(['=', '>'])));
+        self.pos = start;
+        if is_assignment {
+            self.assign()
+        } else {
+            self.bitwise()
+        }
+    }
+
+    /// `a=>…` or `function(a){…}`, whose body is only evaluated once the function is called
+    #[inline(never)]
+    fn function_literal(&mut self) -> EvalResult<Option<Function>> {
+        let start = self.pos;
+        let (params, body) = if self.eat("function") && self.eat("(") {
+            let mut params = vec![];
+            while !self.eat(")") {
+                if !params.is_empty() {
+                    self.expect(",")?;
+                }
+                match self.identifier_name() {
+                    Some(param) => params.push(self.src[param].to_owned()),
+                    None => return self.unexpected(),
+                }
+            }
+            self.expect("{")?;
+            let body = self.skim_balanced();
+            self.expect("}")?;
+            (params, body)
+        } else {
+            self.pos = start;
+            match self.identifier_name() {
+                Some(param) if self.eat("=>") => {
+                    (vec![self.src[param].to_owned()], self.skim_balanced())
+                }
+                _ => return Ok(None),
+            }
+        };
+        Ok(Some(Function::Literal(Box::new(Literal {
+            params,
+            body: self.src[body].to_owned(),
+            source: self.src[start..self.pos].trim().to_owned(),
+            closure: self.scope.clone(),
+        }))))
+    }
+
+    /// Skips to the first `,`, `;` or closing bracket that was not opened after the current
+    /// position, returning the span skipped
+    fn skim_balanced(&mut self) -> Range<usize> {
+        let start = self.pos;
+        let mut depth = 0;
+        let mut quote = None;
+        let mut escaped = false;
+        for (offset, c) in self.src[start..].char_indices() {
+            match (quote, c) {
+                (Some(_), _) if escaped => escaped = false,
+                (Some(_), '\\') => escaped = true,
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '(' | '[' | '{') => depth += 1,
+                (None, ')' | ']' | '}' | ',' | ';') if depth == 0 => {
+                    self.pos = start + offset;
+                    return start..self.pos;
+                }
+                (None, ')' | ']' | '}') => depth -= 1,
+                _ => {}
+            }
+        }
+        self.pos = self.src.len();
+        start..self.pos
+    }
+
+    // Kept out of `expression` so deeply nested expressions do not overflow the stack
+    #[inline(never)]
+    fn assign(&mut self) -> EvalResult<Value> {
+        let start = self.pos;
+        let reference = self.reference()?;
+        let compound = self.eat("+=");
+        if !compound {
+            self.expect("=")?;
+        }
+        let mut value = self.nested(Self::expression)?;
+        if compound {
+            let lhs = self.read(&reference)?;
+            value = add(lhs, value).or_else(|err_type| self.fail(err_type, start..self.pos))?;
+        }
+        self.write(&reference, value.clone())?;
+        Ok(value)
+    }
+
+    /// Skips over what [`reference`](Self::reference) would parse, telling whether it could
+    fn skim_reference(&mut self) -> bool {
+        let mut parens = 0;
+        while self.eat("(") {
+            parens += 1;
+        }
+        if self.identifier_name().is_none() || !(0..parens).all(|_| self.eat(")")) {
+            return false;
+        }
+        if self.eat(".") {
+            return self.identifier_name().is_some();
+        }
+        if !self.eat("[") {
+            return true;
+        }
+        let mut depth = 1;
+        let mut quote = None;
+        let mut escaped = false;
+        for (offset, c) in self.src[self.pos..].char_indices() {
+            match (quote, c) {
+                (Some(_), _) if escaped => escaped = false,
+                (Some(_), '\\') => escaped = true,
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '[') => depth += 1,
+                (None, ']') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.pos += offset + 1;
+                        return true;
+                    }
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// A variable, possibly parenthesized, and one property of it read with `.` or `[]`
+    fn reference(&mut self) -> EvalResult<Reference> {
+        let start = self.pos;
+        let mut parens = 0;
+        while self.eat("(") {
+            parens += 1;
+        }
+        let Some(name) = self.identifier_name() else {
+            return self.unexpected();
+        };
+        let name = self.src[name].to_owned();
+        for _ in 0..parens {
+            self.expect(")")?;
+        }
+        let key = if self.eat(".") {
+            match self.identifier_name() {
+                Some(key) => Some(self.src[key].to_owned()),
+                None => return self.unexpected(),
+            }
+        } else if self.eat("[") {
+            let key = self.nested(Self::expression)?;
+            self.expect("]")?;
+            Some(String::from_utf16_lossy(&key.to_js_string()))
+        } else {
+            None
+        };
+        Ok(Reference {
+            name,
+            key,
+            span: start..self.pos,
+        })
+    }
+
+    fn read(&mut self, reference: &Reference) -> EvalResult<Value> {
+        let value = self.identifier(&reference.name, reference.span.clone())?;
+        match &reference.key {
+            Some(key) => self.get(&value, key, reference.span.clone()),
+            None => Ok(value),
+        }
+    }
+
+    /// Assigns a variable, or a property of a plain object, ignoring properties of primitives
+    fn write(&mut self, reference: &Reference, value: Value) -> EvalResult<()> {
+        let Some(key) = &reference.key else {
+            match self.scope.get_mut(&reference.name) {
+                Some(old) => *old = value,
+                None => {
+                    self.evaluator.globals.insert(reference.name.clone(), value);
+                }
+            }
+            return Ok(());
+        };
+        let variable = match self.scope.get_mut(&reference.name) {
+            Some(variable) => Some(variable),
+            None => self.evaluator.globals.get_mut(&reference.name),
+        };
+        match variable {
+            Some(Value::Object(Object::Plain(properties))) => {
+                let key: JsString = key.encode_utf16().collect();
+                match properties.iter_mut().find(|(k, _)| *k == key) {
+                    Some((_, old)) => *old = value,
+                    None => properties.push((key, value)),
+                }
+                Ok(())
+            }
+            Some(Value::Bool(_) | Value::Number(_) | Value::String(_)) => Ok(()),
+            Some(variable) => {
+                let err_type = EvalErrorType::Unsupported(format!(
+                    "setting {key:?} of {}",
+                    variable.constructor().name()
+                ));
+                self.fail(err_type, reference.span.clone())
+            }
+            None => self.fail(
+                EvalErrorType::NotDefined(reference.name.clone()),
+                reference.span.clone(),
+            ),
+        }
+    }
+
+    /// `^` along with the equality operators binding tighter than it, in a single stack frame
+    fn bitwise(&mut self) -> EvalResult<Value> {
+        let mut xor = None;
+        let mut lhs = self.additive()?;
+        loop {
+            let (strict, negated) = if self.eat("===") {
+                (true, false)
+            } else if self.eat("!==") {
+                (true, true)
+            } else if self.eat("==") {
+                (false, false)
+            } else if self.eat("!=") {
+                (false, true)
+            } else if self.operator(b"^").is_some() {
+                xor = Some(xor.unwrap_or(0) ^ to_int32(&lhs));
+                lhs = self.additive()?;
+                continue;
+            } else {
+                break;
+            };
+            let rhs = self.additive()?;
+            let equal = if strict {
+                strict_equals(&lhs, &rhs)
+            } else {
+                loose_equals(&lhs, &rhs)
+            };
+            lhs = Value::Bool(equal != negated);
+        }
+        Ok(match xor {
+            Some(xor) => Value::Number(f64::from(xor ^ to_int32(&lhs))),
+            None => lhs,
+        })
+    }
+
+    fn additive(&mut self) -> EvalResult<Value> {
+        let start = self.pos;
+        let mut lhs = self.multiplicative()?;
+        while let Some(op) = self.operator(b"+-") {
+            let rhs = self.multiplicative()?;
+            lhs = if op == b'-' {
+                Value::Number(lhs.to_number() - rhs.to_number())
+            } else {
+                add(lhs, rhs).or_else(|err_type| self.fail(err_type, start..self.pos))?
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn multiplicative(&mut self) -> EvalResult<Value> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.operator(b"*/") {
+            let rhs = self.unary()?.to_number();
+            lhs = Value::Number(if op == b'*' {
+                lhs.to_number() * rhs
+            } else {
+                lhs.to_number() / rhs
+            });
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> EvalResult<Value> {
+        if self.eat("++") {
+            return self.increment(1.0);
+        }
+        if self.eat("--") {
+            return self.increment(-1.0);
+        }
+        match self.operator(b"+-!~") {
+            Some(b'+') => Ok(Value::Number(self.nested(Self::unary)?.to_number())),
+            Some(b'-') => Ok(Value::Number(-self.nested(Self::unary)?.to_number())),
+            Some(b'~') => Ok(Value::Number(f64::from(!to_int32(
+                &self.nested(Self::unary)?,
+            )))),
+            Some(_) => Ok(Value::Bool(!self.nested(Self::unary)?.is_truthy())),
+            None => self.postfix(),
+        }
+    }
+
+    /// `++` or `--` after the operator
+    #[inline(never)]
+    fn increment(&mut self, step: f64) -> EvalResult<Value> {
+        let start = self.pos;
+        // Anything but a variable or property, like in `++[]`, is a syntax error
+        let is_reference = self.skim_reference();
+        self.pos = start;
+        if !is_reference {
+            // Properties of values no variable holds, like in `++[[]][+[]]`, are only read
+            let value = self.postfix()?;
+            if !self.member {
+                self.pos = start - 2;
+                return self.unexpected();
+            }
+            return Ok(Value::Number(value.to_number() + step));
+        }
+        let reference = self.reference()?;
+        let value = Value::Number(self.read(&reference)?.to_number() + step);
+        self.write(&reference, value.clone())?;
+        Ok(value)
+    }
+
+    fn postfix(&mut self) -> EvalResult<Value> {
+        let start = self.pos;
+        let mut value = self.primary()?;
+        let mut member = false;
+        loop {
+            let next = self.peek();
+            if !matches!(next, Some(b'[' | b'.' | b'(' | b'`')) {
+                self.member = member;
+                return Ok(value);
+            }
+            member = matches!(next, Some(b'[' | b'.'));
+            if self.eat("[") {
+                let key = self.nested(Self::expression)?;
+                self.expect("]")?;
+                let key = String::from_utf16_lossy(&key.to_js_string());
+                value = self.get(&value, &key, start..self.pos)?;
+            } else if self.eat(".") {
+                let Some(key) = self.identifier_name() else {
+                    return self.unexpected();
+                };
+                let key = self.src[key].to_owned();
+                value = self.get(&value, &key, start..self.pos)?;
+            } else if self.eat("(") {
+                let args = self.list(")")?;
+                value = match value {
+                    Value::Function(function) => {
+                        self.call(&function, None, args, start..self.pos)?
+                    }
+                    _ => return self.fail(EvalErrorType::NotAFunction, start..self.pos),
+                };
+            } else {
+                // A tagged template, called with the array of its strings
+                let strings = self.template()?;
+                value = match value {
+                    Value::Function(function) => self.call(
+                        &function,
+                        None,
+                        vec![Value::Array(vec![strings])],
+                        start..self.pos,
+                    )?,
+                    _ => return self.fail(EvalErrorType::NotAFunction, start..self.pos),
+                };
+            }
+        }
+    }
+
+    /// A template literal without substitutions
+    fn template(&mut self) -> EvalResult<Value> {
+        let start = self.pos;
+        let value = self.string(b'`')?;
+        if self.src[start..self.pos].contains("${") {
+            return self.fail(
+                EvalErrorType::Unsupported("substitutions in templates".to_owned()),
+                start..self.pos,
+            );
+        }
+        Ok(value)
+    }
+
+    /// Comma separated expressions up to `close`
+    fn list(&mut self, close: &str) -> EvalResult<Vec<Value>> {
+        let mut values = vec![];
+        if self.eat(close) {
+            return Ok(values);
+        }
+        loop {
+            values.push(self.nested(Self::expression)?);
+            if self.eat(close) {
+                return Ok(values);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn primary(&mut self) -> EvalResult<Value> {
+        match self.peek() {
+            Some(b'[') => {
+                self.pos += 1;
+                Ok(Value::Array(self.list("]")?))
+            }
+            Some(b'(') => {
+                self.pos += 1;
+                let mut value = self.nested(Self::expression)?;
+                // The comma operator, evaluating to its last operand
+                while self.eat(",") {
+                    value = self.nested(Self::expression)?;
+                }
+                self.expect(")")?;
+                Ok(value)
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                self.object()
+            }
+            Some(b'/') => self.regexp(),
+            Some(quote @ (b'"' | b'\'')) => self.string(quote),
+            Some(b'`') => self.template(),
+            Some(b'0'..=b'9' | b'.') => self.number(),
+            _ => match self.identifier_name() {
+                Some(name) => self.identifier(&self.src[name.clone()], name),
+                None => self.unexpected(),
+            },
+        }
+    }
+
+    fn identifier(&self, name: &str, span: Range<usize>) -> EvalResult<Value> {
+        if let Some(value) = self
+            .scope
+            .get(name)
+            .or_else(|| self.evaluator.globals.get(name))
+        {
+            return Ok(value.clone());
+        }
+        Ok(match name {
+            "undefined" => Value::Undefined,
+            "null" => Value::Null,
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            "NaN" => Value::Number(f64::NAN),
+            "Infinity" => Value::Number(f64::INFINITY),
+            name => match Builtin::GLOBALS.iter().find(|g| g.name() == name) {
+                Some(&builtin) => Value::Function(Function::Builtin(builtin)),
+                None => return self.fail(EvalErrorType::NotDefined(name.to_owned()), span),
+            },
+        })
+    }
+
+    /// The properties of an object literal after its `{`
+    fn object(&mut self) -> EvalResult<Value> {
+        let mut properties: Vec<(JsString, Value)> = vec![];
+        while !self.eat("}") {
+            let start = self.pos;
+            let (key, computed) = match self.peek() {
+                Some(b'[') => {
+                    self.pos += 1;
+                    let key = self.nested(Self::expression)?;
+                    self.expect("]")?;
+                    (key.to_js_string(), true)
+                }
+                Some(quote @ (b'"' | b'\'')) => (self.string(quote)?.to_js_string(), false),
+                Some(b'0'..=b'9' | b'.') => (self.number()?.to_js_string(), false),
+                _ => match self.identifier_name() {
+                    Some(name) => (self.src[name].encode_utf16().collect(), false),
+                    None => return self.unexpected(),
+                },
+            };
+            self.expect(":")?;
+            let value = self.nested(Self::expression)?;
+            if !computed && key == "__proto__".encode_utf16().collect::<JsString>() {
+                return self.fail(
+                    EvalErrorType::Unsupported("setting the prototype".to_owned()),
+                    start..self.pos,
+                );
+            }
+            match properties.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, old)) => *old = value,
+                None => properties.push((key, value)),
+            }
+            if !self.eat(",") {
+                self.expect("}")?;
+                break;
+            }
+        }
+        Ok(Value::Object(Object::Plain(properties)))
+    }
+
+    fn number(&mut self) -> EvalResult<Value> {
+        let start = self.pos;
+        let rest = &self.src.as_bytes()[start..];
+        let mut len = rest
+            .iter()
+            .take_while(|b| b.is_ascii_digit() || **b == b'.')
+            .count();
+        if matches!(rest.get(len), Some(b'e' | b'E')) {
+            let sign = usize::from(matches!(rest.get(len + 1), Some(b'+' | b'-')));
+            let exponent = rest[len + 1 + sign..]
+                .iter()
+                .take_while(|b| b.is_ascii_digit())
+                .count();
+            if exponent > 0 {
+                len += 1 + sign + exponent;
+            }
+        }
+        match self.src[start..start + len].parse() {
+            Ok(n) => {
+                self.pos += len;
+                Ok(Value::Number(n))
+            }
+            Err(_) => self.unexpected(),
+        }
+    }
+
+    fn string(&mut self, quote: u8) -> EvalResult<Value> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut units = vec![];
+        let mut chars = self.src[self.pos..].char_indices().peekable();
+        let unterminated = |pos| Fail {
+            err_type: EvalErrorType::UnexpectedEnd,
+            span: start..pos,
+        };
+        let hex = |digits: &str, pos: usize| {
+            u32::from_str_radix(digits, 16).map_err(|_| Fail {
+                err_type: EvalErrorType::Unsupported(format!("escape sequence {digits:?}")),
+                span: pos..pos + digits.len(),
+            })
+        };
+        while let Some((offset, c)) = chars.next() {
+            let pos = self.pos + offset;
+            match c {
+                c if c as u32 == u32::from(quote) => {
+                    self.pos = pos + 1;
+                    return Ok(Value::String(units));
+                }
+                '\\' => {
+                    let (offset, escaped) = chars.next().ok_or_else(|| unterminated(pos))?;
+                    let rest = &self.src[self.pos + offset + escaped.len_utf8()..];
+                    let mut skip = |n: usize| {
+                        for _ in 0..n {
+                            chars.next();
+                        }
+                    };
+                    let unit = match escaped {
+                        'n' => u32::from('\n'),
+                        't' => u32::from('\t'),
+                        'r' => u32::from('\r'),
+                        'b' => 0x08,
+                        'f' => 0x0c,
+                        'v' => 0x0b,
+                        'x' => {
+                            let digits = rest.get(..2).ok_or_else(|| unterminated(pos))?;
+                            skip(2);
+                            hex(digits, pos)?
+                        }
+                        'u' if rest.starts_with('{') => {
+                            let len = rest.find('}').ok_or_else(|| unterminated(pos))?;
+                            skip(len + 1);
+                            hex(&rest[1..len], pos)?
+                        }
+                        'u' => {
+                            let digits = rest.get(..4).ok_or_else(|| unterminated(pos))?;
+                            skip(4);
+                            hex(digits, pos)?
+                        }
+                        '0'..='7' => {
+                            // Legacy octal escapes take up to three digits while below 256
+                            let max = if escaped <= '3' { 2 } else { 1 };
+                            let len = rest
+                                .bytes()
+                                .take(max)
+                                .take_while(|b| (b'0'..=b'7').contains(b))
+                                .count();
+                            skip(len);
+                            u32::from_str_radix(&format!("{escaped}{}", &rest[..len]), 8)
+                                .unwrap_or_default()
+                        }
+                        '\n' => continue,
+                        c => c as u32,
+                    };
+                    match char::from_u32(unit) {
+                        Some(c) => units.extend(c.encode_utf16(&mut [0; 2]).iter()),
+                        // Lone surrogates written as `\uXXXX`
+                        None => units.push(unit as u16),
+                    }
+                }
+                c => units.extend(c.encode_utf16(&mut [0; 2]).iter()),
+            }
+        }
+        Err(unterminated(self.src.len()))
+    }
+
+    fn regexp(&mut self) -> EvalResult<Value> {
+        let start = self.pos;
+        let mut escaped = false;
+        let mut class = false;
+        for (offset, c) in self.src[start + 1..].char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '[' => class = true,
+                ']' => class = false,
+                '/' if !class && offset > 0 => {
+                    let source = &self.src[start + 1..start + 1 + offset];
+                    self.pos = start + offset + 2;
+                    if self.src[self.pos..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+                        return self.fail(
+                            EvalErrorType::Unsupported("regular expression flags".to_owned()),
+                            self.pos..self.pos + 1,
+                        );
+                    }
+                    return Ok(Value::RegExp(source.encode_utf16().collect()));
+                }
+                '\n' => break,
+                _ => {}
+            }
+        }
+        self.unexpected()
+    }
+
+    fn get(&mut self, value: &Value, key: &str, span: Range<usize>) -> EvalResult<Value> {
+        let index = key.parse::<usize>().ok().filter(|i| i.to_string() == key);
+        if let Value::Object(Object::Plain(properties)) = value {
+            let own = properties
+                .iter()
+                .find(|(k, _)| String::from_utf16_lossy(k) == key);
+            if let Some((_, value)) = own {
+                return Ok(value.clone());
+            }
+        }
+        Ok(match (value, key, index) {
+            (Value::Undefined, ..) => {
+                return self.fail(EvalErrorType::ReadOfUndefined(key.to_owned()), span)
+            }
+            (Value::Null, ..) => return self.fail(EvalErrorType::ReadOfNull(key.to_owned()), span),
+            (value, "constructor", _) => Value::Function(Function::Builtin(value.constructor())),
+            (Value::String(s), "length", _) => Value::Number(s.len() as f64),
+            (Value::Array(values), "length", _) => Value::Number(values.len() as f64),
+            (Value::String(s), _, Some(i)) => s
+                .get(i)
+                .map_or(Value::Undefined, |&unit| Value::String(vec![unit])),
+            (Value::Array(values), _, Some(i)) => {
+                values.get(i).cloned().unwrap_or(Value::Undefined)
+            }
+            (Value::Function(function), "name", _) => Value::string(function.name()),
+            (Value::Function(Function::Builtin(Builtin::String)), "fromCharCode", _) => {
+                Value::Function(Function::Builtin(Builtin::FromCharCode))
+            }
+            (Value::Function(Function::Builtin(Builtin::String)), "fromCodePoint", _) => {
+                Value::Function(Function::Builtin(Builtin::FromCodePoint))
+            }
+            (value, name, _) => Method::lookup(value, name).map_or(Value::Undefined, |method| {
+                Value::Function(Function::Method(method, Box::new(value.clone())))
+            }),
+        })
+    }
+
+    /// Calls `function`, with `this` overriding the value a method was read from
+    fn call(
+        &mut self,
+        function: &Function,
+        this: Option<Value>,
+        args: Vec<Value>,
+        span: Range<usize>,
+    ) -> EvalResult<Value> {
+        let arg = |i: usize| args.get(i).cloned().unwrap_or(Value::Undefined);
+        let string_arg = |i: usize| arg(i).to_js_string();
+        Ok(match function {
+            Function::Arrow => Value::Undefined,
+            Function::Method(method, value) => {
+                let this = this.unwrap_or_else(|| (**value).clone());
+                self.call_method(*method, this, args, span)?
+            }
+            Function::Dynamic { params, body } => {
+                let scope = params.iter().cloned().zip(args).collect();
+                self.run(body, scope, span)?
+            }
+            Function::Literal(literal) => {
+                let mut scope = literal.closure.clone();
+                scope.extend(literal.params.iter().cloned().zip(args));
+                self.run(&literal.body, scope, span)?
+            }
+            Function::Builtin(builtin) => match builtin {
+                Builtin::Array => Value::Array(args),
+                Builtin::Boolean => Value::Bool(arg(0).is_truthy()),
+                Builtin::Date => Value::string(DATE),
+                Builtin::Number => Value::Number(args.first().map_or(0.0, Value::to_number)),
+                Builtin::Object => match arg(0) {
+                    Value::Undefined | Value::Null => Value::Object(Object::Plain(vec![])),
+                    value => value,
+                },
+                Builtin::String => Value::String(args.first().map_or(vec![], Value::to_js_string)),
+                Builtin::RegExp => Value::RegExp(match arg(0) {
+                    Value::Undefined => "(?:)".encode_utf16().collect(),
+                    Value::RegExp(source) => source,
+                    value => {
+                        let source = value.to_js_string();
+                        if source.is_empty() {
+                            "(?:)".encode_utf16().collect()
+                        } else {
+                            // `/` ends the literal unless it is escaped
+                            let mut escaped = vec![];
+                            let mut backslash = false;
+                            for unit in source {
+                                if unit == u16::from(b'/') && !backslash {
+                                    escaped.push(u16::from(b'\\'));
+                                }
+                                backslash = unit == u16::from(b'\\') && !backslash;
+                                escaped.push(unit);
+                            }
+                            escaped
+                        }
+                    }
+                }),
+                Builtin::Function => {
+                    let mut strings = args
+                        .iter()
+                        .map(|value| String::from_utf16_lossy(&value.to_js_string()))
+                        .collect_vec();
+                    let body = strings.pop().unwrap_or_default();
+                    let params = strings
+                        .iter()
+                        .flat_map(|params| params.split(','))
+                        .map(|param| param.trim().to_owned())
+                        .filter(|param| !param.is_empty())
+                        .collect();
+                    self.evaluator.code.push(body.clone());
+                    Value::Function(Function::Dynamic { params, body })
+                }
+                Builtin::Escape => Value::String(escape(&string_arg(0))),
+                Builtin::Unescape => Value::String(unescape(&string_arg(0))),
+                Builtin::Eval => match arg(0) {
+                    Value::String(code) => {
+                        let code = String::from_utf16_lossy(&code);
+                        self.evaluator.code.push(code.clone());
+                        self.run(&code, HashMap::new(), span)?
+                    }
+                    value => value,
+                },
+                // `ToUint16`
+                Builtin::FromCharCode => Value::String(
+                    args.iter()
+                        .map(|value| {
+                            let n = value.to_number();
+                            if n.is_finite() {
+                                n.trunc().rem_euclid(65536.0) as u16
+                            } else {
+                                0
+                            }
+                        })
+                        .collect(),
+                ),
+                Builtin::FromCodePoint => {
+                    let mut units = vec![];
+                    for value in &args {
+                        let n = value.to_number();
+                        match char::from_u32(n as u32).filter(|_| n.fract() == 0.0 && n >= 0.0) {
+                            Some(c) => units.extend(c.encode_utf16(&mut [0; 2]).iter()),
+                            None => {
+                                return self.fail(
+                                    EvalErrorType::Unsupported(format!("code point {value}")),
+                                    span,
+                                )
+                            }
+                        }
+                    }
+                    Value::String(units)
+                }
+            },
+        })
+    }
+
+    fn call_method(
+        &mut self,
+        method: Method,
+        this: Value,
+        args: Vec<Value>,
+        span: Range<usize>,
+    ) -> EvalResult<Value> {
+        let arg = |i: usize| args.get(i).cloned().unwrap_or(Value::Undefined);
+        let this_string = || this.to_js_string();
+        let html = |open: &str, close: &str| {
+            let mut s: JsString = open.encode_utf16().collect();
+            s.extend(this_string());
+            s.extend(close.encode_utf16());
+            Value::String(s)
+        };
+        let check_length = |len: usize, kind| {
+            check_length(len, kind).or_else(|err_type| self.fail(err_type, span.clone()))
+        };
+        Ok(match (method, &this) {
+            (_, Value::Undefined) if method != Method::ObjectToString => {
+                return self.fail(
+                    EvalErrorType::ReadOfUndefined(method.name().to_owned()),
+                    span,
+                )
+            }
+            (_, Value::Null) if method != Method::ObjectToString => {
+                return self.fail(EvalErrorType::ReadOfNull(method.name().to_owned()), span)
+            }
+            (Method::Call, Value::Function(function)) => {
+                let mut args = args.into_iter();
+                let this = args.next().unwrap_or(Value::Undefined);
+                self.call(function, Some(this), args.collect(), span)?
+            }
+            (Method::Concat, Value::Array(values)) => {
+                let len = args.iter().fold(values.len(), |len, arg| match arg {
+                    Value::Array(arg) => len.saturating_add(arg.len()),
+                    _ => len.saturating_add(1),
+                });
+                check_length(len, "array")?;
+                let mut values = values.clone();
+                for arg in args {
+                    match arg {
+                        Value::Array(arg) => values.extend(arg),
+                        arg => values.push(arg),
+                    }
+                }
+                Value::Array(values)
+            }
+            (Method::Concat, _) => {
+                let len = args.iter().fold(this.js_string_len(), |len, arg| {
+                    len.saturating_add(arg.js_string_len())
+                });
+                check_length(len, "string")?;
+                let mut s = this_string();
+                for arg in args {
+                    s.extend(arg.to_js_string());
+                }
+                Value::String(s)
+            }
+            (Method::Entries, _) => Value::Object(Object::ArrayIterator),
+            (Method::Flat, Value::Array(values)) => {
+                let len = values.iter().fold(0, |len: usize, value| match value {
+                    Value::Array(values) => len.saturating_add(values.len()),
+                    _ => len.saturating_add(1),
+                });
+                check_length(len, "array")?;
+                Value::Array(
+                    values
+                        .iter()
+                        .flat_map(|value| match value {
+                            Value::Array(values) => values.clone(),
+                            value => vec![value.clone()],
+                        })
+                        .collect(),
+                )
+            }
+            (Method::Fontcolor, _) => {
+                let color = String::from_utf16_lossy(&arg(0).to_js_string());
+                html(
+                    &format!("<font color=\"{}\">", color.replace('"', "&quot;")),
+                    "</font>",
+                )
+            }
+            (Method::Italics, _) => html("<i>", "</i>"),
+            (Method::Join, Value::Array(values)) => {
+                let separator = match arg(0) {
+                    Value::Undefined => vec![u16::from(b',')],
+                    separator => separator.to_js_string(),
+                };
+                let separators = separator
+                    .len()
+                    .saturating_mul(values.len().saturating_sub(1));
+                let len = values.iter().fold(separators, |len, value| {
+                    len.saturating_add(if value.is_nullish() {
+                        0
+                    } else {
+                        value.js_string_len()
+                    })
+                });
+                check_length(len, "string")?;
+                let mut s = vec![];
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        s.extend(&separator);
+                    }
+                    if !value.is_nullish() {
+                        s.extend(value.to_js_string());
+                    }
+                }
+                Value::String(s)
+            }
+            (Method::Slice, Value::Array(values)) => {
+                let start = relative_index(&arg(0), values.len(), 0);
+                let end = relative_index(&arg(1), values.len(), values.len());
+                Value::Array(values[start..end.max(start)].to_vec())
+            }
+            (Method::Slice, _) => {
+                let s = this_string();
+                let start = relative_index(&arg(0), s.len(), 0);
+                let end = relative_index(&arg(1), s.len(), s.len());
+                Value::String(s[start..end.max(start)].to_vec())
+            }
+            (Method::Split, _) => {
+                let s = this_string();
+                match arg(0) {
+                    Value::Undefined => Value::Array(vec![Value::String(s)]),
+                    separator => {
+                        let separator = separator.to_js_string();
+                        if separator.is_empty() {
+                            Value::Array(
+                                s.into_iter()
+                                    .map(|unit| Value::String(vec![unit]))
+                                    .collect(),
+                            )
+                        } else {
+                            let mut parts = vec![];
+                            let mut last = 0;
+                            let mut i = 0;
+                            while i + separator.len() <= s.len() {
+                                if s[i..].starts_with(&separator) {
+                                    parts.push(Value::String(s[last..i].to_vec()));
+                                    i += separator.len();
+                                    last = i;
+                                } else {
+                                    i += 1;
+                                }
+                            }
+                            parts.push(Value::String(s[last..].to_vec()));
+                            Value::Array(parts)
+                        }
+                    }
+                }
+            }
+            (Method::ToString, Value::Number(n)) => {
+                let radix = match arg(0) {
+                    Value::Undefined => 10.0,
+                    radix => radix.to_number(),
+                };
+                if radix == 10.0 {
+                    Value::string(&number_to_string(*n))
+                } else {
+                    let digits = (2.0..=36.0)
+                        .contains(&radix)
+                        .then(|| integer_to_radix(*n, radix as u32))
+                        .flatten();
+                    match digits {
+                        Some(digits) => Value::string(&digits),
+                        None => {
+                            return self.fail(
+                                EvalErrorType::Unsupported(format!("{n} in radix {radix}")),
+                                span,
+                            )
+                        }
+                    }
+                }
+            }
+            (Method::ToString, _) => Value::String(this_string()),
+            (Method::ObjectToString, _) => {
+                let tag = match &this {
+                    Value::Object(Object::ArrayIterator) => "Array Iterator",
+                    Value::Undefined => "Undefined",
+                    Value::Null => "Null",
+                    value => value.constructor().name(),
+                };
+                Value::string(&format!("[object {tag}]"))
+            }
+            (method, value) => {
+                return self.fail(
+                    EvalErrorType::Unsupported(format!(
+                        "call of {} on {}",
+                        method.name(),
+                        value.constructor().name()
+                    )),
+                    span,
+                )
+            }
+        })
+    }
+
+    /// Runs the body of a function, reporting any error at the call site
+    fn run(
+        &mut self,
+        code: &str,
+        scope: HashMap<String, Value>,
+        span: Range<usize>,
+    ) -> EvalResult<Value> {
+        let mut parser = Parser {
+            evaluator: &mut *self.evaluator,
+            src: code,
+            pos: 0,
+            depth: self.depth,
+            scope,
+            member: false,
+        };
+        parser
+            .nested(Parser::program)
+            .map_err(|Fail { err_type, .. }| Fail { err_type, span })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn eval_string(src: &str) -> String {
+        eval(src).unwrap().to_string()
+    }
+
+    fn err_type(src: &str) -> (EvalErrorType, Range<usize>) {
+        let err = eval(src).unwrap_err();
+        let start = err.location.offset();
+        (err.err_type, start..start + err.location.len())
+    }
+
+    #[test]
+    fn coercions() {
+        assert_eq!(eval("+[]").unwrap(), Value::Number(0.0));
+        assert_eq!(eval("+!![]").unwrap(), Value::Number(1.0));
+        assert_eq!(eval("!![]+!![]").unwrap(), Value::Number(2.0));
+        assert_eq!(eval_string("![]+[]"), "false");
+        assert_eq!(eval_string("[][[]]+[]"), "undefined");
+        assert_eq!(eval_string("+[![]]+[]"), "NaN");
+        assert_eq!(eval_string("{}+[]"), "[object Object]");
+        assert_eq!(eval_string("[[],[+!![]]]+[]"), ",1");
+        assert_eq!(eval_string("+!![]/+[]+[]"), "Infinity");
+        assert_eq!(eval_string("+(+!![]+[+[]])*(+!![] + +!![])"), "20");
+    }
+
+    #[test]
+    fn numbers() {
+        for (n, s) in [
+            (1e21, "1e+21"),
+            (1.1e101, "1.1e+101"),
+            (1e-7, "1e-7"),
+            (0.000_001, "0.000001"),
+            (123.456, "123.456"),
+            (1e20, "100000000000000000000"),
+            (-0.5, "-0.5"),
+        ] {
+            assert_eq!(number_to_string(n), s);
+        }
+        assert_eq!(eval_string("+\"11e100\"+[]"), "1.1e+101");
+        assert_eq!(eval_string("+\".0000001\"+[]"), "1e-7");
+        assert!(eval("+\"1e\"").unwrap().to_number().is_nan());
+        assert!(eval("+\"inf\"").unwrap().to_number().is_nan());
+    }
+
+    #[test]
+    fn builtins() {
+        assert_eq!(
+            eval_string("[][\"flat\"]+[]"),
+            "function flat() { [native code] }"
+        );
+        assert_eq!(
+            eval_string("[][\"entries\"]()+[]"),
+            "[object Array Iterator]"
+        );
+        assert_eq!(eval_string("([]+[])[\"constructor\"][\"name\"]"), "String");
+        assert_eq!(eval_string("(+(22))[\"toString\"](23)"), "m");
+        assert_eq!(eval_string("([]+[])[\"italics\"]()"), "<i></i>");
+        assert_eq!(
+            eval_string("([]+[])[\"fontcolor\"](\"\\\"\")"),
+            "<font color=\"&quot;\"></font>"
+        );
+        assert_eq!(eval_string("[[]][\"concat\"]([[]])+[]"), ",");
+        assert_eq!(eval_string("(\"abc\")[\"slice\"](\"-1\")"), "c");
+        assert_eq!(
+            eval_string("(\"atbtc\")[\"split\"](\"t\")[\"join\"](\"-\")"),
+            "a-b-c"
+        );
+        assert_eq!(
+            eval_string("Object()[\"toString\"][\"call\"]()"),
+            "[object Undefined]"
+        );
+        assert_eq!(
+            eval_string("([]+[])[\"constructor\"][\"fromCharCode\"](97)"),
+            "a"
+        );
+        assert_eq!(eval_string("(()=>{})+[]"), "()=>{}");
+    }
+
+    #[test]
+    fn regexps() {
+        assert_eq!(eval_string("/-/+[]"), "/-/");
+        assert_eq!(eval_string("RegExp()+[]"), "/(?:)/");
+        assert_eq!(eval_string("RegExp(\"/\")+[]"), "/\\//");
+        assert_eq!(
+            eval_string("[]+(/-/)[\"constructor\"]"),
+            "function RegExp() { [native code] }"
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            eval_string("escape(\"a b~\\u00e9\\u4e16\")"),
+            "a%20b%7E%E9%u4E16"
+        );
+        assert_eq!(
+            eval_string("unescape(\"%u4E16%e9%zz\")"),
+            "\u{4e16}\u{e9}%zz"
+        );
+        assert_eq!(eval_string("\"\\110\\151\\x21\\u{1f600}\""), "Hi!\u{1f600}");
+        assert_eq!(eval_string("\"\\\u{e9}\\\u{1f600}1\""), "\u{e9}\u{1f600}1");
+        assert_eq!(
+            eval("unescape(\"%ud83d\")").unwrap(),
+            Value::String(vec![0xd83d])
+        );
+        assert_eq!(
+            eval("unescape(\"%ud83d\")+unescape(\"%ude00\")")
+                .unwrap()
+                .to_string(),
+            "\u{1f600}"
+        );
+    }
+
+    #[test]
+    fn functions() {
+        let mut evaluator = Evaluator::default();
+        let value = evaluator
+            .eval("Function(\"return/\"+false+\"/\")()[\"constructor\"](\"/\")+[]")
+            .unwrap();
+        assert_eq!(value.to_string(), "/\\//");
+        assert_eq!(evaluator.code(), ["return/false/"]);
+        assert_eq!(eval_string("Function(\"return escape\")()(\"<\")"), "%3C");
+        assert_eq!(eval_string("Function(\"a\",\"return a+a\")(\"b\")"), "bb");
+        assert_eq!(eval_string("Function(\"return Date\")()()"), DATE);
+        assert_eq!(
+            eval_string("Function(\"\")+[]"),
+            "function anonymous(\n) {\n\n}"
+        );
+    }
+
+    #[test]
+    fn function_literals() {
+        assert_eq!(eval_string("(a=>a+a)(\"b\")"), "bb");
+        assert_eq!(eval_string("(function(a,b){return b+a})(1,\"2\")"), "21");
+        assert_eq!(eval_string("(a=>(b=>[a,b])(2))(1)+[]"), "1,2");
+        assert_eq!(eval_string("Function(\"return a=>[a]\")()(\"b\")+[]"), "b");
+        assert_eq!(eval_string("(a=>a[\"x,y\"])+[]"), "a=>a[\"x,y\"]");
+    }
+
+    #[test]
+    fn objects() {
+        assert_eq!(
+            eval("{a: 1, \"b\": [null], 2: \"c\", [\"__proto__\"]: {}, a: -1}").unwrap(),
+            Value::Object(Object::Plain(vec![
+                ("a".encode_utf16().collect(), Value::Number(-1.0)),
+                (
+                    "b".encode_utf16().collect(),
+                    Value::Array(vec![Value::Null])
+                ),
+                ("2".encode_utf16().collect(), Value::string("c")),
+                (
+                    "__proto__".encode_utf16().collect(),
+                    Value::Object(Object::Plain(vec![]))
+                ),
+            ]))
+        );
+        assert_eq!(eval_string("{a: \"b\"}[\"a\"]"), "b");
+        assert_eq!(eval_string("{}[\"toString\"]()"), "[object Object]");
+        assert_eq!(eval_string("[null, [][[]], +[]]+[]"), ",,0");
+        assert_eq!(
+            eval("+Function(\"return null\")()").unwrap(),
+            Value::Number(0.0)
+        );
+        assert_eq!(
+            err_type("{__proto__: {}}"),
+            (
+                EvalErrorType::Unsupported("setting the prototype".to_owned()),
+                1..14
+            )
+        );
+        assert_eq!(
+            err_type("Function(\"return null\")()[[]]"),
+            (EvalErrorType::ReadOfNull(String::new()), 0..29)
+        );
+    }
+
+    #[test]
+    fn scripts() {
+        assert_eq!(eval("").unwrap(), Value::Undefined);
+        assert_eq!(eval_string("$=~[]; ++$; ++$ /* two */;"), "1");
+        assert_eq!(eval_string("a=b={}; a.c=(b)['d']=3; a.c+b.d"), "6");
+        assert_eq!(eval_string("ω=3; ω+=\"\"; ω==3^2"), "3");
+        assert_eq!(eval_string("o=1; o.x=2; +o.x // ignored"), "NaN");
+        assert_eq!(eval_string("x=/-/ //\n['y']; x"), "undefined");
+        assert_eq!(eval_string("(a=1, b=a+1, [a,b])+[]"), "1,2");
+        assert_eq!(
+            eval_string("[[],+[]==\"\",[]===[],~-2]+[]"),
+            ",true,false,1"
+        );
+        let mut evaluator = Evaluator::default();
+        evaluator.eval("n = 1").unwrap();
+        assert_eq!(evaluator.eval("--n").unwrap(), Value::Number(0.0));
+        assert_eq!(
+            err_type("x.y=1"),
+            (EvalErrorType::NotDefined("x".to_owned()), 0..3)
+        );
+        assert_eq!(
+            err_type("x=[]; x[0]=1"),
+            (
+                EvalErrorType::Unsupported("setting \"0\" of Array".to_owned()),
+                6..10
+            )
+        );
+        assert_eq!(err_type("++[]"), (EvalErrorType::UnexpectedChar('+'), 0..1));
+        assert_eq!(eval_string("++[[]][+[]]+[--[+!![]][+[]]]"), "10");
+        assert_eq!(
+            eval_string("String`a`+`b`+[][\"find\"]"),
+            "abfunction find() { [native code] }"
+        );
+        assert_eq!(
+            err_type("`${1}`"),
+            (
+                EvalErrorType::Unsupported("substitutions in templates".to_owned()),
+                0..6
+            )
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(err_type("+[]+"), (EvalErrorType::UnexpectedEnd, 4..4));
+        assert_eq!(
+            err_type("+!![]++!![]"),
+            (EvalErrorType::UnexpectedChar('+'), 5..6)
+        );
+        assert_eq!(
+            err_type("[][[]][[]]"),
+            (EvalErrorType::ReadOfUndefined(String::new()), 0..10)
+        );
+        assert_eq!(err_type("[+[]]([])"), (EvalErrorType::NotAFunction, 0..9));
+        assert_eq!(
+            err_type("[][\"flat\"][\"constructor\"](\"alert(1)\")()"),
+            (EvalErrorType::NotDefined("alert".to_owned()), 0..39)
+        );
+        assert_eq!(
+            err_type("()=>{}+[]"),
+            (EvalErrorType::UnexpectedChar('+'), 6..7)
+        );
+        let nested = format!("{}[]{}", "(".repeat(1000), ")".repeat(1000));
+        assert_eq!(eval(&nested).unwrap_err().err_type, EvalErrorType::TooDeep);
+        let doubled = format!("a=\"ab\";{}a", "a=a+a;".repeat(40));
+        assert_eq!(
+            eval(&doubled).unwrap_err().err_type,
+            EvalErrorType::TooLong("string")
+        );
+        let compound = format!("a=\"ab\";{}a", "a+=a;".repeat(40));
+        assert_eq!(
+            eval(&compound).unwrap_err().err_type,
+            EvalErrorType::TooLong("string")
+        );
+        let joined = format!("a=[\"ab\"];{}a", "a=[a,a,a,a,a,a,a,a].join(a);".repeat(10));
+        assert_eq!(
+            eval(&joined).unwrap_err().err_type,
+            EvalErrorType::TooLong("string")
+        );
+        let concatenated = format!("a=[[]];{}a", "a=a.concat(a);".repeat(40));
+        assert_eq!(
+            eval(&concatenated).unwrap_err().err_type,
+            EvalErrorType::TooLong("array")
+        );
+    }
+}
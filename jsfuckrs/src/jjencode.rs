@@ -0,0 +1,318 @@
+//! Port of Yosuke Hasegawa's JJencode
+//!
+//! The output is a script that keeps its building blocks in the properties of a single global
+//! variable, `$` unless [another name](Jjencode::new) is given. Apart from that name it only uses
+//! the symbols `[]()!+{}~:;,.=_$"\` and the punctuation of the encoded code, which is copied as is.
+//!
+//! [Original](https://utf-8.jp/public/jjencode.html)
+use std::fmt;
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::{encoder::is_identifier, writer::collect, JsFuckEncoder};
+
+/// The properties holding the hexadecimal digits, which are numbers up to `9` and letters after
+const DIGITS: [&str; 16] = [
+    "___", "__$", "_$_", "_$$", "$__", "$_$", "$$_", "$$$", "$___", "$__$", "$_$_", "$_$$", "$$__",
+    "$$_$", "$$$_", "$$$$",
+];
+
+/// Fills the variable, written `@`, with the digits, `"constructor"` in `$_`, `"return"` in `$$`,
+/// `Function` in `$`, and the letters `o`, `t` and `u` in `_$`, `__` and `_`
+const PRELUDE: &str = concat!(
+    r#"@=~[];@={___:++@,$$$$:(![]+"")[@],__$:++@,$_$_:(![]+"")[@],_$_:++@,$_$$:({}+"")[@],"#,
+    r#"$$_$:(@[@]+"")[@],_$$:++@,$$$_:(!""+"")[@],$__:++@,$_$:++@,$$__:({}+"")[@],$$_:++@,"#,
+    r#"$$$:++@,$___:++@,$__$:++@};"#,
+    r#"@.$_=(@.$_=@+"")[@.$_$]+(@._$=@.$_[@.__$])+(@.$$=(@.$+"")[@.__$])+((!@)+"")[@._$$]+"#,
+    r#"(@.__=@.$_[@.$$_])+(@.$=(!""+"")[@.__$])+(@._=(!""+"")[@._$_])+@.$_[@.$_$]+@.__+@._$+@.$;"#,
+    r#"@.$$=@.$+(!""+"")[@._$$]+@.__+@._+@.$+@.$$;@.$=(@.___)[@.$_][@.$_];"#,
+);
+
+#[derive(Error, Diagnostic, Debug, Clone, PartialEq, Eq)]
+#[error("{0:?} can not be used as the name of a variable")]
+#[diagnostic(help("use an identifier like `$` or `_jj` that is not a keyword"))]
+pub struct InvalidVariable(pub String);
+
+/// The encoder, along with the name of the global variable its output assigns
+///
+/// # Examples
+/// ```
+/// use jsfuckrs::{decode, jjencode::Jjencode};
+/// let encoder = Jjencode::new("_j").unwrap();
+/// let encoded = encoder.compile("alert(1)");
+/// assert!(encoded.starts_with("_j=~[];"));
+/// assert_eq!(decode(&encoded).unwrap(), "alert(1)");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Jjencode {
+    variable: String,
+}
+
+impl Default for Jjencode {
+    fn default() -> Self {
+        Self {
+            variable: "$".to_owned(),
+        }
+    }
+}
+
+impl Jjencode {
+    /// # Errors
+    /// When `variable` is not an identifier, or one of the words javascript reserves
+    pub fn new(variable: &str) -> Result<Self, InvalidVariable> {
+        if !is_identifier(variable) {
+            return Err(InvalidVariable(variable.to_owned()));
+        }
+        Ok(Self {
+            variable: variable.to_owned(),
+        })
+    }
+
+    /// The name of the global variable the output assigns
+    #[must_use]
+    pub fn variable(&self) -> &str {
+        &self.variable
+    }
+
+    /// Encodes `code` into a script running it
+    #[must_use]
+    pub fn compile(&self, code: &str) -> String {
+        collect(|out| self.compile_to(out, code))
+    }
+
+    /// Streams what [`compile`](Self::compile) returns into `out`
+    ///
+    /// # Errors
+    /// When `out` fails
+    pub fn compile_to<W: fmt::Write + ?Sized>(&self, out: &mut W, code: &str) -> fmt::Result {
+        let v = &self.variable;
+        out.write_str(&PRELUDE.replace('@', v))?;
+        write!(out, "{v}.$(")?;
+        self.write_escaped(out, code)?;
+        out.write_str(")();")
+    }
+
+    /// `Function("return\"…\"")()`, where the string holds escape sequences, evaluating to `s`
+    fn write_escaped<W: fmt::Write + ?Sized>(&self, out: &mut W, s: &str) -> fmt::Result {
+        let v = &self.variable;
+        write!(out, "{v}.$({v}.$$+\"\\\"\"+")?;
+        // Punctuation waiting to be written in a single string literal
+        let mut literal = String::new();
+        for unit in s.encode_utf16() {
+            let c = char::from_u32(u32::from(unit)).filter(char::is_ascii);
+            let property = match c {
+                // Escaped once for the literal and once for the string it builds
+                Some(c @ ('"' | '\\')) => {
+                    literal.push_str("\\\\\\");
+                    literal.push(c);
+                    continue;
+                }
+                Some(c) if c.is_ascii_punctuation() => {
+                    literal.push(c);
+                    continue;
+                }
+                Some(c) => match self.property(c) {
+                    Some(property) => property,
+                    // An octal escape sequence, padded so a following digit is not taken as its own
+                    None => {
+                        write!(out, "\"{literal}\\\\\"+")?;
+                        literal.clear();
+                        for digit in format!("{:03o}", u32::from(c)).chars() {
+                            let digit = digit.to_digit(8).unwrap_or_default() as usize;
+                            write!(out, "{v}.{}+", DIGITS[digit])?;
+                        }
+                        continue;
+                    }
+                },
+                None => {
+                    write!(out, "\"{literal}\\\\\"+{v}._+")?;
+                    literal.clear();
+                    for digit in format!("{unit:04x}").chars() {
+                        let digit = digit.to_digit(16).unwrap_or_default() as usize;
+                        write!(out, "{v}.{}+", DIGITS[digit])?;
+                    }
+                    continue;
+                }
+            };
+            if !literal.is_empty() {
+                write!(out, "\"{literal}\"+")?;
+                literal.clear();
+            }
+            write!(out, "{property}+")?;
+        }
+        if !literal.is_empty() {
+            write!(out, "\"{literal}\"+")?;
+        }
+        out.write_str("\"\\\"\")()")
+    }
+
+    /// The property of the variable holding `c`, or the expression reading it from `false`
+    fn property(&self, c: char) -> Option<String> {
+        let v = &self.variable;
+        Some(match c {
+            '0'..='9' | 'a'..='f' => {
+                let digit = c.to_digit(16).unwrap_or_default() as usize;
+                format!("{v}.{}", DIGITS[digit])
+            }
+            'l' => format!("(![]+\"\")[{v}._$_]"),
+            'o' => format!("{v}._$"),
+            't' => format!("{v}.__"),
+            'u' => format!("{v}._"),
+            _ => return None,
+        })
+    }
+
+    /// A string literal of `s`, with the characters the variable holds concatenated in between
+    fn write_concatenation(&self, out: &mut dyn fmt::Write, s: &str) -> fmt::Result {
+        out.write_char('"')?;
+        for c in s.chars() {
+            match self.property(c) {
+                Some(property) => write!(out, "\"+{property}+\"")?,
+                None => {
+                    if matches!(c, '"' | '\\') {
+                        out.write_char('\\')?;
+                    }
+                    out.write_char(c)?;
+                }
+            }
+        }
+        out.write_char('"')
+    }
+
+    /// Opens an expression that makes the assignments of [`PRELUDE`], then evaluates to what is
+    /// written after it up to a closing parenthesis
+    fn open_expression(&self, out: &mut dyn fmt::Write) -> fmt::Result {
+        let assignments = PRELUDE.trim_end_matches(';').replace(';', ",");
+        write!(out, "({},", assignments.replace('@', &self.variable))
+    }
+}
+
+impl JsFuckEncoder for Jjencode {
+    fn number(&self, n: usize) -> String {
+        collect(|out| {
+            self.open_expression(out)?;
+            if n < 10 {
+                write!(out, "{}.{}", self.variable, DIGITS[n])?;
+            } else {
+                out.write_str("+(")?;
+                self.write_concatenation(out, &n.to_string())?;
+                out.write_char(')')?;
+            }
+            out.write_char(')')
+        })
+    }
+
+    /// Concatenates the characters the variable holds and punctuation when every character is
+    /// mapped, and builds the string from escape sequences otherwise
+    fn string_to(&self, out: &mut dyn fmt::Write, s: &str) -> fmt::Result {
+        self.open_expression(out)?;
+        if s.chars().all(|c| self.is_mapped(c)) {
+            self.write_concatenation(out, s)?;
+        } else {
+            self.write_escaped(out, s)?;
+        }
+        out.write_char(')')
+    }
+
+    fn compile_to(&self, out: &mut dyn fmt::Write, code: &str) -> fmt::Result {
+        Self::compile_to(self, out, code)
+    }
+
+    fn is_mapped(&self, c: char) -> bool {
+        c.is_ascii_punctuation() || self.property(c).is_some()
+    }
+
+    fn function_constructor(&self) -> String {
+        collect(|out| {
+            self.open_expression(out)?;
+            write!(out, "{}.$)", self.variable)
+        })
+    }
+}
+
+/// Encodes `code` into a script running it, keeping its building blocks in `$`
+#[must_use]
+pub fn compile(code: &str) -> String {
+    Jjencode::default().compile(code)
+}
+
+/// Streams what [`compile`] returns into `out`
+///
+/// # Errors
+/// When `out` fails
+pub fn compile_to<W: fmt::Write + ?Sized>(out: &mut W, code: &str) -> fmt::Result {
+    Jjencode::default().compile_to(out, code)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{decode, eval::Evaluator};
+
+    const CORPUS: [&str; 7] = [
+        "alert(1);",
+        "console.log(\"Hello world!\");",
+        "",
+        "x = '\\\\' + \"\\\"\"\n\t\u{7f}",
+        "document.title = 'caf\u{e9} \u{1f600}'",
+        "\u{0}1 \u{9}5 {ltou}",
+        "return [1e3,0xff]",
+    ];
+
+    #[test]
+    fn round_trip() {
+        for variable in ["$", "_", "jj", "\u{3c9}"] {
+            let encoder = Jjencode::new(variable).unwrap();
+            for code in CORPUS {
+                let encoded = encoder.compile(code);
+                let mut evaluator = Evaluator::default();
+                // Running the code itself fails on anything but the empty string
+                evaluator.eval(&encoded).ok();
+                assert_eq!(
+                    evaluator.code().last().unwrap(),
+                    code,
+                    "{variable} {code:?}"
+                );
+                assert_eq!(decode(&encoded).unwrap(), code, "{variable} {code:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn expressions() {
+        let encoder = Jjencode::new("jj").unwrap();
+        for code in CORPUS.into_iter().chain(["\\\"{}", "fault 0.1"]) {
+            assert_eq!(decode(&encoder.string(code)).unwrap(), code, "{code:?}");
+        }
+        for n in [0, 9, 10, 4096] {
+            assert_eq!(decode(&encoder.number(n)).unwrap(), n.to_string());
+        }
+        let name = format!(
+            "{}[{}]",
+            encoder.function_constructor(),
+            encoder.string("name")
+        );
+        assert_eq!(decode(&name).unwrap(), "Function");
+    }
+
+    #[test]
+    fn symbols() {
+        let encoded = compile("alert(1)");
+        assert!(encoded.starts_with("$=~[];$={___:++$,"));
+        assert!(encoded.ends_with("\"\\\"\")())();"));
+        assert!(encoded.chars().all(|c| "[]()!+{}~:;,.=_$\"\\".contains(c)));
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(Jjencode::default().variable(), "$");
+        for variable in ["", "1a", "a-b", "if", "undefined"] {
+            assert_eq!(
+                Jjencode::new(variable),
+                Err(InvalidVariable(variable.to_owned()))
+            );
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! JSON-like values and their jsf*ck encoding
+//!
+//! Booleans, numbers, strings and arrays are built from the six symbols alone: arrays are joined
+//! with `concat` and numbers other than small integers are parsed from their digits. Only `null`
+//! and objects, which the symbols have no way to express, go through `Function("return …")()`.
+use crate::{eval::number_to_string, JsFuckEncoder};
+
+/// A JSON-like value, see [`JsFuckEncoder::encode_value`]
+///
+/// Unlike JSON, numbers may also be `NaN`, infinite or `-0`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Value {
+    #[default]
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// The properties of an object, in order
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The javascript source of an expression evaluating to the value
+    ///
+    /// # Examples
+    /// ```
+    /// use jsfuckrs::json::Value;
+    /// let value = Value::Object(vec![
+    ///     ("a".to_owned(), Value::Array(vec![Value::Number(-1.5), Value::Null])),
+    ///     ("__proto__".to_owned(), Value::from("\"")),
+    /// ]);
+    /// assert_eq!(value.to_source(), r#"{"a":[-1.5,null],["__proto__"]:"\""}"#);
+    /// ```
+    #[must_use]
+    pub fn to_source(&self) -> String {
+        match self {
+            Self::Null => "null".to_owned(),
+            Self::Bool(b) => b.to_string(),
+            Self::Number(n) => number_source(*n),
+            Self::String(s) => quote(s),
+            Self::Array(values) => format!(
+                "[{}]",
+                values
+                    .iter()
+                    .map(Self::to_source)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            Self::Object(properties) => format!(
+                "{{{}}}",
+                properties
+                    .iter()
+                    .map(|(key, value)| {
+                        // A plain `__proto__` key would set the prototype instead
+                        if key == "__proto__" {
+                            format!("[{}]:{}", quote(key), value.to_source())
+                        } else {
+                            format!("{}:{}", quote(key), value.to_source())
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Self::Number(n)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Self::String(s.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Self::String(s)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(values: Vec<Value>) -> Self {
+        Self::Array(values)
+    }
+}
+
+/// Javascript's `String(n)`, except that it keeps the sign of `-0`
+fn number_source(n: f64) -> String {
+    if n == 0.0 && n.is_sign_negative() {
+        "-0".to_owned()
+    } else {
+        number_to_string(n)
+    }
+}
+
+/// A javascript string literal of `s`
+fn quote(s: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted += "\\n",
+            '\r' => quoted += "\\r",
+            // Line terminators inside string literals are only allowed since ES2019
+            '\u{2028}' | '\u{2029}' => quoted += &format!("\\u{:04x}", u32::from(c)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// See [`JsFuckEncoder::encode_value`]
+pub(crate) fn encode<E>(encoder: &E, value: &Value) -> String
+where
+    E: JsFuckEncoder + ?Sized,
+{
+    match value {
+        Value::Bool(false) => "![]".to_owned(),
+        Value::Bool(true) => "!![]".to_owned(),
+        Value::Number(n) => number(encoder, *n),
+        // Encoders turn the empty string into no code at all
+        Value::String(s) if s.is_empty() => "[]+[]".to_owned(),
+        Value::String(s) => encoder.string(s),
+        Value::Array(values) => {
+            let concat = encoder.string("concat");
+            // Arrays are wrapped so `concat` adds them as elements instead of spreading them
+            values
+                .iter()
+                .map(|value| format!("[{}]", encode(encoder, value)))
+                .reduce(|array, value| format!("{array}[{concat}]({value})"))
+                .unwrap_or_else(|| "[]".to_owned())
+        }
+        Value::Null | Value::Object(_) => format!(
+            "{}({})()",
+            encoder.function_constructor(),
+            encoder.string(&format!("return {}", value.to_source()))
+        ),
+    }
+}
+
+fn number<E>(encoder: &E, n: f64) -> String
+where
+    E: JsFuckEncoder + ?Sized,
+{
+    if n.is_nan() {
+        return "+[![]]".to_owned();
+    }
+    let parsed = format!("+({})", encoder.string(&number_source(n)));
+    // Backends build large integers slowly, and they are no shorter than the parsed digits
+    let is_small = n.fract() == 0.0 && (0.0..1000.0).contains(&n);
+    if is_small && n.is_sign_positive() {
+        let direct = encoder.number(n as usize);
+        if direct.len() <= parsed.len() {
+            return direct;
+        }
+    }
+    parsed
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{eval, is_jsfuck, Backend};
+
+    /// What the evaluator should produce for `value`
+    fn expected(value: &Value) -> eval::Value {
+        match value {
+            Value::Null => eval::Value::Null,
+            Value::Bool(b) => eval::Value::Bool(*b),
+            Value::Number(n) => eval::Value::Number(*n),
+            Value::String(s) => eval::Value::String(s.encode_utf16().collect()),
+            Value::Array(values) => eval::Value::Array(values.iter().map(expected).collect()),
+            Value::Object(properties) => {
+                let mut plain: Vec<(eval::JsString, eval::Value)> = vec![];
+                for (key, value) in properties {
+                    let key = key.encode_utf16().collect();
+                    match plain.iter_mut().find(|(k, _)| *k == key) {
+                        Some((_, old)) => *old = expected(value),
+                        None => plain.push((key, expected(value))),
+                    }
+                }
+                eval::Value::Object(eval::Object::Plain(plain))
+            }
+        }
+    }
+
+    fn corpus() -> Vec<Value> {
+        let numbers = [
+            0.0,
+            -0.0,
+            1.0,
+            10.0,
+            1234.0,
+            -1.0,
+            0.5,
+            -2.25,
+            1e21,
+            1.5e-7,
+            9007199254740993.0,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::MAX,
+        ];
+        let mut values = vec![
+            Value::Null,
+            Value::from(true),
+            Value::from(false),
+            Value::from(""),
+            Value::from("config"),
+            Value::from("\"quoted\\\"\n\u{2028}caf\u{e9} \u{1f600}"),
+            Value::Array(vec![]),
+            Value::Array(vec![Value::Array(vec![])]),
+            Value::Array(vec![
+                Value::from(1.0),
+                Value::Array(vec![Value::from("a"), Value::Null]),
+                Value::from(""),
+            ]),
+            Value::Object(vec![]),
+            Value::Object(vec![
+                ("name".to_owned(), Value::from("jsf*ck")),
+                ("__proto__".to_owned(), Value::Array(vec![])),
+                ("1".to_owned(), Value::from(-0.0)),
+                ("name".to_owned(), Value::from(false)),
+                (
+                    "nested".to_owned(),
+                    Value::Object(vec![("".to_owned(), Value::Null)]),
+                ),
+            ]),
+        ];
+        values.extend(numbers.map(Value::from));
+        values
+    }
+
+    #[test]
+    fn round_trip() {
+        for &backend in Backend::ALL {
+            for value in corpus() {
+                let encoded = backend.encode_value(&value);
+                assert!(
+                    !backend.is_strict() || is_jsfuck(&encoded),
+                    "{backend} {value:?}"
+                );
+                let evaluated = eval::eval(&encoded).unwrap();
+                assert_eq!(evaluated, expected(&value), "{backend} {value:?}");
+                if let (eval::Value::Number(a), Value::Number(b)) = (&evaluated, &value) {
+                    assert_eq!(a.is_sign_negative(), b.is_sign_negative(), "{backend} {b}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nan() {
+        for &backend in Backend::ALL {
+            let encoded = backend.encode_value(&Value::Number(f64::NAN));
+            assert!(matches!(eval::eval(&encoded).unwrap(), eval::Value::Number(n) if n.is_nan()));
+        }
+    }
+
+    #[test]
+    fn without_function() {
+        let array = Value::Array(vec![
+            Value::from(-0.5),
+            Value::from(true),
+            Value::from("s"),
+            Value::Array(vec![Value::from(3.0)]),
+        ]);
+        for &backend in Backend::ALL {
+            let mut evaluator = eval::Evaluator::default();
+            evaluator.eval(&backend.encode_value(&array)).unwrap();
+            // Some characters are derived through `Function`, but never the value itself
+            assert!(
+                !evaluator.code().iter().any(|code| code.contains("-0.5")),
+                "{backend}"
+            );
+        }
+        #[cfg(feature = "lbp")]
+        assert_eq!(
+            Backend::Lbp.encode_value(&Value::from(2.0)),
+            Backend::Lbp.number(2)
+        );
+    }
+
+    #[test]
+    fn source() {
+        assert_eq!(Value::Number(-0.0).to_source(), "-0");
+        assert_eq!(Value::Number(1e21).to_source(), "1e+21");
+        assert_eq!(Value::Number(f64::NEG_INFINITY).to_source(), "-Infinity");
+        assert_eq!(Value::from("\u{2029}\r").to_source(), "\"\\u2029\\r\"");
+        assert_eq!(Value::Object(vec![]).to_source(), "{}");
+    }
+}
@@ -0,0 +1,939 @@
+//! Encoding with any alphabet of symbols
+//!
+//! [`TABLE`] declares the primitives the encodings are built from, the constants of
+//! [`building_blocks`](super::building_blocks) among them, along with the rules coercing and
+//! combining them. Each entry is javascript where `{0}` and `{1}` stand for operands, `{"…"}` for
+//! an encoded string and `{Function}` for the `Function` constructor. An [`Encoder`] keeps the
+//! entries whose symbols are all in its alphabet and whose features its [`Target`] supports,
+//! checks the rules with the [evaluator](crate::eval) since operands can change how they parse,
+//! and then searches for the shortest derivation of every character. The encodings of
+//! [`building_blocks`](super::building_blocks) and [`strict`](super::strict) are the ones of the
+//! symbols they write.
+use std::collections::{BTreeMap, BTreeSet};
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+use super::building_blocks::{ARROW_FN, FALSE, INFINITY, NAN, OBJECT, ONE, TRUE, UNDEFINED, ZERO};
+use super::target::{Feature, Target};
+use crate::{eval::eval, json};
+
+/// The six symbols of jsf*ck
+pub const JSFUCK: &str = "[]()!+";
+
+/// The largest factor tried when writing a number as a product
+const MAX_FACTOR: usize = 1 << 16;
+
+/// What an [`Entry`] of the [`TABLE`] builds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// The number `0`
+    Zero,
+    /// The number `1`
+    One,
+    /// A value counting as `1` in a sum of two or more, like `true`
+    Unit,
+    /// The sum of the numbers `{0}` and `{1}`
+    Add,
+    /// The product of the numbers `{0}` and `{1}`
+    Multiply,
+    /// `{0}`, binding tighter than any operator
+    Group,
+    /// The string of `{0}`
+    ToString,
+    /// The property `{1}` of the group `{0}`
+    Index,
+    /// The concatenation of the strings `{0}` and `{1}`
+    Concat,
+    /// The digits of `{0}` followed by those of the number `{1}`
+    Append,
+    /// The number whose digits are `{0}`
+    Parse,
+    /// The function `{0}` called without arguments
+    Call,
+    /// The function `{0}` called with `{1}`
+    CallWith,
+    /// The number `{0}` written in the radix `{1}`
+    Radix,
+    /// A function, whose `constructor` is `Function`
+    Function,
+    /// A string starting with the given text
+    Text(&'static str),
+}
+
+/// A piece of javascript and what it builds, see [`TABLE`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub role: Role,
+    pub template: &'static str,
+    /// What the target has to support beyond ES5
+    feature: Option<Feature>,
+}
+
+const fn entry(role: Role, template: &'static str) -> Entry {
+    Entry {
+        role,
+        template,
+        feature: None,
+    }
+}
+
+/// An entry only kept when the target supports `feature`
+const fn since(feature: Feature, role: Role, template: &'static str) -> Entry {
+    Entry {
+        role,
+        template,
+        feature: Some(feature),
+    }
+}
+
+/// The primitives and rules of the encodings, the earlier entries of a role preferred
+pub const TABLE: &[Entry] = &[
+    entry(Role::Zero, ZERO),
+    entry(Role::One, ONE),
+    entry(Role::One, "+!+[]"),
+    entry(Role::One, "++[[]][+[]]"),
+    entry(Role::Unit, "!![]"),
+    entry(Role::Unit, "!+[]"),
+    // Only a space keeps `+!![]` from being incremented when added to
+    entry(Role::Add, "{0} + {1}"),
+    entry(Role::Add, "{0}+{1}"),
+    entry(Role::Add, "{0}+({1})"),
+    entry(Role::Add, "{0}+[{1}][+[]]"),
+    entry(Role::Multiply, "({0})*({1})"),
+    entry(Role::Group, "({0})"),
+    entry(Role::Group, "[{0}][+[]]"),
+    entry(Role::ToString, "{0}+[]"),
+    entry(Role::Index, "{0}[{1}]"),
+    entry(Role::Concat, "{0}+{1}"),
+    entry(Role::Append, "{0}+[{1}]"),
+    entry(Role::Parse, "+({0})"),
+    entry(Role::Parse, "+[{0}]"),
+    entry(Role::Call, "{0}()"),
+    since(Feature::Es2015, Role::Call, "{0}``"),
+    entry(Role::CallWith, "{0}({1})"),
+    entry(Role::Radix, "{0}[{\"toString\"}]({1})"),
+    since(Feature::Es2015, Role::Function, ARROW_FN),
+    since(Feature::Es2019, Role::Function, "[][{\"flat\"}]"),
+    since(Feature::Es2015, Role::Function, "[][{\"find\"}]"),
+    entry(Role::Function, "[][{\"filter\"}]"),
+    entry(Role::Text("[object Object]"), OBJECT),
+    entry(Role::Text("NaN"), NAN),
+    entry(Role::Text("NaN"), super::strict::NAN),
+    entry(Role::Text("NaN"), "+[][[]]+[]"),
+    entry(Role::Text("Infinity"), INFINITY),
+    entry(Role::Text("Infinity"), "+({\"1e1000\"})+[]"),
+    entry(Role::Text("Infinity"), "+[{\"1e1000\"}]+[]"),
+    entry(Role::Text("1.1e+101"), "+({\"11e100\"})+[]"),
+    entry(Role::Text("1.1e+101"), "+[{\"11e100\"}]+[]"),
+    entry(Role::Text("false"), FALSE),
+    entry(Role::Text("true"), TRUE),
+    entry(Role::Text("undefined"), UNDEFINED),
+    since(Feature::Es2015, Role::Text("()=>{}"), "(()=>{})+[]"),
+    entry(Role::Text("/\\\\/"), "/\\\\/+[]"),
+    // `RegExp("/")` is displayed as `/\//`
+    entry(
+        Role::Text("/\\//"),
+        "({Function}({\"return/\"}+(![])+{\"/\"})())[{\"constructor\"}]({\"/\"})+[]",
+    ),
+    since(
+        Feature::Es2015,
+        Role::Text("function find()"),
+        "[][{\"find\"}]+[]",
+    ),
+    since(
+        Feature::Es2019,
+        Role::Text("function flat()"),
+        "[][{\"flat\"}]+[]",
+    ),
+    since(
+        Feature::V8Source,
+        Role::Text("function flat() { [native code] }"),
+        "[][{\"flat\"}]+[]",
+    ),
+    entry(Role::Text("function filter()"), "[][{\"filter\"}]+[]"),
+    entry(
+        Role::Text("function String()"),
+        "[]+([]+[])[{\"constructor\"}]",
+    ),
+    entry(
+        Role::Text("function String()"),
+        "[]+[[]+[]][+[]][{\"constructor\"}]",
+    ),
+    entry(
+        Role::Text("function Number()"),
+        "[]+(+[])[{\"constructor\"}]",
+    ),
+    entry(
+        Role::Text("function Number()"),
+        "[]+[+[]][+[]][{\"constructor\"}]",
+    ),
+    entry(
+        Role::Text("function Boolean()"),
+        "[]+(![])[{\"constructor\"}]",
+    ),
+    entry(
+        Role::Text("function Boolean()"),
+        "[]+[![]][+[]][{\"constructor\"}]",
+    ),
+    entry(Role::Text("function Array()"), "[]+[][{\"constructor\"}]"),
+    entry(Role::Text("function Function()"), "[]+{Function}"),
+    entry(
+        Role::Text("function RegExp()"),
+        "[]+(/-/)[{\"constructor\"}]",
+    ),
+    entry(
+        Role::Text("function RegExp()"),
+        "[]+({Function}({\"return/\"}+(![])+{\"/\"})())[{\"constructor\"}]",
+    ),
+    // Functions created by `Function` are displayed the same way everywhere
+    since(
+        Feature::Es2019,
+        Role::Text("function anonymous(\n) {\n\n}"),
+        "[]+{Function}()",
+    ),
+    since(
+        Feature::Es2015,
+        Role::Text("[object Array Iterator]"),
+        "[][{\"entries\"}]()+[]",
+    ),
+    entry(Role::Text("<i></i>"), "([]+[])[{\"italics\"}]()"),
+    entry(
+        Role::Text("<font color=\"undefined\"></font>"),
+        "([]+[])[{\"fontcolor\"}]()",
+    ),
+    entry(Role::Text(","), "[[]][{\"concat\"}]([[]])+[]"),
+    entry(
+        Role::Text("/(?:)/"),
+        "{Function}({\"return RegExp\"})()()+[]",
+    ),
+    entry(
+        Role::Text("%5C"),
+        "{Function}({\"return escape\"})()({\"\\\\\"})",
+    ),
+    entry(
+        Role::Text("%5B"),
+        "{Function}({\"return escape\"})()({\"[\"})",
+    ),
+    since(
+        Feature::Dom,
+        Role::Text("[object HTMLDocument]"),
+        "{Function}({\"return document\"})()+[]",
+    ),
+    since(
+        Feature::Dom,
+        Role::Text("[object Window]"),
+        "{Function}({\"return self\"})()+[]",
+    ),
+];
+
+#[derive(Error, Diagnostic, Debug, Clone, PartialEq, Eq)]
+#[error("The alphabet {alphabet:?} can not produce {}", list(.chars))]
+#[diagnostic(help("add symbols to the alphabet, like the six of jsf*ck `[]()!+`"))]
+pub struct Unproducible {
+    pub alphabet: String,
+    /// The characters of the input that could not be derived, or the symbols some syntax needs
+    pub chars: Vec<char>,
+}
+
+fn list(chars: &[char]) -> String {
+    chars
+        .iter()
+        .map(|c| format!("{c:?}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// How a character is derived
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Derivation {
+    /// The javascript the character is read from, like `"false"[0]`
+    pub source: String,
+    /// The encoded expression
+    pub encoded: String,
+}
+
+impl Derivation {
+    /// The length of the encoded expression
+    #[must_use]
+    pub fn cost(&self) -> usize {
+        self.encoded.len()
+    }
+}
+
+/// Fills in the placeholders of `template`, or `None` when one of them is missing
+fn expand(
+    template: &str,
+    operands: &[&str],
+    string: &dyn Fn(&str) -> Option<String>,
+    function: Option<&str>,
+) -> Option<String> {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        rest = &rest[open..];
+        if let Some(after) = rest.strip_prefix("{}") {
+            out.push_str("{}");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("{Function}") {
+            out.push_str(function?);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("{\"") {
+            // A string literal, where `\` escapes the next character
+            let mut literal = String::new();
+            let mut chars = after.char_indices();
+            let end = loop {
+                match chars.next()? {
+                    (_, '\\') => literal.push(chars.next()?.1),
+                    (i, '"') if after[i..].starts_with("\"}") => break i + 2,
+                    (_, c) => literal.push(c),
+                }
+            };
+            out.push_str(&string(&literal)?);
+            rest = &after[end..];
+        } else {
+            let close = rest.find('}')?;
+            let operand = rest[1..close].parse::<usize>().ok()?;
+            out.push_str(operands.get(operand)?);
+            rest = &rest[close + 1..];
+        }
+    }
+    out.push_str(rest);
+    Some(out)
+}
+
+/// Fills in `template` with javascript literals, to check how it evaluates
+fn apply(template: &str, operands: &[&str]) -> String {
+    let literal = |s: &str| Some(json::Value::from(s).to_source());
+    expand(template, operands, &literal, Some("Function")).unwrap_or_default()
+}
+
+/// The symbols `template` writes itself
+fn symbols(template: &str) -> String {
+    expand(template, &["", ""], &|_| Some(String::new()), Some("")).unwrap_or_default()
+}
+
+fn evaluates_to(code: &str, expected: &str) -> bool {
+    eval(code).is_ok_and(|value| value.to_string() == expected)
+}
+
+/// The templates of the rules an alphabet allows, checked to evaluate as they should
+#[derive(Debug, Clone, Copy, Default)]
+struct Rules {
+    zero: Option<&'static str>,
+    one: Option<&'static str>,
+    unit: Option<&'static str>,
+    add: Option<&'static str>,
+    multiply: Option<&'static str>,
+    group: Option<&'static str>,
+    to_string: Option<&'static str>,
+    index: Option<&'static str>,
+    concat: Option<&'static str>,
+    append: Option<&'static str>,
+    parse: Option<&'static str>,
+    call: Option<&'static str>,
+    call_with: Option<&'static str>,
+    radix: Option<&'static str>,
+}
+
+/// Derives numbers, strings and programs from the symbols of an alphabet alone
+///
+/// # Examples
+/// ```
+/// use jsfuckrs::lbp::alphabet::Encoder;
+/// let encoder = Encoder::new("[]+!");
+/// assert_eq!(encoder.number(2).unwrap(), "!![]+!![]");
+/// assert!(encoder.string("alert").unwrap().chars().all(|c| "[]+!".contains(c)));
+/// let err = encoder.compile("alert(1)").unwrap_err();
+/// assert_eq!(err.chars, ['(', ')']);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Encoder {
+    alphabet: BTreeSet<char>,
+    target: Target,
+    rules: Rules,
+    derived: BTreeMap<char, Derivation>,
+}
+
+impl Encoder {
+    #[must_use]
+    pub fn new(alphabet: &str) -> Self {
+        Self::for_target(alphabet, Target::default())
+    }
+
+    /// Like [`new`](Self::new), keeping only the entries whose features `target` supports
+    ///
+    /// # Examples
+    /// ```
+    /// use jsfuckrs::lbp::{alphabet::Encoder, Target};
+    /// let source = |target| {
+    ///     let encoder = Encoder::for_target("[]()!+", target);
+    ///     encoder.derivations()[&'{'].source.clone()
+    /// };
+    /// assert_eq!(source(Target::Node), "\"function flat() { [native code] }\"[16]");
+    /// // Neither `flat` nor the source of anonymous functions predate ES2015
+    /// assert_eq!(source(Target::Es5), "unescape(\"%7b\")");
+    /// ```
+    #[must_use]
+    pub fn for_target(alphabet: &str, target: Target) -> Self {
+        let mut encoder = Self {
+            alphabet: alphabet.chars().collect(),
+            target,
+            rules: Rules::default(),
+            derived: BTreeMap::new(),
+        };
+        encoder.rules = encoder.rules();
+        encoder.search();
+        encoder
+    }
+
+    /// The symbols the output is restricted to
+    #[must_use]
+    pub fn alphabet(&self) -> String {
+        self.alphabet.iter().collect()
+    }
+
+    /// The engines the output has to run on
+    #[must_use]
+    pub const fn target(&self) -> Target {
+        self.target
+    }
+
+    /// The shortest derivation found for each character that can be produced
+    #[must_use]
+    pub const fn derivations(&self) -> &BTreeMap<char, Derivation> {
+        &self.derived
+    }
+
+    /// Whether `c` is produced without building it from its character code
+    #[must_use]
+    pub fn is_mapped(&self, c: char) -> bool {
+        self.derived.contains_key(&c)
+    }
+
+    /// Encodes the number `n`
+    ///
+    /// # Errors
+    /// When the alphabet cannot write numbers
+    pub fn number(&self, n: usize) -> Result<String, Unproducible> {
+        self.number_expr(n).ok_or_else(|| {
+            let role = match n {
+                0 => Role::Zero,
+                1 => Role::One,
+                _ => Role::Add,
+            };
+            self.error(self.missing_symbols(role))
+        })
+    }
+
+    /// Encodes `s` into an expression evaluating to it, which is empty for the empty string
+    ///
+    /// # Errors
+    /// When some characters of `s` can be neither derived nor built from their character code
+    pub fn string(&self, s: &str) -> Result<String, Unproducible> {
+        self.string_expr(s).map_err(|chars| self.error(chars))
+    }
+
+    /// Encodes `code` into an expression running it
+    ///
+    /// # Errors
+    /// When some characters of `code` cannot be produced, or functions cannot be created or called
+    pub fn compile(&self, code: &str) -> Result<String, Unproducible> {
+        let mut missing = vec![];
+        let function = self.function(&|s| self.derived_string(s));
+        if function.is_none() {
+            missing.extend(self.underived("constructor"));
+            if missing.is_empty() {
+                missing.extend(self.underived("flat"));
+            }
+        }
+        if self.rules.call.is_none() {
+            missing.extend(self.missing_symbols(Role::Call));
+        }
+        if self.rules.call_with.is_none() {
+            missing.extend(self.missing_symbols(Role::CallWith));
+        }
+        let string = self
+            .string_expr(code)
+            .map_err(|chars| missing.extend(chars));
+        match (function, self.rules.call, self.rules.call_with, string) {
+            (Some(function), Some(call), Some(call_with), Ok(string)) if missing.is_empty() => {
+                Ok(apply(call, &[&apply(call_with, &[&function, &string])]))
+            }
+            _ => Err(self.error(missing)),
+        }
+    }
+
+    fn error(&self, mut chars: Vec<char>) -> Unproducible {
+        chars.sort_unstable();
+        chars.dedup();
+        Unproducible {
+            alphabet: self.alphabet(),
+            chars,
+        }
+    }
+
+    fn allows(&self, entry: &Entry) -> bool {
+        entry
+            .feature
+            .is_none_or(|feature| self.target.supports(feature))
+            && symbols(entry.template)
+                .chars()
+                .all(|c| self.alphabet.contains(&c))
+    }
+
+    /// The templates of `role` the alphabet allows
+    fn allowed(&self, role: Role) -> impl Iterator<Item = &'static str> + Clone + '_ {
+        TABLE
+            .iter()
+            .filter(move |entry| entry.role == role && self.allows(entry))
+            .map(|entry| entry.template)
+    }
+
+    /// The symbols missing from the alphabet for the preferred template of `role`
+    fn missing_symbols(&self, role: Role) -> Vec<char> {
+        TABLE
+            .iter()
+            .find(|entry| entry.role == role)
+            .map(|entry| {
+                symbols(entry.template)
+                    .chars()
+                    .filter(|c| !self.alphabet.contains(c))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn underived(&self, s: &str) -> Vec<char> {
+        s.chars().filter(|c| !self.is_mapped(*c)).collect()
+    }
+
+    /// Picks the first template of each role that evaluates as it should, given the ones before
+    fn rules(&self) -> Rules {
+        let mut rules = Rules {
+            zero: self.allowed(Role::Zero).find(|t| evaluates_to(t, "0")),
+            one: self.allowed(Role::One).find(|t| evaluates_to(t, "1")),
+            ..Rules::default()
+        };
+        let (Some(zero), Some(one)) = (rules.zero, rules.one) else {
+            return rules;
+        };
+        // `1` itself is preferred as the unit, although few sums can add it
+        let units = self.allowed(Role::One).chain(self.allowed(Role::Unit));
+        let Some((unit, add)) = self
+            .allowed(Role::Add)
+            .flat_map(|add| units.clone().map(move |unit| (unit, add)))
+            .find(|&(unit, add)| {
+                let two = apply(add, &[unit, unit]);
+                evaluates_to(&two, "2") && evaluates_to(&apply(add, &[&two, unit]), "3")
+            })
+        else {
+            return rules;
+        };
+        rules.unit = Some(unit);
+        rules.add = Some(add);
+        let two = apply(add, &[unit, unit]);
+        let three = apply(add, &[&two, unit]);
+        rules.multiply = self
+            .allowed(Role::Multiply)
+            .find(|t| evaluates_to(&apply(t, &[&two, &three]), "6"));
+        rules.to_string = self
+            .allowed(Role::ToString)
+            .find(|t| evaluates_to(&apply(t, &[&three]), "3"));
+        rules.call = self
+            .allowed(Role::Call)
+            .find(|t| evaluates_to(&apply(t, &["String"]), ""));
+        rules.call_with = self
+            .allowed(Role::CallWith)
+            .find(|t| evaluates_to(&apply(t, &["String", &three]), "3"));
+        let (append, parse) = self
+            .allowed(Role::Append)
+            .flat_map(|append| self.allowed(Role::Parse).map(move |parse| (append, parse)))
+            .find(|&(append, parse)| {
+                evaluates_to(&apply(parse, &[&apply(append, &[one, zero])]), "10")
+                    && evaluates_to(&apply(parse, &[&apply(append, &[&two, zero])]), "20")
+            })
+            .unzip();
+        (rules.append, rules.parse) = (append, parse);
+        let Some(to_string) = rules.to_string else {
+            return rules;
+        };
+        let string = apply(to_string, &[&three]);
+        let (group, index) = self
+            .allowed(Role::Group)
+            .flat_map(|group| self.allowed(Role::Index).map(move |index| (group, index)))
+            .find(|&(group, index)| {
+                evaluates_to(&apply(index, &[&apply(group, &[&string]), zero]), "3")
+            })
+            .unzip();
+        (rules.group, rules.index) = (group, index);
+        let (Some(group), Some(index)) = (group, index) else {
+            return rules;
+        };
+        let char = apply(index, &[&apply(group, &[&string]), zero]);
+        rules.concat = self
+            .allowed(Role::Concat)
+            .find(|t| evaluates_to(&apply(t, &[&char, &char]), "33"));
+        rules.radix = self
+            .allowed(Role::Radix)
+            .find(|t| evaluates_to(&apply(t, &[&apply(group, &["17"]), "18"]), "h"));
+        rules
+    }
+
+    /// `n` as the shortest of its sum, its digits and the products of two factors
+    fn number_expr(&self, n: usize) -> Option<String> {
+        let plain = self.plain_number(n)?;
+        let Some(multiply) = self.rules.multiply else {
+            return Some(plain);
+        };
+        // Trial division stops at MAX_FACTOR so that huge numbers stay quick to encode
+        let products = (2..=MAX_FACTOR)
+            .take_while(|&factor| factor <= n / factor)
+            .filter(|&factor| n.is_multiple_of(factor))
+            .filter_map(|factor| {
+                let (a, b) = (self.plain_number(factor)?, self.plain_number(n / factor)?);
+                Some(apply(multiply, &[&a, &b]))
+            });
+        Some(products.fold(plain, |shortest, product| {
+            if product.len() < shortest.len() {
+                product
+            } else {
+                shortest
+            }
+        }))
+    }
+
+    /// `n` as a sum of units, or from its decimal digits
+    fn plain_number(&self, n: usize) -> Option<String> {
+        let rules = &self.rules;
+        match n {
+            0 => return rules.zero.map(str::to_owned),
+            1 => return rules.one.map(str::to_owned),
+            _ => {}
+        }
+        let (unit, add) = (rules.unit?, rules.add?);
+        let digits = (n >= 10).then(|| self.digits(n)).flatten();
+        // Sums of units only beat digits below ten, and grow quadratically to build
+        if let Some(digits) = digits {
+            return Some(digits);
+        }
+        let mut sum = unit.to_owned();
+        for _ in 1..n {
+            sum = apply(add, &[&sum, unit]);
+        }
+        Some(sum)
+    }
+
+    /// `n` parsed from its decimal digits
+    fn digits(&self, n: usize) -> Option<String> {
+        let (append, parse) = (self.rules.append?, self.rules.parse?);
+        let digits = n.to_string();
+        let mut digits = digits.bytes().map(|d| usize::from(d - b'0'));
+        let mut out = self.plain_number(digits.next()?)?;
+        for digit in digits {
+            out = apply(append, &[&out, &self.plain_number(digit)?]);
+        }
+        Some(apply(parse, &[&out]))
+    }
+
+    /// The character at `i` in the string `text` evaluates to
+    fn char_at(&self, text: &str, i: usize) -> Option<String> {
+        let group = apply(self.rules.group?, &[text]);
+        Some(apply(self.rules.index?, &[&group, &self.number_expr(i)?]))
+    }
+
+    /// `s` concatenated from the characters derived so far
+    fn derived_string(&self, s: &str) -> Option<String> {
+        let mut chars = s.chars();
+        let mut out = self.derived.get(&chars.next()?)?.encoded.clone();
+        for c in chars {
+            out = apply(self.rules.concat?, &[&out, &self.derived.get(&c)?.encoded]);
+        }
+        Some(out)
+    }
+
+    /// The shortest `Function` constructor, given how to encode strings
+    fn function(&self, string: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+        let constructor = string("constructor")?;
+        self.allowed(Role::Function)
+            .filter_map(|template| {
+                let function = apply(self.rules.group?, &[&expand(template, &[], string, None)?]);
+                Some(apply(self.rules.index?, &[&function, &constructor]))
+            })
+            .min_by_key(String::len)
+    }
+
+    /// The `unescape` function
+    fn unescape(&self, string: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+        let function = self.function(string)?;
+        let body = apply(
+            self.rules.call_with?,
+            &[&function, &string("return unescape")?],
+        );
+        Some(apply(self.rules.call?, &[&body]))
+    }
+
+    fn string_expr(&self, s: &str) -> Result<String, Vec<char>> {
+        let unescape = self.unescape(&|s| self.derived_string(s));
+        let mut parts = vec![];
+        let mut missing = vec![];
+        // Runs of characters that are not derived are built from their character codes at once
+        let mut run = String::new();
+        let mut flush = |run: &mut String, parts: &mut Vec<String>| {
+            if run.is_empty() {
+                return;
+            }
+            let escaped = run
+                .encode_utf16()
+                .map(|unit| format!("%u{unit:04x}"))
+                .collect::<String>();
+            let code = unescape
+                .as_ref()
+                .zip(self.rules.call_with)
+                .and_then(|(f, call)| Some(apply(call, &[f, &self.derived_string(&escaped)?])));
+            match code {
+                Some(code) => parts.push(code),
+                None => missing.extend(run.chars()),
+            }
+            run.clear();
+        };
+        for c in s.chars() {
+            match self.derived.get(&c) {
+                Some(derivation) => {
+                    flush(&mut run, &mut parts);
+                    parts.push(derivation.encoded.clone());
+                }
+                None => run.push(c),
+            }
+        }
+        flush(&mut run, &mut parts);
+        if !missing.is_empty() {
+            return Err(missing);
+        }
+        let mut parts = parts.into_iter();
+        let mut out = parts.next().unwrap_or_default();
+        for part in parts {
+            let Some(concat) = self.rules.concat else {
+                return Err(self.missing_symbols(Role::Concat));
+            };
+            out = apply(concat, &[&out, &part]);
+        }
+        Ok(out)
+    }
+
+    /// Every character some entry or rule produces, derived from the characters found so far
+    fn candidates(&self) -> Vec<(char, Derivation)> {
+        let mut candidates = vec![];
+        let mut offer = |c: char, source: String, encoded: String| {
+            candidates.push((c, Derivation { source, encoded }));
+        };
+        let string = |s: &str| self.derived_string(s);
+        let function = self.function(&string);
+        for entry in TABLE {
+            let Role::Text(text) = entry.role else {
+                continue;
+            };
+            if !self.allows(entry) {
+                continue;
+            }
+            let Some(code) = expand(entry.template, &[], &string, function.as_deref()) else {
+                continue;
+            };
+            for (i, c) in text.chars().enumerate() {
+                if let Some(code) = self.char_at(&code, i) {
+                    offer(c, format!("{text:?}[{i}]"), code);
+                }
+            }
+        }
+        for (d, c) in ('0'..='9').enumerate() {
+            let code = self.rules.to_string.zip(self.number_expr(d));
+            if let Some((group, (to_string, n))) = self.rules.group.zip(code) {
+                offer(
+                    c,
+                    format!("{d}+[]"),
+                    apply(group, &[&apply(to_string, &[&n])]),
+                );
+            }
+        }
+        // The letters are the last digits of the radices up to 36, like `(10)["toString"](11)`
+        if let Some((radix, group)) = self.rules.radix.zip(self.rules.group) {
+            for (n, c) in ('a'..='z').enumerate().map(|(i, c)| (i + 10, c)) {
+                let (Some(digit), Some(base)) = (self.number_expr(n), self.number_expr(n + 1))
+                else {
+                    continue;
+                };
+                let digit = apply(group, &[&digit]);
+                if let Some(code) = expand(radix, &[&digit, &base], &string, None) {
+                    offer(c, format!("({n}).toString({})", n + 1), code);
+                }
+            }
+        }
+        let unescape = self.unescape(&string).zip(self.rules.call_with);
+        if let Some((unescape, call_with)) = unescape {
+            for c in ' '..='~' {
+                let escaped = format!("%{:02x}", u32::from(c));
+                if let Some(code) = string(&escaped) {
+                    let source = format!("unescape({escaped:?})");
+                    offer(c, source, apply(call_with, &[&unescape, &code]));
+                }
+            }
+        }
+        candidates
+    }
+
+    /// Derives characters until none of them gets any cheaper
+    ///
+    /// Derivations depend on other characters, so the candidates are tried again until none of
+    /// them makes a character cheaper.
+    fn search(&mut self) {
+        loop {
+            let mut cheaper = BTreeMap::new();
+            for (c, derivation) in self.candidates() {
+                let best = cheaper.get(&c).or_else(|| self.derived.get(&c));
+                if best.is_none_or(|best: &Derivation| derivation.cost() < best.cost()) {
+                    cheaper.insert(c, derivation);
+                }
+            }
+            if cheaper.is_empty() {
+                return;
+            }
+            self.derived.extend(cheaper);
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{eval::Evaluator, lbp::strict};
+
+    #[test]
+    fn table() {
+        for entry in TABLE {
+            // The evaluator has no DOM
+            if entry.feature == Some(Feature::Dom) {
+                continue;
+            }
+            let code = apply(entry.template, &[]);
+            match entry.role {
+                Role::Text(text) => {
+                    let value = eval(&code).unwrap().to_string();
+                    assert!(value.starts_with(text), "{code} is {value:?}");
+                }
+                Role::Function => assert!(evaluates_to(
+                    &format!("({code})[\"constructor\"]===Function"),
+                    "true"
+                )),
+                _ => {}
+            }
+        }
+        assert_eq!(symbols("[][{\"flat\"}]+{Function}"), "[][]+");
+        assert_eq!(symbols(ARROW_FN), "()=>{}");
+    }
+
+    #[test]
+    fn jsfuck() {
+        let encoder = Encoder::new(JSFUCK);
+        assert_eq!(encoder.number(1).unwrap(), strict::number(1));
+        assert_eq!(encoder.number(2).unwrap(), strict::number(2));
+        assert_eq!(
+            eval(&encoder.number(1234).unwrap()).unwrap().to_string(),
+            "1234"
+        );
+        for c in ' '..='~' {
+            let code = &encoder.derivations()[&c].encoded;
+            assert_eq!(eval(code).unwrap().to_string(), c.to_string(), "{c:?}");
+        }
+        for code in ["alert(1)", "", "caf\u{e9} \u{1f600}"] {
+            let encoded = encoder.compile(code).unwrap();
+            assert!(encoded.chars().all(|c| JSFUCK.contains(c)), "{code:?}");
+            let mut evaluator = Evaluator::default();
+            evaluator.eval(&encoded).ok();
+            assert_eq!(evaluator.code().last().unwrap(), code);
+        }
+        assert_eq!(encoder.string("").unwrap(), "");
+    }
+
+    #[test]
+    fn search_is_settled() {
+        for target in Target::ALL {
+            for alphabet in [JSFUCK, "[]()!+{}=>/\\-* "] {
+                let encoder = Encoder::for_target(alphabet, target);
+                for (c, derivation) in encoder.candidates() {
+                    let chosen = &encoder.derivations()[&c];
+                    assert!(derivation.cost() >= chosen.cost(), "{c:?} {derivation:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn targets() {
+        let flat = "\"function flat() { [native code] }\"[16]";
+        assert_eq!(
+            Encoder::for_target(JSFUCK, Target::Node).derivations()[&'{'].source,
+            flat
+        );
+        assert_ne!(Encoder::new(JSFUCK).derivations()[&'{'].source, flat);
+        let es5 = Encoder::for_target(JSFUCK, Target::Es5);
+        for (c, derivation) in es5.derivations() {
+            for newer in ["Array Iterator", "flat", "find", "anonymous"] {
+                assert!(!derivation.source.contains(newer), "{c:?}: {derivation:?}");
+            }
+        }
+        assert_eq!(es5.target(), Target::Es5);
+    }
+
+    #[test]
+    fn template_calls() {
+        let alphabet = "[]!+`";
+        assert_eq!(Encoder::new(alphabet).rules.call, Some("{0}``"));
+        let es5 = Encoder::for_target(alphabet, Target::Es5);
+        assert_eq!(es5.rules.call, None);
+        for (c, derivation) in es5.derivations() {
+            assert!(!derivation.encoded.contains('`'), "{c:?}: {derivation:?}");
+        }
+    }
+
+    #[test]
+    fn other_alphabets() {
+        let encoder = Encoder::new("+[]");
+        assert_eq!(encoder.number(1).unwrap(), "++[[]][+[]]");
+        assert_eq!(
+            eval(&encoder.number(23).unwrap()).unwrap().to_string(),
+            "23"
+        );
+        let encoded = encoder.string("function").unwrap();
+        assert!(encoded.chars().all(|c| "+[]".contains(c)));
+        assert_eq!(eval(&encoded).unwrap().to_string(), "function");
+        let encoder = Encoder::new("[]!+`()");
+        let encoded = encoder.compile("alert(1)").unwrap();
+        assert_eq!(
+            Evaluator::default()
+                .eval(&encoded)
+                .unwrap_err()
+                .err_type
+                .to_string(),
+            "alert is not defined"
+        );
+    }
+
+    #[test]
+    fn unproducible() {
+        let err = Encoder::new("[]()+").compile("alert(1)").unwrap_err();
+        assert_eq!(err.chars, ['l', 'r', 's']);
+        assert_eq!(
+            err.to_string(),
+            "The alphabet \"()+[]\" can not produce 'l', 'r', 's'"
+        );
+        let encoder = Encoder::new("[]+!");
+        assert_eq!(encoder.compile("").unwrap_err().chars, ['(', ')']);
+        assert_eq!(encoder.string("\u{e9}").unwrap_err().chars, ['\u{e9}']);
+        let encoder = Encoder::new("[]!");
+        assert_eq!(encoder.number(0).unwrap_err().chars, ['+']);
+        assert!(encoder.derivations().is_empty());
+        assert_eq!(encoder.string("ab").unwrap_err().chars, ['a', 'b']);
+    }
+}
@@ -1,7 +1,16 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::OnceLock,
+};
 
-use crate::iter_utils::IteratorUtils;
-use lazy_static::lazy_static;
+use super::{
+    alphabet::{Encoder, JSFUCK},
+    target::{Feature, Target},
+};
+use crate::writer::{collect, count};
+
+pub use super::alphabet::Derivation;
 
 pub const ZERO: &str = "+[]";
 pub const ONE: &str = "+!![]";
@@ -12,9 +21,67 @@ pub const NAN: &str = "+{}+[]";
 pub const ARROW_FN: &str = "()=>{}";
 pub const INFINITY: &str = "(+!![]/+[])+[]";
 pub const EMPTY_STR: &str = "[]+[]";
+pub const UNDEFINED: &str = "[][[]]+[]";
+
+/// The symbols of the loose encoding, those of jsf*ck along with the ones its other constants write
+const LOOSE_SYMBOLS: &str = "[]()!+{}=>/\\-* ";
+
+/// Whether the output may use javascript beyond the six jsf*ck symbols, and where it has to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Mode {
+    pub(super) strict: bool,
+    pub(super) target: Target,
+}
+
+impl Mode {
+    pub(super) const LOOSE: Self = Self {
+        strict: false,
+        target: Target::Generic,
+    };
+    pub(super) const STRICT: Self = Self {
+        strict: true,
+        target: Target::Generic,
+    };
+
+    /// How many modes there are, one for each target in both the loose and strict encodings
+    const COUNT: usize = 2 * Target::ALL.len();
+
+    /// The position of the mode among all [`COUNT`](Self::COUNT) of them
+    fn index(self) -> usize {
+        usize::from(self.strict) * Target::ALL.len() + self.target as usize
+    }
+
+    /// The symbols the output is restricted to
+    const fn symbols(self) -> &'static str {
+        if self.strict {
+            JSFUCK
+        } else {
+            LOOSE_SYMBOLS
+        }
+    }
+
+    fn derivations(self) -> &'static Derivations {
+        // Searched on first use, once for each mode
+        static SEARCHED: [OnceLock<Derivations>; Mode::COUNT] =
+            [const { OnceLock::new() }; Mode::COUNT];
+        SEARCHED[self.index()]
+            .get_or_init(|| Derivations::new(Encoder::for_target(self.symbols(), self.target)))
+    }
+
+    fn encoder(self) -> &'static Encoder {
+        &self.derivations().encoder
+    }
+
+    pub(super) fn map(self) -> &'static HashMap<char, String> {
+        &self.derivations().map
+    }
+}
 
 /// Generates jsf*ck expression that will evaluate into the desired number
 ///
+/// Small numbers are sums of ones, larger ones are built from their decimal digits, e.g.
+/// `+(+!![]+[+[]])` is `+("1"+"0")`, or multiplied from two such factors, whichever is shortest.
+///
 /// # Examples
 /// ```
 /// use jsfuckrs::lbp::building_blocks::{number, ONE};
@@ -22,32 +89,103 @@ pub const EMPTY_STR: &str = "[]+[]";
 /// let two = number(2);
 /// assert_eq!(zero, "+[]");
 /// assert_eq!(two, "+!![] + +!![]");
+/// assert_eq!(number(10), "+(+!![]+[+[]])");
 /// ```
 pub fn number(n: usize) -> String {
-    match n {
-        0 => ZERO.to_owned(),
-        n => (0..n).map(|_| ONE).collect_vec().join(" + "),
-    }
+    number_in(Mode::LOOSE, n)
+}
+
+pub(super) fn number_in(mode: Mode, n: usize) -> String {
+    mode.encoder()
+        .number(n)
+        .expect("every mode writes `+[]` and `+!![]`, and the sums and digits built from them")
+}
+
+pub(super) fn is_mapped_in(mode: Mode, c: char) -> bool {
+    mode.map().contains_key(&c)
 }
 
+/// Generates jsf*ck expression that will evaluate into the desired string
+///
+/// Characters missing from the map are encoded as UTF-16 code units, so characters outside the
+/// basic multilingual plane become surrogate pairs, and every run of them is decoded at once.
 pub fn string<T>(s: T) -> String
 where
     T: AsRef<str>,
 {
-    s.as_ref()
-        .chars()
-        .map(|c| match MAP.get(&c) {
-            Some(s) => s.clone(),
-            None => format!(
-                "({})[{}][{}]({})",
-                EMPTY_STR,
-                string("constructor"),
-                string("fromCharCode"),
-                number(c as usize)
-            ),
-        })
-        .collect_vec()
-        .join("+")
+    string_in(Mode::LOOSE, s.as_ref())
+}
+
+pub(super) fn string_in(mode: Mode, s: &str) -> String {
+    collect(|out| string_to_in(mode, out, s))
+}
+
+/// Writes the expression [`string`] generates into `out`, one character at a time
+///
+/// # Examples
+/// ```
+/// use jsfuckrs::lbp::building_blocks::{string, string_to};
+/// let mut out = String::new();
+/// string_to(&mut out, "jsf*ck").unwrap();
+/// assert_eq!(out, string("jsf*ck"));
+/// ```
+pub fn string_to<W, T>(out: &mut W, s: T) -> fmt::Result
+where
+    W: fmt::Write,
+    T: AsRef<str>,
+{
+    string_to_in(Mode::LOOSE, out, s.as_ref())
+}
+
+pub(super) fn string_to_in(mode: Mode, out: &mut dyn fmt::Write, s: &str) -> fmt::Result {
+    let mut first = true;
+    let mut separate = |out: &mut dyn fmt::Write| {
+        if std::mem::take(&mut first) {
+            Ok(())
+        } else {
+            out.write_char('+')
+        }
+    };
+    let map = mode.map();
+    let mut unmapped = vec![];
+    for c in s.chars() {
+        match map.get(&c) {
+            Some(s) => {
+                if !unmapped.is_empty() {
+                    separate(out)?;
+                    code_units_to_in(mode, out, &unmapped)?;
+                    unmapped.clear();
+                }
+                separate(out)?;
+                out.write_str(s)?;
+            }
+            None => unmapped.extend(c.encode_utf16(&mut [0; 2]).iter()),
+        }
+    }
+    if !unmapped.is_empty() {
+        separate(out)?;
+        code_units_to_in(mode, out, &unmapped)?;
+    }
+    Ok(())
+}
+
+/// Decodes a run of UTF-16 code units at once with `unescape("%uXXXX...")`
+///
+/// Unlike `String.fromCharCode` this only costs a few characters per code unit once the
+/// `unescape` function has been looked up.
+fn code_units_to_in(mode: Mode, out: &mut dyn fmt::Write, units: &[u16]) -> fmt::Result {
+    write!(out, "{}(", function_constructor_in(mode))?;
+    string_to_in(mode, out, "return unescape")?;
+    out.write_str(")()(")?;
+    string_to_in(
+        mode,
+        out,
+        &units
+            .iter()
+            .map(|unit| format!("%u{unit:04x}"))
+            .collect::<String>(),
+    )?;
+    out.write_char(')')
 }
 
 /// Compile a javascript expression into jsf*ck
@@ -55,92 +193,232 @@ pub fn compile<T>(code: T) -> String
 where
     T: AsRef<str>,
 {
+    compile_in(Mode::LOOSE, code.as_ref())
+}
+
+pub(super) fn compile_in(mode: Mode, code: &str) -> String {
+    collect(|out| compile_to_in(mode, out, code))
+}
+
+/// Writes the output of [`compile`] into `out`, one character at a time
+///
+/// # Examples
+/// ```
+/// use jsfuckrs::{lbp::building_blocks::compile_to, IoWriter};
+/// let mut out = IoWriter::new(std::io::sink());
+/// let result = compile_to(&mut out, "alert(1)");
+/// out.finish(result).unwrap();
+/// ```
+pub fn compile_to<W, T>(out: &mut W, code: T) -> fmt::Result
+where
+    W: fmt::Write,
+    T: AsRef<str>,
+{
+    compile_to_in(Mode::LOOSE, out, code.as_ref())
+}
+
+pub(super) fn compile_to_in(mode: Mode, out: &mut dyn fmt::Write, code: &str) -> fmt::Result {
+    write!(out, "{}(", function_constructor_in(mode))?;
+    string_to_in(mode, out, code)?;
+    out.write_str(")()")
+}
+
+/// Compile a javascript expression into jsf*ck like [`compile`], sharing the expensive
+/// derivations between characters
+///
+/// The fragments every costly character would derive again are encoded once, in the slots of an
+/// array handed to a function as its parameter, which refers to them by index: `"constructor"`,
+/// read twice to reach the `Function` constructor that another parameter then holds,
+/// `"return unescape"`, and the `%` of the escape sequences. Characters cheaper to write as an
+/// escape sequence are escaped with a cheap marker standing in for `%`, which also separates the
+/// slots, so splitting the one string they are encoded in cuts the code where its `%` go back in.
+/// The plain [`compile`] output is returned when that is shorter.
+///
+/// # Examples
+/// ```
+/// use jsfuckrs::lbp::building_blocks::{compile, compile_hoisted};
+/// let code = "alert(\"Hello world!\");";
+/// assert!(compile_hoisted(code).len() < compile(code).len());
+/// ```
+pub fn compile_hoisted<T>(code: T) -> String
+where
+    T: AsRef<str>,
+{
+    compile_hoisted_in(Mode::LOOSE, code.as_ref())
+}
+
+pub(super) fn compile_hoisted_in(mode: Mode, code: &str) -> String {
+    collect(|out| compile_hoisted_to_in(mode, out, code))
+}
+
+/// Writes the output of [`compile_hoisted`] into `out`, one character at a time
+pub fn compile_hoisted_to<W, T>(out: &mut W, code: T) -> fmt::Result
+where
+    W: fmt::Write,
+    T: AsRef<str>,
+{
+    compile_hoisted_to_in(Mode::LOOSE, out, code.as_ref())
+}
+
+/// The slots [`compile_hoisted`] caches, followed by the pieces of the escaped code
+const SLOTS: [&str; 5] = ["constructor", "return unescape", "slice", "join", "%"];
+
+pub(super) fn compile_hoisted_to_in(
+    mode: Mode,
+    out: &mut dyn fmt::Write,
+    code: &str,
+) -> fmt::Result {
+    let map = mode.map();
+    let cost = |s: &str| -> usize { s.chars().map(|c| map[&c].len() + 1).sum() };
+    let escape = |c: char, marker: char| {
+        c.encode_utf16(&mut [0; 2])
+            .iter()
+            .map(|unit| match u8::try_from(*unit) {
+                Ok(b) => format!("{marker}{b:02x}"),
+                Err(_) => format!("{marker}u{unit:04x}"),
+            })
+            .collect::<String>()
+    };
+    // The marker must not be mistaken for part of an escape sequence or of a slot
+    let escaped_with = |marker: char| {
+        code.chars()
+            .map(|c| {
+                let escaped = escape(c, marker);
+                let forced = c == marker || c == '%' || !map.contains_key(&c);
+                if forced || cost(&escaped) < map[&c].len() + 1 {
+                    escaped
+                } else {
+                    c.to_string()
+                }
+            })
+            .collect::<String>()
+    };
+    let Some((marker, escaped)) = (' '..='~')
+        .filter(|c| !"0123456789abcdefu".contains(*c) && !SLOTS.concat().contains(*c))
+        .map(|marker| (marker, escaped_with(marker)))
+        .min_by_key(|(_, escaped)| cost(escaped))
+    else {
+        return compile_to_in(mode, out, code);
+    };
+    let hoisted = |out: &mut dyn fmt::Write| {
+        // The `Function` constructor is the `constructor` of the `Array` constructor, and is kept
+        // in a parameter of its own to build the function the final call runs
+        let run = format!("f(f(a[1])()(a[a[2]]({})[a[3]](a[4])))", SLOTS.len());
+        let body = format!("({})(a[a[0]][a[0]])", lambda(mode, "f", &run));
+        let function = format!("return {}", lambda(mode, "a", &body));
+        write!(out, "{}(", function_constructor_in(mode))?;
+        string_to_in(mode, out, &function)?;
+        out.write_str(")()((")?;
+        let slots = SLOTS.join(&marker.to_string());
+        string_to_in(mode, out, &format!("{slots}{marker}{escaped}"))?;
+        write!(
+            out,
+            ")[{}]({}))()",
+            string_in(mode, "split"),
+            string_in(mode, &marker.to_string())
+        )
+    };
+    // Only the lengths are compared, so neither output is ever held in memory
+    if count(hoisted) < count(|out| compile_to_in(mode, out, code)) {
+        hoisted(out)
+    } else {
+        compile_to_in(mode, out, code)
+    }
+}
+
+/// A function taking `param` and returning `body`, as source code
+fn lambda(mode: Mode, param: &str, body: &str) -> String {
+    if mode.target.supports(Feature::Es2015) {
+        format!("{param}=>{body}")
+    } else {
+        format!("function({param}){{return {body}}}")
+    }
+}
+
+/// An expression evaluating to the `Function` constructor
+pub(super) fn function_constructor_in(mode: Mode) -> String {
     format!(
-        "({})[{}]({})()",
-        ARROW_FN,
-        string("constructor"),
-        string(code)
+        "({})[{}]",
+        function_in(mode),
+        string_in(mode, "constructor")
     )
 }
 
-lazy_static! {
-    static ref MAP: HashMap<char, String> = {
-        let false_index = |n| format!("({FALSE})[{}]", number(n));
-        let true_index = |n| format!("({TRUE})[{}]", number(n));
-        let obj_index = |n| format!("({OBJECT})[{}]", number(n));
-        let infinity_index = |n| format!("({INFINITY})[{}]", number(n));
-        let mut m = HashMap::new();
-        macro_rules! string {
-            ($s:expr) => {
-                $s.chars()
-                    .map(|c| m.get(&c).unwrap())
-                    .cloned()
-                    .collect_vec()
-                    .join("+")
-            };
-        }
-        macro_rules! number_base {
-            ($n:expr,$base:expr) => {
-                format!(
-                    "({})[{}]({})",
-                    number($n),
-                    string!("toString"),
-                    number($base)
-                )
-            };
-        }
-        m.insert('a', format!("({})[{}]", NAN, number(1)));
-        m.insert('o', obj_index(1));
-        m.insert('b', obj_index(2));
-        m.insert('j', obj_index(3));
-        m.insert('e', obj_index(4));
-        m.insert('c', obj_index(5));
-        m.insert('t', obj_index(6));
-        m.insert(' ', obj_index(7));
-        m.insert('f', false_index(0));
-        m.insert('s', false_index(3));
-        m.insert('r', true_index(1));
-        m.insert('u', true_index(2));
-        m.insert('i', infinity_index(3));
-        m.insert('n', infinity_index(4));
-        let constructor_str = string!("constructor");
-        m.insert(
-            'S',
-            format!("([]+({EMPTY_STR})[{}])[{}]", constructor_str, number(9)),
-        );
-        m.insert(
-            'g',
-            format!("([]+({EMPTY_STR})[{}])[{}]", constructor_str, number(14)),
-        );
-        m.insert(
-            'p',
-            format!("([]+(/-/)[{}])[{}]", constructor_str, number(14)),
-        );
-        m.insert('\\', format!("(/\\\\/+[])[{}]", number(1)));
-        let d = number_base!(13, 14);
-        let h = number_base!(17, 18);
-        let _m = number_base!(22, 23);
-        m.insert('d', d);
-        m.insert('h', h);
-        m.insert('m', _m);
-        m.insert(
-            'C',
-            format!(
-                "(({})[{}]({})()({}))[{}]",
-                ARROW_FN,
-                constructor_str,
-                string!("return escape"),
-                m[&'\\'],
-                number(2)
-            ),
-        );
-        m
-    };
+/// An expression whose constructor is `Function`
+fn function_in(mode: Mode) -> String {
+    match function_source(mode) {
+        FunctionSource::Arrow => ARROW_FN.to_owned(),
+        FunctionSource::Method(name) => format!("[][{}]", string_in(mode, name)),
+    }
+}
+
+enum FunctionSource {
+    Arrow,
+    /// A method of arrays
+    Method(&'static str),
+}
+
+/// What the `Function` constructor is read from
+fn function_source(mode: Mode) -> FunctionSource {
+    if !mode.target.supports(Feature::Es2015) {
+        FunctionSource::Method("filter")
+    } else if mode.strict {
+        FunctionSource::Method("flat")
+    } else {
+        FunctionSource::Arrow
+    }
+}
+
+/// The derivations of an [`Encoder`], and their encodings by character
+struct Derivations {
+    encoder: Encoder,
+    map: HashMap<char, String>,
+}
+
+impl Derivations {
+    fn new(encoder: Encoder) -> Self {
+        let map = encoder
+            .derivations()
+            .iter()
+            .map(|(&c, derivation)| (c, derivation.encoded.clone()))
+            .collect();
+        Self { encoder, map }
+    }
+}
+
+/// The cheapest derivation of every character the encoder maps
+///
+/// # Examples
+/// ```
+/// use jsfuckrs::lbp::building_blocks::derivations;
+/// let a = &derivations()[&'a'];
+/// assert_eq!(a.source, "\"NaN\"[1]");
+/// assert_eq!(a.encoded, "(+{}+[])[+!![]]");
+/// assert_eq!(a.cost(), 15);
+/// ```
+#[must_use]
+pub fn derivations() -> &'static BTreeMap<char, Derivation> {
+    derivations_in(Mode::LOOSE)
+}
+
+/// The cheapest derivation of every character when the output only has to run on `target`
+#[must_use]
+pub fn derivations_for(target: Target) -> &'static BTreeMap<char, Derivation> {
+    derivations_in(Mode {
+        target,
+        ..Mode::LOOSE
+    })
+}
+
+pub(super) fn derivations_in(mode: Mode) -> &'static BTreeMap<char, Derivation> {
+    mode.encoder().derivations()
 }
 
 #[cfg(test)]
 mod tests {
     mod numbers {
+        use crate::eval::eval;
+        use crate::iter_utils::IteratorUtils;
         use crate::lbp::building_blocks::*;
 
         #[test]
@@ -162,20 +440,102 @@ mod tests {
         fn three() {
             assert_eq!(number(3), format!("{} + {} + {}", ONE, ONE, ONE));
         }
+
+        #[test]
+        fn digits() {
+            assert_eq!(number(10), "+(+!![]+[+[]])");
+            assert_eq!(number(21), "+(+!![] + +!![]+[+!![]])");
+        }
+
+        #[test]
+        fn product() {
+            assert_eq!(
+                number(99),
+                "(+!![] + +!![] + +!![])*(+(+!![] + +!![] + +!![]+[+!![] + +!![] + +!![]]))"
+            );
+        }
+
+        #[test]
+        fn evaluates() {
+            for n in (0..300).chain([1000, 4096, 8364, 65535, 1_000_000]) {
+                for mode in [Mode::LOOSE, Mode::STRICT] {
+                    let encoded = number_in(mode, n);
+                    let value = eval(&encoded).unwrap().to_string();
+                    assert_eq!(value, n.to_string(), "{mode:?} {n}: {encoded}");
+                }
+            }
+        }
+
+        #[test]
+        fn large() {
+            // usize::MAX, and primes below and above 2^32
+            for n in [usize::MAX, 1_000_000_007, 4_294_967_311] {
+                for mode in [Mode::LOOSE, Mode::STRICT] {
+                    let encoded = number_in(mode, n);
+                    let value = eval(&encoded).unwrap().to_string();
+                    assert_eq!(
+                        value,
+                        eval(&n.to_string()).unwrap().to_string(),
+                        "{mode:?} {n}"
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn size_reduction() {
+            // (value, length of the unary sum, length of the encoding)
+            let table = [
+                (100, 797, 20),
+                (1000, 7997, 26),
+                (8364, 66_909, 99),
+                (65535, 524_277, 144),
+            ];
+            for (n, unary, encoded) in table {
+                assert_eq!((0..n).map(|_| ONE).collect_vec().join(" + ").len(), unary);
+                assert_eq!(number(n).len(), encoded, "{n}: {}", number(n));
+            }
+        }
     }
 
     mod chars {
         use crate::lbp::building_blocks::*;
 
+        #[test]
+        fn printable_ascii_is_mapped() {
+            for target in Target::ALL {
+                for strict in [false, true] {
+                    let map = Mode { strict, target }.map();
+                    for c in ' '..='~' {
+                        assert!(map.contains_key(&c), "{c:?} is not mapped for {target}");
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn digits() {
+            assert_eq!(Mode::LOOSE.map()[&'0'], "(+[]+[])");
+            assert_eq!(Mode::LOOSE.map()[&'2'], "(+!![] + +!![]+[])");
+            assert_eq!(Mode::STRICT.map()[&'2'], "(!![]+!![]+[])");
+        }
+
+        #[test]
+        fn punctuation() {
+            assert_eq!(Mode::LOOSE.map()[&'+'], "(+((+!![]+[])+(+!![]+[])+({}+[])[+(+!![]+[+!![]])]+(+!![]+[])+(+[]+[])+(+[]+[]))+[])[+!![] + +!![] + +!![] + +!![]]");
+            assert_eq!(Mode::LOOSE.map()[&'('], "((()=>{})+[])[+[]]");
+            assert_eq!(Mode::LOOSE.map()[&','], "([[]][({}+[])[+(+!![]+[+!![] + +!![]])]+({}+[])[+!![]]+([][[]]+[])[+!![]]+({}+[])[+(+!![]+[+!![] + +!![]])]+(+{}+[])[+!![]]+(!![]+[])[+[]]]([[]])+[])[+[]]");
+        }
+
         #[test]
         fn a() {
-            assert_eq!(MAP[&'a'], "(+{}+[])[+!![]]");
+            assert_eq!(Mode::LOOSE.map()[&'a'], "(+{}+[])[+!![]]");
         }
 
         #[test]
         fn space() {
             assert_eq!(
-                MAP[&' '],
+                Mode::LOOSE.map()[&' '],
                 "({}+[])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![]]"
             );
         }
@@ -184,8 +544,8 @@ mod tests {
         #[allow(non_snake_case)]
         fn S() {
             assert_eq!(
-                MAP[&'S'],
-                "([]+([]+[])[({}+[])[+!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![]]+((+!![]/+[])+[])[+!![] + +!![] + +!![] + +!![]]+(![]+[])[+!![] + +!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![]]+(!![]+[])[+!![]]+(!![]+[])[+!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![]]+(!![]+[])[+!![]]])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![]]"
+                Mode::LOOSE.map()[&'S'],
+                "([]+([]+[])[({}+[])[+(+!![]+[+!![] + +!![]])]+({}+[])[+!![]]+([][[]]+[])[+!![]]+(![]+[])[+!![] + +!![] + +!![]]+(!![]+[])[+[]]+(!![]+[])[+!![]]+([][[]]+[])[+[]]+({}+[])[+(+!![]+[+!![] + +!![]])]+(!![]+[])[+[]]+({}+[])[+!![]]+(!![]+[])[+!![]]])[(+!![] + +!![] + +!![])*(+!![] + +!![] + +!![])]"
             );
         }
 
@@ -193,19 +553,34 @@ mod tests {
         #[allow(non_snake_case)]
         fn C() {
             assert_eq!(
-                MAP[&'C'],
-                "((()=>{})[({}+[])[+!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![]]+((+!![]/+[])+[])[+!![] + +!![] + +!![] + +!![]]+(![]+[])[+!![] + +!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![]]+(!![]+[])[+!![]]+(!![]+[])[+!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![]]+(!![]+[])[+!![]]]((!![]+[])[+!![]]+({}+[])[+!![] + +!![] + +!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![]]+(!![]+[])[+!![] + +!![]]+(!![]+[])[+!![]]+((+!![]/+[])+[])[+!![] + +!![] + +!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![]]+(![]+[])[+!![] + +!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![]]+(+{}+[])[+!![]]+([]+(/-/)[({}+[])[+!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![]]+((+!![]/+[])+[])[+!![] + +!![] + +!![] + +!![]]+(![]+[])[+!![] + +!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![]]+(!![]+[])[+!![]]+(!![]+[])[+!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![]]+(!![]+[])[+!![]]])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![]])()((/\\\\/+[])[+!![]]))[+!![] + +!![]]"
+                Mode::LOOSE.map()[&'C'],
+                "((()=>{})[({}+[])[+(+!![]+[+!![] + +!![]])]+({}+[])[+!![]]+([][[]]+[])[+!![]]+(![]+[])[+!![] + +!![] + +!![]]+(!![]+[])[+[]]+(!![]+[])[+!![]]+([][[]]+[])[+[]]+({}+[])[+(+!![]+[+!![] + +!![]])]+(!![]+[])[+[]]+({}+[])[+!![]]+(!![]+[])[+!![]]]((!![]+[])[+!![]]+({}+[])[+(+!![]+[+!![]])]+(!![]+[])[+[]]+([][[]]+[])[+[]]+(!![]+[])[+!![]]+([][[]]+[])[+!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+(+!![]+[+!![]])]+(![]+[])[+!![] + +!![] + +!![]]+({}+[])[+(+!![]+[+!![] + +!![]])]+(+{}+[])[+!![]]+([]+(/-/)[({}+[])[+(+!![]+[+!![] + +!![]])]+({}+[])[+!![]]+([][[]]+[])[+!![]]+(![]+[])[+!![] + +!![] + +!![]]+(!![]+[])[+[]]+(!![]+[])[+!![]]+([][[]]+[])[+[]]+({}+[])[+(+!![]+[+!![] + +!![]])]+(!![]+[])[+[]]+({}+[])[+!![]]+(!![]+[])[+!![]]])[+(+!![]+[+!![] + +!![] + +!![] + +!![]])]+({}+[])[+(+!![]+[+!![]])])()((/\\\\/+[])[+!![]]))[+!![] + +!![]]"
             );
         }
 
         #[test]
         fn p() {
-            assert_eq!(MAP[&'p'], "([]+(/-/)[({}+[])[+!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![]]+((+!![]/+[])+[])[+!![] + +!![] + +!![] + +!![]]+(![]+[])[+!![] + +!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![]]+(!![]+[])[+!![]]+(!![]+[])[+!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![]]+(!![]+[])[+!![]]])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![] + +!![]]");
+            assert_eq!(Mode::LOOSE.map()[&'p'], "([]+(/-/)[({}+[])[+(+!![]+[+!![] + +!![]])]+({}+[])[+!![]]+([][[]]+[])[+!![]]+(![]+[])[+!![] + +!![] + +!![]]+(!![]+[])[+[]]+(!![]+[])[+!![]]+([][[]]+[])[+[]]+({}+[])[+(+!![]+[+!![] + +!![]])]+(!![]+[])[+[]]+({}+[])[+!![]]+(!![]+[])[+!![]]])[+(+!![]+[+!![] + +!![] + +!![] + +!![]])]");
+        }
+
+        #[test]
+        fn chosen_derivations() {
+            let derivations = derivations();
+            assert_eq!(derivations[&'a'].source, "\"NaN\"[1]");
+            assert_eq!(derivations[&'h'].source, "(17).toString(18)");
+            assert_eq!(derivations[&'%'].source, "\"%5B\"[0]");
+            assert_eq!(
+                derivations_in(Mode::STRICT)[&'{'].source,
+                "\"function anonymous(\\n) {\\n\\n}\"[22]"
+            );
+            for (c, derivation) in derivations {
+                assert_eq!(derivation.cost(), Mode::LOOSE.map()[c].len());
+            }
         }
 
         #[test]
         fn back_slash() {
-            assert_eq!(MAP[&'\\'], "(/\\\\/+[])[+!![]]");
+            assert_eq!(Mode::LOOSE.map()[&'\\'], "(/\\\\/+[])[+!![]]");
         }
     }
 
@@ -214,12 +589,66 @@ mod tests {
 
         #[test]
         fn constructor() {
-            assert_eq!(string("constructor"), "({}+[])[+!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![]]+((+!![]/+[])+[])[+!![] + +!![] + +!![] + +!![]]+(![]+[])[+!![] + +!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![]]+(!![]+[])[+!![]]+(!![]+[])[+!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![] + +!![] + +!![] + +!![] + +!![] + +!![]]+({}+[])[+!![]]+(!![]+[])[+!![]]");
+            assert_eq!(string("constructor"), "({}+[])[+(+!![]+[+!![] + +!![]])]+({}+[])[+!![]]+([][[]]+[])[+!![]]+(![]+[])[+!![] + +!![] + +!![]]+(!![]+[])[+[]]+(!![]+[])[+!![]]+([][[]]+[])[+[]]+({}+[])[+(+!![]+[+!![] + +!![]])]+(!![]+[])[+[]]+({}+[])[+!![]]+(!![]+[])[+!![]]");
+        }
+    }
+
+    mod unicode {
+        use crate::iter_utils::IteratorUtils;
+        use crate::lbp::building_blocks::*;
+        use crate::lbp::strict;
+
+        fn unescape(escaped: &str) -> String {
+            format!(
+                "({ARROW_FN})[{}]({})()({})",
+                string("constructor"),
+                string("return unescape"),
+                string(escaped)
+            )
+        }
+
+        #[test]
+        fn bmp() {
+            assert_eq!(string("\t"), unescape("%u0009"));
+            assert_eq!(
+                string("caf\u{e9}"),
+                format!("{}+{}", string("caf"), unescape("%u00e9"))
+            );
+        }
+
+        #[test]
+        fn surrogate_pair() {
+            assert_eq!("\u{1f600}".encode_utf16().collect_vec(), [0xd83d, 0xde00]);
+            assert_eq!(string("\u{1f600}"), unescape("%ud83d%ude00"));
+            assert_eq!(
+                strict::string("\u{1f600}"),
+                format!(
+                    "([][{}])[{}]({})()({})",
+                    strict::string("flat"),
+                    strict::string("constructor"),
+                    strict::string("return unescape"),
+                    strict::string("%ud83d%ude00")
+                )
+            );
+        }
+
+        #[test]
+        fn runs_are_unescaped() {
+            assert_eq!(
+                string("\u{4f60}\u{597d}\u{4e16}\u{754c}"),
+                unescape("%u4f60%u597d%u4e16%u754c")
+            );
+            assert_eq!(
+                string("\u{1f600}\u{1f44d} ok"),
+                format!("{}+{}", unescape("%ud83d%ude00%ud83d%udc4d"), string(" ok"))
+            );
         }
     }
 
     mod compiled {
-        use crate::lbp::building_blocks::compile;
+        use crate::decode;
+        use crate::lbp::building_blocks::{compile, compile_hoisted};
+        use crate::lbp::strict;
 
         #[test]
         fn hello_world() {
@@ -233,5 +662,128 @@ mod tests {
         fn alert() {
             assert_eq!(compile("alert(1);"), include_str!("data/alert.txt"));
         }
+
+        #[test]
+        fn hoisted() {
+            // (code, lengths of the plain and hoisted encodings, then of the strict ones)
+            let table = [
+                ("console.log(\"Hello world!\");", 7601, 5357, 20462, 16946),
+                // Not enough characters share the derivations for hoisting to pay off
+                ("alert(1);", 2169, 2169, 6202, 6202),
+            ];
+            for (code, plain, hoisted, strict_plain, strict_hoisted) in table {
+                assert_eq!(compile(code).len(), plain);
+                assert_eq!(compile_hoisted(code).len(), hoisted, "{code}");
+                assert_eq!(strict::compile(code).len(), strict_plain);
+                assert_eq!(
+                    strict::compile_hoisted(code).len(),
+                    strict_hoisted,
+                    "{code}"
+                );
+                assert_eq!(decode(&compile_hoisted(code)).unwrap(), code);
+                assert_eq!(decode(&strict::compile_hoisted(code)).unwrap(), code);
+            }
+        }
+    }
+
+    mod targets {
+        use crate::decode;
+        use crate::lbp::building_blocks::*;
+        use crate::lbp::{strict, Lbp};
+        use crate::JsFuckEncoder;
+
+        #[test]
+        fn es5() {
+            for strict in [false, true] {
+                let mode = Mode {
+                    strict,
+                    target: Target::Es5,
+                };
+                for (c, derivation) in derivations_in(mode) {
+                    for newer in ["Array Iterator", "flat", "anonymous", ARROW_FN] {
+                        assert!(!derivation.source.contains(newer), "{c:?}: {derivation:?}");
+                    }
+                }
+                let lbp = Lbp {
+                    strict,
+                    hoist: false,
+                    target: Target::Es5,
+                };
+                let compiled = lbp.compile("alert(1);");
+                assert!(!compiled.contains("=>"));
+                assert_eq!(decode(&compiled).unwrap(), "alert(1);");
+            }
+        }
+
+        #[test]
+        fn browser() {
+            let browser = derivations_for(Target::Browser);
+            assert_eq!(browser[&'W'].source, "\"[object Window]\"[8]");
+            assert!(browser[&'H'].cost() < derivations()[&'H'].cost());
+        }
+
+        #[test]
+        fn node() {
+            let node = strict::derivations_for(Target::Node);
+            assert_eq!(
+                node[&'{'].source,
+                "\"function flat() { [native code] }\"[16]"
+            );
+            assert!(node[&'{'].cost() < strict::derivations()[&'{'].cost());
+        }
+
+        #[test]
+        fn generic_is_the_default() {
+            assert_eq!(Target::default(), Target::Generic);
+            assert_eq!(Lbp::default().compile("alert(1);"), compile("alert(1);"));
+        }
+    }
+
+    mod evaluated {
+        use crate::eval::{eval, EvalErrorType, Evaluator};
+        use crate::lbp::building_blocks::*;
+        use crate::lbp::strict;
+
+        #[test]
+        fn chars() {
+            for target in Target::ALL {
+                for strict in [false, true] {
+                    for (c, derivation) in derivations_in(Mode { strict, target }) {
+                        let encoded = &derivation.encoded;
+                        match eval(encoded) {
+                            Ok(value) => assert_eq!(value.to_string(), c.to_string(), "{encoded}"),
+                            // The evaluator has no DOM
+                            Err(e) if matches!(e.err_type, EvalErrorType::NotDefined(_)) => {
+                                assert_eq!(target, Target::Browser, "{c:?}: {e:?}");
+                            }
+                            Err(e) => panic!("{c:?}: {e:?}"),
+                        }
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn strings() {
+            for s in [
+                "Hello world!",
+                "caf\u{e9}",
+                "\u{1f600}\u{1f44d} ok",
+                "\n\t\"'\\",
+            ] {
+                assert_eq!(eval(&string(s)).unwrap().to_string(), s);
+                assert_eq!(eval(&strict::string(s)).unwrap().to_string(), s);
+            }
+        }
+
+        #[test]
+        fn compiled() {
+            let code = "console.log(\"Hello world!\");";
+            for compiled in [compile(code), strict::compile(code)] {
+                let mut evaluator = Evaluator::default();
+                assert!(evaluator.eval(&compiled).is_err());
+                assert_eq!(evaluator.code().last().unwrap(), code);
+            }
+        }
     }
 }
@@ -3,6 +3,59 @@
 //! [Video Link](https://www.youtube.com/watch?v=sRWE5tnaxlI)
 //!
 //! [GitHub Link](https://github.com/lowbyteproductions/JavaScript-Is-Weird/blob/master/index.js)
+use std::fmt;
+
+pub mod alphabet;
 pub mod building_blocks;
+pub mod strict;
+mod target;
+
+pub use building_blocks::{compile, compile_hoisted, compile_hoisted_to, compile_to};
+pub use target::{Target, UnknownTarget};
+
+/// The [`JsFuckEncoder`](crate::JsFuckEncoder) of this backend
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lbp {
+    /// Only emit the six jsf*ck symbols, see [`strict`]
+    pub strict: bool,
+    /// Share the expensive derivations between characters when compiling, see
+    /// [`building_blocks::compile_hoisted`]
+    pub hoist: bool,
+    /// The engines the output has to run on
+    pub target: Target,
+}
+
+impl Lbp {
+    const fn mode(self) -> building_blocks::Mode {
+        building_blocks::Mode {
+            strict: self.strict,
+            target: self.target,
+        }
+    }
+}
+
+impl crate::JsFuckEncoder for Lbp {
+    fn number(&self, n: usize) -> String {
+        building_blocks::number_in(self.mode(), n)
+    }
+
+    fn string_to(&self, out: &mut dyn fmt::Write, s: &str) -> fmt::Result {
+        building_blocks::string_to_in(self.mode(), out, s)
+    }
+
+    fn compile_to(&self, out: &mut dyn fmt::Write, code: &str) -> fmt::Result {
+        if self.hoist {
+            building_blocks::compile_hoisted_to_in(self.mode(), out, code)
+        } else {
+            building_blocks::compile_to_in(self.mode(), out, code)
+        }
+    }
+
+    fn is_mapped(&self, c: char) -> bool {
+        building_blocks::is_mapped_in(self.mode(), c)
+    }
 
-pub use building_blocks::compile;
\ No newline at end of file
+    fn function_constructor(&self) -> String {
+        building_blocks::function_constructor_in(self.mode())
+    }
+}
@@ -0,0 +1,134 @@
+//! The [`building_blocks`](super::building_blocks) restricted to the six jsf*ck symbols `[]()!+`
+//!
+//! Every constant that relies on other syntax is derived again: `{}` becomes the
+//! `[object Array Iterator]` returned by `[]["entries"]()`, `()=>{}` becomes `[]["flat"]`, and the
+//! regular expression literals are replaced by the ones `Function("return/"+false+"/")()` creates.
+use super::building_blocks::{
+    compile_hoisted_in, compile_hoisted_to_in, compile_in, compile_to_in, derivations_in,
+    number_in, string_in, string_to_in, Derivation, Mode,
+};
+use super::Target;
+use std::{collections::BTreeMap, fmt};
+
+pub use super::building_blocks::{EMPTY_STR, FALSE, ONE, TRUE, UNDEFINED, ZERO};
+pub const NAN: &str = "+[![]]+[]";
+
+/// Generates jsf*ck expression that will evaluate into the desired number
+///
+/// # Examples
+/// ```
+/// use jsfuckrs::lbp::strict::number;
+/// assert_eq!(number(1), "+!![]");
+/// assert_eq!(number(2), "!![]+!![]");
+/// ```
+pub fn number(n: usize) -> String {
+    number_in(Mode::STRICT, n)
+}
+
+/// Generates jsf*ck expression that will evaluate into the desired string
+pub fn string<T>(s: T) -> String
+where
+    T: AsRef<str>,
+{
+    string_in(Mode::STRICT, s.as_ref())
+}
+
+/// Writes the expression [`string`] generates into `out`, one character at a time
+pub fn string_to<W, T>(out: &mut W, s: T) -> fmt::Result
+where
+    W: fmt::Write,
+    T: AsRef<str>,
+{
+    string_to_in(Mode::STRICT, out, s.as_ref())
+}
+
+/// Compile a javascript expression into jsf*ck
+pub fn compile<T>(code: T) -> String
+where
+    T: AsRef<str>,
+{
+    compile_in(Mode::STRICT, code.as_ref())
+}
+
+/// Writes the output of [`compile`] into `out`, one character at a time
+pub fn compile_to<W, T>(out: &mut W, code: T) -> fmt::Result
+where
+    W: fmt::Write,
+    T: AsRef<str>,
+{
+    compile_to_in(Mode::STRICT, out, code.as_ref())
+}
+
+/// Compile a javascript expression into jsf*ck, see
+/// [`building_blocks::compile_hoisted`](super::building_blocks::compile_hoisted)
+pub fn compile_hoisted<T>(code: T) -> String
+where
+    T: AsRef<str>,
+{
+    compile_hoisted_in(Mode::STRICT, code.as_ref())
+}
+
+/// Writes the output of [`compile_hoisted`] into `out`, one character at a time
+pub fn compile_hoisted_to<W, T>(out: &mut W, code: T) -> fmt::Result
+where
+    W: fmt::Write,
+    T: AsRef<str>,
+{
+    compile_hoisted_to_in(Mode::STRICT, out, code.as_ref())
+}
+
+/// The cheapest derivation of every character the encoder maps, see
+/// [`building_blocks::derivations`](super::building_blocks::derivations)
+#[must_use]
+pub fn derivations() -> &'static BTreeMap<char, Derivation> {
+    derivations_in(Mode::STRICT)
+}
+
+/// The cheapest derivation of every character when the output only has to run on `target`
+#[must_use]
+pub fn derivations_for(target: Target) -> &'static BTreeMap<char, Derivation> {
+    derivations_in(Mode {
+        target,
+        ..Mode::STRICT
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::is_jsfuck;
+
+    #[test]
+    fn numbers() {
+        for n in 0..64 {
+            assert!(is_jsfuck(&number(n)), "{n} is not strict: {}", number(n));
+        }
+    }
+
+    #[test]
+    fn chars() {
+        for (c, encoded) in Mode::STRICT.map() {
+            assert!(is_jsfuck(encoded), "{c:?} is not strict: {encoded}");
+        }
+    }
+
+    #[test]
+    fn unmapped() {
+        assert!(is_jsfuck(&string("Hello World!")));
+        assert!(is_jsfuck(&string("\u{e9}")));
+    }
+
+    #[test]
+    fn hello_world() {
+        let compiled = compile("console.log(\"Hello world!\");");
+        assert!(is_jsfuck(&compiled));
+        assert_eq!(compiled, include_str!("data/strict_hello_world.txt"));
+    }
+
+    #[test]
+    fn alert() {
+        let compiled = compile("alert(1);");
+        assert!(is_jsfuck(&compiled));
+        assert_eq!(compiled, include_str!("data/strict_alert.txt"));
+    }
+}
@@ -0,0 +1,116 @@
+//! The javascript engines an encoding has to run on
+//!
+//! Every character derivation depends on some language or host feature. A [`Target`] decides which
+//! of them the encoder may use, so the output is guaranteed to work on that engine.
+use std::{fmt, str::FromStr};
+
+use thiserror::Error;
+
+/// The javascript engines an encoding has to run on
+///
+/// # Examples
+/// ```
+/// use jsfuckrs::lbp::{building_blocks::derivations_for, Target};
+/// // `document` only exists in browsers
+/// let html_document = "\"[object HTMLDocument]\"[8]";
+/// assert_eq!(derivations_for(Target::Browser)[&'H'].source, html_document);
+/// assert_ne!(derivations_for(Target::Generic)[&'H'].source, html_document);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Target {
+    /// Any engine implementing ES2019, along with `escape` and the HTML methods of strings which
+    /// every engine provides
+    #[default]
+    Generic,
+    /// Engines predating ES2015, so neither arrow functions nor `flat` or `entries`
+    Es5,
+    /// Web browsers, where the DOM is available
+    Browser,
+    /// Node.js, where the source of native functions is known
+    Node,
+}
+
+/// What character derivations can depend on beyond ES5
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Feature {
+    /// Arrow functions and `[]["entries"]`
+    Es2015,
+    /// `[]["flat"]` and the source of functions created by `Function`
+    Es2019,
+    /// Globals like `document`
+    Dom,
+    /// V8 displays native functions as `function flat() { [native code] }`
+    V8Source,
+}
+
+impl Target {
+    /// Every target
+    pub const ALL: [Self; 4] = [Self::Generic, Self::Es5, Self::Browser, Self::Node];
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Generic => "generic",
+            Self::Es5 => "es5",
+            Self::Browser => "browser",
+            Self::Node => "node",
+        }
+    }
+
+    pub(super) const fn supports(self, feature: Feature) -> bool {
+        match feature {
+            Feature::Es2015 | Feature::Es2019 => !matches!(self, Self::Es5),
+            Feature::Dom => matches!(self, Self::Browser),
+            Feature::V8Source => matches!(self, Self::Node),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("Unknown target {0:?}")]
+pub struct UnknownTarget(pub String);
+
+impl FromStr for Target {
+    type Err = UnknownTarget;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|target| target.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| UnknownTarget(s.to_owned()))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        for target in Target::ALL {
+            assert_eq!(target.name().parse::<Target>().unwrap(), target);
+        }
+        assert_eq!("ES5".parse::<Target>().unwrap(), Target::Es5);
+        assert_eq!(
+            "deno".parse::<Target>(),
+            Err(UnknownTarget("deno".to_owned()))
+        );
+    }
+
+    #[test]
+    fn features() {
+        assert!(Target::Generic.supports(Feature::Es2019));
+        assert!(!Target::Es5.supports(Feature::Es2015));
+        assert!(!Target::Generic.supports(Feature::Dom));
+        assert!(Target::Browser.supports(Feature::Dom));
+        assert!(!Target::Browser.supports(Feature::V8Source));
+        assert!(Target::Node.supports(Feature::V8Source));
+    }
+}
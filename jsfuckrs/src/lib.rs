@@ -1,8 +1,24 @@
+#[cfg(feature = "aaencode")]
+pub mod aaencode;
 #[cfg(feature = "aemkei")]
 pub mod aemkei;
+mod decode;
+mod encoder;
+pub mod eval;
 mod iter_utils;
+#[cfg(feature = "jjencode")]
+pub mod jjencode;
+pub mod json;
 #[cfg(feature = "lbp")]
 pub mod lbp;
+mod writer;
+
+pub use decode::{decode, DecodeError};
+pub use encoder::{
+    is_jsfuck, Backend, CompileOptions, InvalidParameter, JsFuckEncoder, Parameters,
+    UnknownBackend, Wrapper, ALPHABET,
+};
+pub use writer::IoWriter;
 
 #[cfg(feature = "lbp")]
 #[cfg(not(feature = "aemkei"))]
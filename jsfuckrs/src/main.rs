@@ -1,7 +1,344 @@
-fn main() {
-    // println!("{}", jsfuckrs::building_blocks::string("Sore"));
-    println!(
-        "{}",
-        jsfuckrs::lbp::building_blocks::compile("console.log(\"Hello world!\");")
-    );
+//! Command line interface of the jsf*ck encoders
+use std::{
+    fmt::{self, Write as _},
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
+use jsfuckrs::{
+    aaencode::Aaencode,
+    aemkei::Aemkei,
+    decode,
+    jjencode::Jjencode,
+    json::Value,
+    lbp::{Lbp, Target},
+    Backend, CompileOptions, IoWriter, JsFuckEncoder, Wrapper,
+};
+use miette::{bail, miette, IntoDiagnostic, Result, WrapErr};
+
+/// Encode javascript into jsf*ck, or decode jsf*ck back into javascript
+#[derive(Parser, Debug)]
+#[command(name = "jsfuck", version)]
+struct Cli {
+    /// File to read from, stdin when missing or `-`
+    input: Option<PathBuf>,
+    /// File to write to instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Encoder to use: lbp, lbp-strict, aemkei, jjencode or aaencode
+    #[arg(short, long, default_value_t = Backend::default())]
+    backend: Backend,
+    /// Only emit the six symbols `[]()!+` (lbp only, aemkei always does)
+    #[arg(short, long)]
+    strict: bool,
+    /// What runs the code
+    #[arg(short, long, value_enum, default_value_t = WrapperKind::Function)]
+    wrapper: WrapperKind,
+    /// Parameter of the `call` wrapper, given a string value
+    #[arg(long = "arg", value_name = "NAME=VALUE", value_parser = parse_argument)]
+    arguments: Vec<(String, String)>,
+    /// Engines the output has to run on (lbp only): generic, es5, browser or node
+    #[arg(short, long, default_value_t = Target::default())]
+    target: Target,
+    /// Share the expensive derivations between characters (lbp only)
+    #[arg(long)]
+    hoist: bool,
+    /// Recover the code of a jsf*ck program instead of encoding
+    #[arg(short, long)]
+    decode: bool,
+    /// Report sizes and the characters built from their character code on stderr
+    #[arg(long)]
+    stats: bool,
+}
+
+/// The [`Wrapper`]s, selectable without their arguments
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum WrapperKind {
+    Function,
+    Expression,
+    Eval,
+    SetTimeout,
+    Call,
+    String,
+}
+
+fn parse_argument(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .ok_or_else(|| format!("expected NAME=VALUE, got {s:?}"))
+}
+
+/// Sizes of the input and output, along with the characters built from their character code
+#[derive(Debug, PartialEq)]
+struct Stats {
+    input: usize,
+    output: usize,
+    fallback: Vec<char>,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "input: {} bytes", self.input)?;
+        writeln!(f, "output: {} bytes", self.output)?;
+        if self.input > 0 {
+            writeln!(
+                f,
+                "expansion: {:.2}x",
+                self.output as f64 / self.input as f64
+            )?;
+        }
+        if self.fallback.is_empty() {
+            write!(f, "character codes: none")
+        } else {
+            let chars = self
+                .fallback
+                .iter()
+                .map(|c| format!("{c:?} (U+{:04X})", u32::from(*c)))
+                .collect::<Vec<_>>();
+            write!(f, "character codes: {}", chars.join(", "))
+        }
+    }
+}
+
+impl Cli {
+    /// Rejects the flags clap can not tell apart from the values of others
+    fn check(&self) -> Result<(), clap::Error> {
+        if self.strict && !matches!(self.backend, Backend::Lbp | Backend::LbpStrict) {
+            return Err(Self::command().error(
+                ErrorKind::ArgumentConflict,
+                format!("--strict is only supported by lbp, not {}", self.backend),
+            ));
+        }
+        Ok(())
+    }
+
+    fn encoder(&self) -> Result<Box<dyn JsFuckEncoder>> {
+        match self.backend {
+            Backend::Lbp | Backend::LbpStrict => Ok(Box::new(Lbp {
+                strict: self.strict || self.backend == Backend::LbpStrict,
+                hoist: self.hoist,
+                target: self.target,
+            })),
+            backend => {
+                if self.hoist || self.target != Target::Generic {
+                    bail!("--hoist and --target are only supported by lbp");
+                }
+                Ok(match backend {
+                    Backend::JjEncode => Box::new(Jjencode::default()),
+                    Backend::AaEncode => Box::new(Aaencode),
+                    _ => Box::new(Aemkei),
+                })
+            }
+        }
+    }
+
+    fn wrapper(&self, encoder: &dyn JsFuckEncoder) -> Result<Wrapper> {
+        if self.wrapper != WrapperKind::Call && !self.arguments.is_empty() {
+            bail!("--arg is only supported by the call wrapper");
+        }
+        Ok(match self.wrapper {
+            WrapperKind::Function => Wrapper::Function,
+            WrapperKind::Expression => Wrapper::Expression,
+            WrapperKind::Eval => Wrapper::Eval,
+            WrapperKind::SetTimeout => Wrapper::SetTimeout,
+            WrapperKind::Call => Wrapper::call(
+                self.arguments
+                    .iter()
+                    .map(|(name, value)| {
+                        (
+                            name.clone(),
+                            encoder.encode_value(&Value::from(value.as_str())),
+                        )
+                    })
+                    .collect(),
+            )?,
+            WrapperKind::String => Wrapper::String,
+        })
+    }
+
+    /// Encodes or decodes `input` as asked, streaming the result into `out`
+    fn run(&self, input: &str, out: &mut dyn fmt::Write) -> Result<Stats> {
+        let encoder = self.encoder()?;
+        let mut out = Counted { out, len: 0 };
+        let mut fallback = vec![];
+        let written = if self.decode {
+            out.write_str(&decode(input)?)
+        } else {
+            let options = CompileOptions {
+                wrapper: self.wrapper(encoder.as_ref())?,
+            };
+            fallback = input
+                .chars()
+                .filter(|&c| !encoder.is_mapped(c))
+                .collect::<Vec<_>>();
+            fallback.sort_unstable();
+            fallback.dedup();
+            encoder.compile_with_to(&mut out, input, &options)
+        };
+        written.map_err(|fmt::Error| miette!("Could not write the output"))?;
+        Ok(Stats {
+            input: input.len(),
+            output: out.len,
+            fallback,
+        })
+    }
+}
+
+/// Counts the bytes passing through to `out`
+struct Counted<'a> {
+    out: &'a mut dyn fmt::Write,
+    len: usize,
+}
+
+impl fmt::Write for Counted<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.len += s.len();
+        self.out.write_str(s)
+    }
+}
+
+fn read_input(path: Option<&Path>) -> Result<String> {
+    match path {
+        Some(path) if path != Path::new("-") => fs::read_to_string(path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Could not read {}", path.display())),
+        _ => {
+            let mut input = String::new();
+            io::stdin()
+                .read_to_string(&mut input)
+                .into_diagnostic()
+                .wrap_err("Could not read stdin")?;
+            Ok(input)
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    cli.check().unwrap_or_else(|err| err.exit());
+    let input = read_input(cli.input.as_deref())?;
+    let sink: Box<dyn Write> = match &cli.output {
+        Some(path) => Box::new(
+            File::create(path)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Could not write {}", path.display()))?,
+        ),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut out = IoWriter::new(BufWriter::new(sink));
+    let stats = cli.run(&input, &mut out);
+    // A failed write is the reason the run failed, if it did
+    let mut sink = out
+        .finish(Ok(()))
+        .into_diagnostic()
+        .wrap_err("Could not write the output")?;
+    let stats = stats?;
+    if cli.output.is_none() {
+        writeln!(sink).into_diagnostic()?;
+    }
+    sink.flush().into_diagnostic()?;
+    if cli.stats {
+        eprintln!("{stats}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use jsfuckrs::is_jsfuck;
+
+    fn run(args: &[&str], input: &str) -> Result<(String, Stats)> {
+        let mut output = String::new();
+        let stats = Cli::try_parse_from(["jsfuck"].iter().chain(args))
+            .unwrap()
+            .run(input, &mut output)?;
+        Ok((output, stats))
+    }
+
+    #[test]
+    fn defaults() {
+        let (output, stats) = run(&[], "alert(1)").unwrap();
+        assert_eq!(output, Backend::Lbp.compile("alert(1)"));
+        assert_eq!(
+            stats,
+            Stats {
+                input: 8,
+                output: output.len(),
+                fallback: vec![]
+            }
+        );
+    }
+
+    #[test]
+    fn backends() {
+        let (output, _) = run(&["--strict"], "alert(1)").unwrap();
+        assert_eq!(output, Backend::LbpStrict.compile("alert(1)"));
+        for backend in [Backend::Aemkei, Backend::JjEncode, Backend::AaEncode] {
+            let (output, _) = run(&["-b", backend.name()], "alert(1)").unwrap();
+            assert_eq!(output, backend.compile("alert(1)"));
+            assert_eq!(run(&["-d"], &output).unwrap().0, "alert(1)");
+        }
+        let (output, _) = run(&["-b", "lbp", "-s", "-t", "es5", "--hoist"], "alert(1)").unwrap();
+        assert!(is_jsfuck(&output));
+        assert!(run(&["-b", "aemkei", "-t", "node"], "").is_err());
+        let cli = Cli::try_parse_from(["jsfuck", "-b", "aemkei", "--strict"]).unwrap();
+        assert_eq!(cli.check().unwrap_err().kind(), ErrorKind::ArgumentConflict);
+        let cli = Cli::try_parse_from(["jsfuck", "-b", "jjencode", "--strict"]).unwrap();
+        assert_eq!(cli.check().unwrap_err().kind(), ErrorKind::ArgumentConflict);
+        assert!(run(&["-b", "aaencode", "--hoist"], "").is_err());
+        assert!(Cli::try_parse_from(["jsfuck", "--strict"])
+            .unwrap()
+            .check()
+            .is_ok());
+        assert!(Cli::try_parse_from(["jsfuck", "-b", "brainfuck"]).is_err());
+    }
+
+    #[test]
+    fn wrappers() {
+        let (output, _) = run(&["-w", "set-timeout"], "alert(1)").unwrap();
+        let options = CompileOptions {
+            wrapper: Wrapper::SetTimeout,
+        };
+        assert_eq!(output, Backend::Lbp.compile_with("alert(1)", &options));
+        let (output, _) = run(&["-w", "call", "--arg", "a=", "--arg", "b=c=d"], "").unwrap();
+        let options = CompileOptions {
+            wrapper: Wrapper::call(vec![
+                ("a".to_owned(), "[]+[]".to_owned()),
+                ("b".to_owned(), Backend::Lbp.string("c=d")),
+            ])
+            .unwrap(),
+        };
+        assert_eq!(output, Backend::Lbp.compile_with("", &options));
+        assert!(run(&["--arg", "a=b"], "").is_err());
+        assert!(run(&["-w", "call", "--arg", "a){}//=x"], "").is_err());
+        assert!(Cli::try_parse_from(["jsfuck", "--arg", "a"]).is_err());
+    }
+
+    #[test]
+    fn decoding() {
+        let encoded = Backend::Aemkei.compile("alert(1)");
+        let (output, stats) = run(&["--decode"], &encoded).unwrap();
+        assert_eq!(output, "alert(1)");
+        assert_eq!(stats.output, 8);
+        assert!(run(&["-d"], "+[]]").is_err());
+    }
+
+    #[test]
+    fn stats() {
+        let (_, stats) = run(&["-b", "aemkei"], "Hi \u{e9}\u{e9}").unwrap();
+        assert_eq!(stats.fallback, ['H', '\u{e9}']);
+        assert_eq!(
+            Stats {
+                input: 4,
+                output: 10,
+                fallback: vec!['\u{e9}']
+            }
+            .to_string(),
+            "input: 4 bytes\noutput: 10 bytes\nexpansion: 2.50x\ncharacter codes: '\u{e9}' (U+00E9)"
+        );
+    }
 }
@@ -0,0 +1,134 @@
+//! Sinks for the streaming methods of [`JsFuckEncoder`](crate::JsFuckEncoder)
+use std::{fmt, io};
+
+/// Adapts an [`io::Write`] into the [`fmt::Write`] the encoders stream their output into
+///
+/// # Examples
+/// ```
+/// use jsfuckrs::{Backend, IoWriter, JsFuckEncoder};
+/// let mut out = IoWriter::new(Vec::new());
+/// let result = Backend::default().compile_to(&mut out, "alert(1)");
+/// let bytes = out.finish(result).unwrap();
+/// assert_eq!(bytes, Backend::default().compile("alert(1)").into_bytes());
+/// ```
+#[derive(Debug)]
+pub struct IoWriter<W> {
+    inner: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> IoWriter<W> {
+    pub const fn new(inner: W) -> Self {
+        Self { inner, error: None }
+    }
+
+    /// The writer once `result`, what the encoder returned, is known to be a success
+    ///
+    /// # Errors
+    /// The error that stopped the encoder from writing
+    pub fn finish(self, result: fmt::Result) -> io::Result<W> {
+        match (result, self.error) {
+            (_, Some(err)) => Err(err),
+            (Err(fmt::Error), None) => Err(io::Error::other("formatter error")),
+            (Ok(()), None) => Ok(self.inner),
+        }
+    }
+}
+
+impl<W: io::Write> fmt::Write for IoWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|err| {
+            self.error = Some(err);
+            fmt::Error
+        })
+    }
+}
+
+/// Counts the bytes written into it
+#[cfg(any(feature = "lbp", feature = "aemkei"))]
+#[derive(Debug, Default)]
+struct Counter(usize);
+
+#[cfg(any(feature = "lbp", feature = "aemkei"))]
+impl fmt::Write for Counter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+/// The length of what `write` streams, without keeping it
+///
+/// # Panics
+/// When `write` fails, which it only does when the writer it is given does, and counting never fails
+#[cfg(any(feature = "lbp", feature = "aemkei"))]
+pub(crate) fn count(write: impl FnOnce(&mut dyn fmt::Write) -> fmt::Result) -> usize {
+    let mut counter = Counter::default();
+    write(&mut counter).expect("the encoder failed although counting never fails");
+    counter.0
+}
+
+/// Collects what `write` streams into a `String`
+///
+/// # Panics
+/// When `write` fails, which it only does when the writer it is given does, and writing into a
+/// `String` never fails
+pub(crate) fn collect(write: impl FnOnce(&mut dyn fmt::Write) -> fmt::Result) -> String {
+    let mut out = String::new();
+    write(&mut out).expect("the encoder failed although writing into a `String` never fails");
+    out
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use std::fmt::Write;
+
+    /// Accepts the given number of bytes
+    #[derive(Debug)]
+    struct Full(usize);
+
+    impl io::Write for Full {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.0 == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            let len = buf.len().min(self.0);
+            self.0 -= len;
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn io_errors() {
+        let mut out = IoWriter::new(Full(3));
+        let result = out.write_str("abcd");
+        assert_eq!(
+            out.finish(result).unwrap_err().kind(),
+            io::ErrorKind::WriteZero
+        );
+        let out = IoWriter::new(Full(3));
+        assert!(out.finish(Err(fmt::Error)).is_err());
+        let mut out = IoWriter::new(Full(3));
+        let result = out.write_str("abc");
+        assert_eq!(out.finish(result).unwrap().0, 0);
+    }
+
+    #[test]
+    fn counting() {
+        #[cfg(any(feature = "lbp", feature = "aemkei"))]
+        assert_eq!(count(|out| out.write_str("\u{e9}ab")), 4);
+        assert_eq!(collect(|out| write!(out, "{}", 12)), "12");
+    }
+
+    #[test]
+    #[should_panic(expected = "writing into a `String` never fails")]
+    fn collect_reports_errors() {
+        collect(|_| Err(fmt::Error));
+    }
+}
@@ -0,0 +1,158 @@
+//! Round trips of generated strings and snippets through every encoder, checked with the evaluator
+//! and the decoder rather than against exact outputs
+#![cfg(all(
+    feature = "lbp",
+    feature = "aemkei",
+    feature = "jjencode",
+    feature = "aaencode"
+))]
+
+use std::sync::OnceLock;
+
+use jsfuckrs::{
+    aaencode, decode,
+    eval::{eval, Evaluator},
+    is_jsfuck, jjencode,
+    lbp::alphabet::{self, Encoder},
+    Backend, CompileOptions, JsFuckEncoder, Wrapper,
+};
+use proptest::prelude::*;
+
+/// The symbols lbp writes outside of strict mode
+const LBP_SYMBOLS: &str = " !()*+-/=>[\\]{}";
+
+/// The symbols of jjencode, apart from the punctuation it copies from the code
+const JJ_SYMBOLS: &str = "[]()!+{}~:;,.=_$\"\\";
+
+fn alphabet_encoder() -> &'static Encoder {
+    static ENCODER: OnceLock<Encoder> = OnceLock::new();
+    ENCODER.get_or_init(|| Encoder::new(alphabet::JSFUCK))
+}
+
+/// What the scripts of the encoders that do not implement [`JsFuckEncoder`] pass to `Function`
+fn ran_code(encoded: &str) -> String {
+    let mut evaluator = Evaluator::default();
+    // Running the code itself fails unless it is valid javascript the evaluator knows
+    evaluator.eval(encoded).ok();
+    evaluator.code().last().cloned().unwrap_or_default()
+}
+
+fn text() -> impl Strategy<Value = String> {
+    prop::collection::vec(any::<char>(), 0..12).prop_map(|chars| chars.into_iter().collect())
+}
+
+/// Javascript expressions the evaluator understands
+fn snippet() -> impl Strategy<Value = String> {
+    let leaf = prop_oneof![
+        (0..1000u32).prop_map(|n| n.to_string()),
+        "[a-z ]{0,6}".prop_map(|s| format!("\"{s}\"")),
+        Just("[]".to_owned()),
+        Just("true".to_owned()),
+        Just("false".to_owned()),
+        Just("undefined".to_owned()),
+    ];
+    leaf.prop_recursive(3, 16, 2, |inner| {
+        prop_oneof![
+            (inner.clone(), inner.clone()).prop_map(|(a, b)| format!("{a}+{b}")),
+            (inner.clone(), inner.clone()).prop_map(|(a, b)| format!("[{a},{b}]")),
+            (inner.clone(), inner.clone()).prop_map(|(a, b)| format!("({a})==({b})")),
+            (inner.clone(), inner.clone()).prop_map(|(a, b)| format!("({a})^({b})")),
+            inner.clone().prop_map(|a| format!("!({a})")),
+            inner.prop_map(|a| format!("~({a})")),
+        ]
+    })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn strings_evaluate_to_themselves(s in text()) {
+        for backend in Backend::ALL {
+            let encoded = backend.string(&s);
+            if !s.is_empty() {
+                let value = eval(&encoded).unwrap().to_string();
+                prop_assert_eq!(value, s.clone(), "{:?}", backend);
+            }
+        }
+        let encoded = alphabet_encoder().string(&s).unwrap();
+        prop_assert!(is_jsfuck(&encoded));
+        if !s.is_empty() {
+            prop_assert_eq!(eval(&encoded).unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn compiled_code_decodes(s in text()) {
+        for backend in Backend::ALL {
+            let encoded = backend.compile(&s);
+            prop_assert_eq!(&decode(&encoded).unwrap(), &s, "{:?}", backend);
+            prop_assert_eq!(&ran_code(&encoded), &s, "{:?}", backend);
+        }
+        let encoded = alphabet_encoder().compile(&s).unwrap();
+        prop_assert_eq!(&ran_code(&encoded), &s);
+        for encoded in [jjencode::compile(&s), aaencode::compile(&s)] {
+            prop_assert_eq!(&decode(&encoded).unwrap(), &s);
+            prop_assert_eq!(&ran_code(&encoded), &s);
+        }
+    }
+
+    #[test]
+    fn snippets_keep_their_value(code in snippet()) {
+        let expected = eval(&code).unwrap().to_string();
+        let options = CompileOptions {
+            wrapper: Wrapper::Expression,
+        };
+        for backend in Backend::ALL {
+            let encoded = backend.compile_with(&code, &options);
+            let value = eval(&encoded).unwrap().to_string();
+            prop_assert_eq!(value, expected.clone(), "{:?}", backend);
+        }
+    }
+
+    #[test]
+    fn outputs_stay_in_their_alphabet(s in text()) {
+        prop_assert!(Backend::Lbp.compile(&s).chars().all(|c| LBP_SYMBOLS.contains(c)));
+        prop_assert!(is_jsfuck(&Backend::LbpStrict.compile(&s)));
+        prop_assert!(is_jsfuck(&Backend::Aemkei.compile(&s)));
+        prop_assert!(is_jsfuck(&alphabet_encoder().compile(&s).unwrap()));
+        let copied = |c: char| c.is_ascii_punctuation() && s.contains(c);
+        let jj = jjencode::compile(&s);
+        prop_assert!(jj.chars().all(|c| JJ_SYMBOLS.contains(c) || copied(c)));
+        let prelude = aaencode::compile("");
+        prop_assert!(aaencode::compile(&s).chars().all(|c| prelude.contains(c)));
+    }
+
+    #[test]
+    fn sizes_grow_with_the_input(s in text(), c in any::<char>()) {
+        let longer = format!("{s}{c}");
+        for &backend in Backend::ALL {
+            // Aemkei encodes names like `true` as a whole, and escapes most characters once one
+            // is not ASCII, either of which can make a longer input shorter to encode
+            if backend == Backend::Aemkei {
+                let escaped = !s.is_ascii();
+                let simple = ["false", "true", "undefined", "NaN", "Infinity"];
+                if !escaped || simple.iter().any(|name| longer.contains(name)) {
+                    continue;
+                }
+            }
+            let (compiled, string) = (backend.compile(&s), backend.string(&s));
+            prop_assert!(compiled.len() < backend.compile(&longer).len(), "{:?}", backend);
+            prop_assert!(string.len() < backend.string(&longer).len(), "{:?}", backend);
+        }
+        let encoder = alphabet_encoder();
+        let compiled = encoder.compile(&s).unwrap();
+        prop_assert!(compiled.len() < encoder.compile(&longer).unwrap().len());
+        prop_assert!(jjencode::compile(&s).len() < jjencode::compile(&longer).len());
+        prop_assert!(aaencode::compile(&s).len() < aaencode::compile(&longer).len());
+    }
+
+    #[test]
+    fn numbers_evaluate_to_themselves(n in 0..100_000usize) {
+        for backend in Backend::ALL {
+            prop_assert_eq!(eval(&backend.number(n)).unwrap().to_string(), n.to_string());
+        }
+        let encoded = alphabet_encoder().number(n).unwrap();
+        prop_assert_eq!(eval(&encoded).unwrap().to_string(), n.to_string());
+    }
+}